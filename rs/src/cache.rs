@@ -0,0 +1,496 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+// The cache format is private to this crate and may change between
+// versions (in which case CACHE_VERSION is incremented). It is:
+//
+//   magic (b"UXFC") version (u16 LE) custom:opt_str comment:opt_str
+//   tclass_count:uint tclass* value
+//
+// where a uint is LEB128-encoded, an int is zigzag-encoded as a uint, a
+// str or bytes is its length (uint) followed by its bytes, and an opt_str
+// is a 0 byte (None) or a 1 byte followed by a str. A tclass is its ttype
+// (str), comment (opt_str), field count (uint), and each field's name
+// (str) and vtype (opt_str). A value (or null or key) is a tag byte
+// followed by the data for that tag (see the TAG_ constants).
+
+use crate::error::UxfError;
+use crate::field::Field;
+use crate::list::List;
+use crate::map::Map;
+use crate::table::Table;
+use crate::tclass::TClass;
+use crate::uxf::Uxf;
+use crate::value::{Key, Value};
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+use std::collections::HashMap;
+
+const MAGIC: &[u8] = b"UXFC";
+const CACHE_VERSION: u16 = 1;
+
+// Decoding recurses once per level of collection nesting, so this guards
+// against stack overflows from corrupt (or malicious) data.
+const MAX_DEPTH: usize = 256;
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_BYTES: u8 = 3;
+const TAG_DATE: u8 = 4; // days from CE (int)
+const TAG_DATETIME: u8 = 5; // days from CE (int) secs (uint) nanos (uint)
+const TAG_INT: u8 = 6;
+const TAG_REAL: u8 = 7; // 8 bytes LE
+const TAG_STR: u8 = 8;
+const TAG_LIST: u8 = 9; // vtype comment count values
+const TAG_MAP: u8 = 10; // ktype vtype comment count (key value)*
+const TAG_TABLE: u8 = 11; // ttype-or-tclass comment count records
+const TAG_BIGINT: u8 = 12; // decimal str
+
+// A table's tclass is written as a reference (by ttype) to one of the
+// ``Uxf``'s tclasses if it is identical to it (including its comment),
+// otherwise in full.
+const TCLASS_REF: u8 = 0;
+const TCLASS_INLINE: u8 = 1;
+
+pub(crate) fn to_cache_bytes(uxo: &Uxf) -> Vec<u8> {
+    let mut e = Encoder { bytes: MAGIC.to_vec(), tclasses: uxo.tclasses() };
+    e.bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    e.opt_str(uxo.custom());
+    e.opt_str(uxo.comment());
+    let mut tclasses: Vec<&TClass> = uxo.tclasses().values().collect();
+    tclasses.sort();
+    e.uint(tclasses.len() as u64);
+    for tclass in tclasses {
+        e.tclass(tclass);
+    }
+    e.value(uxo.value());
+    e.bytes
+}
+
+pub(crate) fn from_cache_bytes(bytes: &[u8]) -> Result<Uxf> {
+    if !bytes.starts_with(MAGIC) {
+        bail!(UxfError::new(800, "not UXF cache data".to_string()));
+    }
+    let mut d = Decoder {
+        bytes,
+        pos: MAGIC.len(),
+        depth: 0,
+        tclasses: HashMap::new(),
+    };
+    let version = u16::from_le_bytes(d.array()?);
+    if version != CACHE_VERSION {
+        bail!(UxfError::new(
+            802,
+            format!(
+                "unsupported UXF cache version {} (expected {}); the \
+                  cache must be rebuilt from the UXF text",
+                version, CACHE_VERSION
+            )
+        ));
+    }
+    let custom = d.opt_str()?;
+    let comment = d.opt_str()?;
+    for _ in 0..d.uint()? {
+        let tclass = d.tclass()?;
+        d.tclasses.insert(tclass.ttype().to_string(), tclass);
+    }
+    let value = match d.opt_value()? {
+        Some(
+            value @ (Value::List(_) | Value::Map(_) | Value::Table(_)),
+        ) => value,
+        _ => bail!(corrupt()),
+    };
+    if d.pos != bytes.len() {
+        bail!(corrupt());
+    }
+    let mut uxo = Uxf::from_parts(None, comment, d.tclasses, value);
+    uxo.set_custom(custom.as_deref())?;
+    Ok(uxo)
+}
+
+fn corrupt() -> UxfError {
+    UxfError::new(804, "UXF cache data is truncated or corrupt".to_string())
+}
+
+struct Encoder<'a> {
+    bytes: Vec<u8>,
+    tclasses: &'a HashMap<String, TClass>,
+}
+
+impl<'a> Encoder<'a> {
+    fn uint(&mut self, mut u: u64) {
+        loop {
+            let byte = (u & 0x7F) as u8;
+            u >>= 7;
+            if u == 0 {
+                self.bytes.push(byte);
+                break;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn int(&mut self, i: i64) {
+        self.uint(((i << 1) ^ (i >> 63)) as u64);
+    }
+
+    fn raw(&mut self, b: &[u8]) {
+        self.uint(b.len() as u64);
+        self.bytes.extend_from_slice(b);
+    }
+
+    fn opt_str(&mut self, s: Option<&str>) {
+        match s {
+            None => self.bytes.push(0),
+            Some(s) => {
+                self.bytes.push(1);
+                self.raw(s.as_bytes());
+            }
+        }
+    }
+
+    fn tclass(&mut self, tclass: &TClass) {
+        self.raw(tclass.ttype().as_bytes());
+        self.opt_str(tclass.comment());
+        self.uint(tclass.fields().len() as u64);
+        for field in tclass.fields() {
+            self.raw(field.name().as_bytes());
+            self.opt_str(field.vtype());
+        }
+    }
+
+    fn date(&mut self, d: &NaiveDate) {
+        self.int(d.num_days_from_ce() as i64);
+    }
+
+    fn datetime(&mut self, dt: &NaiveDateTime) {
+        self.date(&dt.date());
+        self.uint(dt.num_seconds_from_midnight() as u64);
+        self.uint(dt.nanosecond() as u64);
+    }
+
+    fn key(&mut self, key: &Key) {
+        match key {
+            Key::Bytes(b) => {
+                self.bytes.push(TAG_BYTES);
+                self.raw(b);
+            }
+            Key::Date(d) => {
+                self.bytes.push(TAG_DATE);
+                self.date(d);
+            }
+            Key::DateTime(dt) => {
+                self.bytes.push(TAG_DATETIME);
+                self.datetime(dt);
+            }
+            Key::Int(i) => {
+                self.bytes.push(TAG_INT);
+                self.int(*i);
+            }
+            Key::Str(s) => {
+                self.bytes.push(TAG_STR);
+                self.raw(s.as_bytes());
+            }
+        }
+    }
+
+    fn opt_value(&mut self, value: &Option<Value>) {
+        match value {
+            None => self.bytes.push(TAG_NULL),
+            Some(value) => self.value(value),
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            #[cfg(feature = "bigint")]
+            Value::BigInt(i) => {
+                self.bytes.push(TAG_BIGINT);
+                self.raw(i.to_string().as_bytes());
+            }
+            Value::Bool(false) => self.bytes.push(TAG_FALSE),
+            Value::Bool(true) => self.bytes.push(TAG_TRUE),
+            Value::Bytes(b) => {
+                self.bytes.push(TAG_BYTES);
+                self.raw(b);
+            }
+            Value::Date(d) => self.key(&Key::Date(*d)),
+            Value::DateTime(dt) => self.key(&Key::DateTime(*dt)),
+            Value::Int(i) => self.key(&Key::Int(*i)),
+            Value::Real(r) => {
+                self.bytes.push(TAG_REAL);
+                self.bytes.extend_from_slice(&r.to_le_bytes());
+            }
+            Value::Str(s) => {
+                self.bytes.push(TAG_STR);
+                self.raw(s.as_bytes());
+            }
+            Value::List(lst) => {
+                self.bytes.push(TAG_LIST);
+                self.opt_str(lst.vtype());
+                self.opt_str(lst.comment());
+                self.uint(lst.len() as u64);
+                for value in lst.values() {
+                    self.opt_value(value);
+                }
+            }
+            Value::Map(m) => {
+                self.bytes.push(TAG_MAP);
+                self.opt_str(m.ktype());
+                self.opt_str(m.vtype());
+                self.opt_str(m.comment());
+                self.uint(m.len() as u64);
+                for (key, value) in m.items() {
+                    self.key(key);
+                    self.opt_value(value);
+                }
+            }
+            Value::Table(t) => {
+                self.bytes.push(TAG_TABLE);
+                let tclass = t.tclass();
+                if self.tclasses.get(tclass.ttype()).is_some_and(|t| {
+                    t == tclass && t.comment() == tclass.comment()
+                }) {
+                    self.bytes.push(TCLASS_REF);
+                    self.raw(tclass.ttype().as_bytes());
+                } else {
+                    self.bytes.push(TCLASS_INLINE);
+                    self.tclass(tclass);
+                }
+                self.opt_str(t.comment());
+                self.uint(t.records_len() as u64);
+                for record in t.records() {
+                    for value in record {
+                        self.opt_value(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize, // of collection nesting
+    tclasses: HashMap<String, TClass>,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        match self.pos.checked_add(n) {
+            Some(end) if end <= self.bytes.len() => {
+                let b = &self.bytes[self.pos..end];
+                self.pos = end;
+                Ok(b)
+            }
+            _ => bail!(corrupt()),
+        }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn uint(&mut self) -> Result<u64> {
+        let mut u = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            u |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(u);
+            }
+        }
+        bail!(corrupt())
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let n = self.uint()?;
+        // Every item takes at least one byte, so this guards against
+        // huge allocations from corrupt lengths
+        if n > (self.bytes.len() - self.pos) as u64 {
+            bail!(corrupt());
+        }
+        Ok(n as usize)
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        let u = self.uint()?;
+        Ok((u >> 1) as i64 ^ -((u & 1) as i64))
+    }
+
+    fn raw(&mut self) -> Result<&'a [u8]> {
+        let n = self.len()?;
+        self.take(n)
+    }
+
+    fn str(&mut self) -> Result<String> {
+        match std::str::from_utf8(self.raw()?) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => bail!(corrupt()),
+        }
+    }
+
+    fn opt_str(&mut self) -> Result<Option<String>> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.str()?)),
+            _ => bail!(corrupt()),
+        }
+    }
+
+    fn tclass(&mut self) -> Result<TClass> {
+        let ttype = self.str()?;
+        let comment = self.opt_str()?;
+        let mut fields = vec![];
+        for _ in 0..self.len()? {
+            let name = self.str()?;
+            fields.push(match self.opt_str()? {
+                None => Field::new_anyvtype(&name)?,
+                Some(vtype) => Field::new(&name, &vtype)?,
+            });
+        }
+        TClass::new(&ttype, fields, comment.as_deref())
+    }
+
+    fn date(&mut self) -> Result<NaiveDate> {
+        match i32::try_from(self.int()?)
+            .ok()
+            .and_then(NaiveDate::from_num_days_from_ce_opt)
+        {
+            Some(d) => Ok(d),
+            None => bail!(corrupt()),
+        }
+    }
+
+    fn datetime(&mut self) -> Result<NaiveDateTime> {
+        let d = self.date()?;
+        let secs = u32::try_from(self.uint()?).ok();
+        let nanos = u32::try_from(self.uint()?).ok();
+        match secs.zip(nanos).and_then(|(secs, nanos)| {
+            NaiveTime::from_num_seconds_from_midnight_opt(secs, nanos)
+        }) {
+            Some(t) => Ok(d.and_time(t)),
+            None => bail!(corrupt()),
+        }
+    }
+
+    fn key(&mut self) -> Result<Key> {
+        Ok(match self.byte()? {
+            TAG_BYTES => Key::Bytes(self.raw()?.to_vec()),
+            TAG_DATE => Key::Date(self.date()?),
+            TAG_DATETIME => Key::DateTime(self.datetime()?),
+            TAG_INT => Key::Int(self.int()?),
+            TAG_STR => Key::Str(self.str()?),
+            _ => bail!(corrupt()),
+        })
+    }
+
+    fn opt_value(&mut self) -> Result<Option<Value>> {
+        let tag = self.byte()?;
+        if matches!(tag, TAG_LIST | TAG_MAP | TAG_TABLE) {
+            if self.depth == MAX_DEPTH {
+                bail!(UxfError::new(
+                    808,
+                    format!(
+                        "UXF cache data is nested more than {} levels deep",
+                        MAX_DEPTH
+                    )
+                ));
+            }
+            self.depth += 1;
+            let value = self.collection(tag)?;
+            self.depth -= 1;
+            return Ok(Some(value));
+        }
+        Ok(Some(match tag {
+            TAG_NULL => return Ok(None),
+            #[cfg(feature = "bigint")]
+            TAG_BIGINT => match self.str()?.parse() {
+                Ok(i) => Value::BigInt(i),
+                Err(_) => bail!(corrupt()),
+            },
+            #[cfg(not(feature = "bigint"))]
+            TAG_BIGINT => bail!(UxfError::new(
+                806,
+                "UXF cache data contains a big int which requires \
+                  the bigint feature"
+                    .to_string()
+            )),
+            TAG_FALSE => Value::Bool(false),
+            TAG_TRUE => Value::Bool(true),
+            TAG_BYTES => Value::Bytes(self.raw()?.to_vec()),
+            TAG_DATE => Value::Date(self.date()?),
+            TAG_DATETIME => Value::DateTime(self.datetime()?),
+            TAG_INT => Value::Int(self.int()?),
+            TAG_REAL => Value::Real(f64::from_le_bytes(self.array()?)),
+            TAG_STR => Value::Str(self.str()?),
+            _ => bail!(corrupt()),
+        }))
+    }
+
+    fn collection(&mut self, tag: u8) -> Result<Value> {
+        match tag {
+            TAG_LIST => self.list(),
+            TAG_MAP => self.map(),
+            _ => self.table(),
+        }
+    }
+
+    fn list(&mut self) -> Result<Value> {
+        let vtype = self.opt_str()?;
+        let comment = self.opt_str()?;
+        let mut lst = List::new(vtype.as_deref(), comment.as_deref())?;
+        for _ in 0..self.len()? {
+            lst.push_unchecked(self.opt_value()?);
+        }
+        Ok(Value::List(lst))
+    }
+
+    fn map(&mut self) -> Result<Value> {
+        let ktype = self.opt_str()?;
+        let vtype = self.opt_str()?;
+        let comment = self.opt_str()?;
+        let mut m = Map::new(
+            ktype.as_deref(),
+            vtype.as_deref(),
+            comment.as_deref(),
+        )?;
+        for _ in 0..self.len()? {
+            let key = self.key()?;
+            m.insert_unchecked(key, self.opt_value()?);
+        }
+        Ok(Value::Map(m))
+    }
+
+    fn table(&mut self) -> Result<Value> {
+        let tclass = match self.byte()? {
+            TCLASS_REF => {
+                let ttype = self.str()?;
+                match self.tclasses.get(&ttype) {
+                    Some(tclass) => tclass.clone(),
+                    None => bail!(corrupt()),
+                }
+            }
+            TCLASS_INLINE => self.tclass()?,
+            _ => bail!(corrupt()),
+        };
+        let columns = tclass.len();
+        let mut t = Table::new(tclass);
+        t.set_comment(self.opt_str()?.as_deref(), None)?;
+        for _ in 0..self.len()? {
+            let mut record = Vec::with_capacity(columns);
+            for _ in 0..columns {
+                record.push(self.opt_value()?);
+            }
+            // A fieldless table can't have records
+            t.push_record(record).map_err(|_| corrupt())?;
+        }
+        Ok(Value::Table(t))
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
+pub const UXF_VERSION: f64 = 1.0;
 pub const MAX_IDENTIFIER_LEN: usize = 60;
 
 pub static ISO8601_DATE: &str = "%Y-%m-%d";
@@ -37,8 +38,13 @@ pub static RESERVED_WORDS: [&str; 13] = [
     BOOL_TRUE,
 ];
 
-pub static KTYPES: [&str; 4] =
-    [VTYPE_NAME_BYTES, VTYPE_NAME_DATE, VTYPE_NAME_INT, VTYPE_NAME_STR];
+pub static KTYPES: [&str; 5] = [
+    VTYPE_NAME_BYTES,
+    VTYPE_NAME_DATE,
+    VTYPE_NAME_DATETIME,
+    VTYPE_NAME_INT,
+    VTYPE_NAME_STR,
+];
 
 pub static VTYPES: [&str; 10] = [
     VTYPE_NAME_BOOL,
@@ -0,0 +1,83 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use std::error::Error;
+use std::fmt;
+
+/// An error with a UXF error code (e.g., `604`), a message, and (for
+/// errors found while parsing) a line number and usually a column.
+///
+/// An `UxfError` displays as `#604:message` (followed by ` (line N)` if
+/// it has a line number, or ` (line N, col M)` if it has a column too).
+/// Functions that return an `anyhow::Result` may still carry an
+/// `UxfError`: use `err.downcast_ref::<UxfError>()` to get it, and then
+/// `code()` to match on the kind of error without having to parse the
+/// message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UxfError {
+    code: u16,
+    message: String,
+    lineno: Option<usize>,
+    column: Option<usize>,
+}
+
+impl UxfError {
+    pub(crate) fn new(code: u16, message: String) -> Self {
+        UxfError { code, message, lineno: None, column: None }
+    }
+
+    pub(crate) fn with_lineno(mut self, lineno: usize) -> Self {
+        self.lineno = Some(lineno);
+        self
+    }
+
+    pub(crate) fn with_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// Returns the ``UxfError``'s code.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Returns the ``UxfError``'s message (without its code or line
+    /// number).
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the ``UxfError``'s line number if it has one.
+    pub fn lineno(&self) -> Option<usize> {
+        self.lineno
+    }
+
+    /// Returns the ``UxfError``'s column if it has one. Columns count
+    /// characters (not bytes) from 1, so a multibyte UTF-8 character
+    /// counts as one column.
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
+}
+
+impl fmt::Display for UxfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}:{}", self.code, self.message)?;
+        match (self.lineno, self.column) {
+            (Some(lineno), Some(column)) => {
+                write!(f, " (line {}, col {})", lineno, column)?
+            }
+            (Some(lineno), None) => write!(f, " (line {})", lineno)?,
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+impl Error for UxfError {}
+
+/// A non-fatal problem, e.g., a mistake that lenient parsing accepted (see
+/// `ParseOptions::on_warning`) or a value that doesn't match its declared
+/// vtype (see `Uxf::validate()`). A `Warning` has a code, a message, and
+/// (if known) a position, just like an `UxfError`.
+pub type Warning = UxfError;
@@ -72,6 +72,19 @@ impl Field {
             Some(vtype) => Some(vtype),
         }
     }
+
+    /// Returns true if this ``Field``'s `vtype` is compatible with the
+    /// `other` ``Field``'s `vtype`; otherwise returns false.
+    ///
+    /// Two vtypes are compatible if they are equal or if either (or both)
+    /// is `None`, since a `vtype` of `None` accepts any `Value` type.
+    /// Field names are ignored.
+    pub fn type_compatible_with(&self, other: &Field) -> bool {
+        match (&self.vtype, &other.vtype) {
+            (Some(avtype), Some(bvtype)) => avtype == bvtype,
+            _ => true,
+        }
+    }
 }
 
 impl Ord for Field {
@@ -0,0 +1,105 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::constants::*;
+use crate::error::UxfError;
+use crate::output;
+use crate::table::Table;
+use crate::value::{Key, Value};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::HashMap;
+
+// Returns the table as an array of objects keyed by field name.
+pub(crate) fn table_to_json(table: &Table) -> Result<JsonValue, UxfError> {
+    let fields = table.tclass().fields();
+    let mut records = Vec::with_capacity(table.records().len());
+    for record in table.records() {
+        let mut object = Map::new();
+        for (field, value) in fields.iter().zip(record) {
+            object.insert(
+                field.name().to_string(),
+                opt_value_to_json(value)?,
+            );
+        }
+        records.push(JsonValue::Object(object));
+    }
+    Ok(JsonValue::Array(records))
+}
+
+fn opt_value_to_json(value: &Option<Value>) -> Result<JsonValue, UxfError> {
+    match value {
+        None => Ok(JsonValue::Null),
+        Some(value) => value_to_json(value),
+    }
+}
+
+// Bytes become base64 strings, dates and datetimes become ISO 8601
+// strings, and reals that JSON can't represent (NaN, inf) become null.
+fn value_to_json(value: &Value) -> Result<JsonValue, UxfError> {
+    Ok(match value {
+        // May not fit in a JSON number so use a string
+        #[cfg(feature = "bigint")]
+        Value::BigInt(i) => JsonValue::String(i.to_string()),
+        Value::Bool(b) => JsonValue::Bool(*b),
+        Value::Bytes(b) => JsonValue::String(STANDARD.encode(b)),
+        Value::Date(d) => {
+            JsonValue::String(d.format(ISO8601_DATE).to_string())
+        }
+        Value::DateTime(dt) => {
+            JsonValue::String(dt.format(ISO8601_DATETIME).to_string())
+        }
+        Value::Int(i) => JsonValue::from(*i),
+        Value::List(lst) => JsonValue::Array(
+            lst.values()
+                .iter()
+                .map(opt_value_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Map(m) => {
+            // Keys are converted in canonical order so that if two
+            // collide the error is deterministic
+            let mut object = Map::new();
+            let mut seen: HashMap<String, &Key> = HashMap::new();
+            for (key, value) in m.iter_sorted() {
+                let name = key_to_json(key);
+                if let Some(other) = seen.get(&name) {
+                    return Err(UxfError::new(
+                        730,
+                        format!(
+                            "can't convert map to JSON: keys {} and {} \
+                              both become {:?}",
+                            key_text(other),
+                            key_text(key),
+                            name
+                        ),
+                    ));
+                }
+                object.insert(name.clone(), opt_value_to_json(value)?);
+                seen.insert(name, key);
+            }
+            JsonValue::Object(object)
+        }
+        Value::Real(r) => JsonValue::from(*r),
+        Value::Str(s) => JsonValue::String(s.clone()),
+        Value::Table(t) => table_to_json(t)?,
+    })
+}
+
+// JSON object keys must be strings, so distinct keys may collide, e.g.,
+// the int 1 and the str <1>.
+fn key_to_json(key: &Key) -> String {
+    match key {
+        Key::Bytes(b) => STANDARD.encode(b),
+        Key::Date(d) => d.format(ISO8601_DATE).to_string(),
+        Key::DateTime(dt) => dt.format(ISO8601_DATETIME).to_string(),
+        Key::Int(i) => i.to_string(),
+        Key::Str(s) => s.clone(),
+    }
+}
+
+fn key_text(key: &Key) -> String {
+    let mut s = String::new();
+    output::write_key(&mut s, key);
+    s
+}
@@ -11,16 +11,26 @@ TODO
 
 */
 
+#[cfg(feature = "cache")]
+mod cache;
 pub mod constants;
+pub mod error;
 pub mod field;
+#[cfg(feature = "serde")]
+mod json;
 pub mod list;
+pub mod macros;
 pub mod map;
+pub mod output;
 pub mod parser;
 pub mod table;
 pub mod tclass;
 pub mod test_utils;
 pub mod util;
+pub mod uxf;
 pub mod value;
+pub mod visitor;
 
+pub use crate::uxf::Uxf;
 pub use crate::value::Value;
 // pub use crate::parser::parser; // etc
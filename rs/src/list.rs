@@ -1,11 +1,302 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
-use crate::value::Value;
+use crate::constants::*;
+use crate::error::UxfError;
+use crate::output::{self, Format};
+use crate::util;
+use crate::value::{self, Value};
+use anyhow::{bail, Result};
+use std::fmt;
+use std::ops::{Add, Bound, RangeBounds};
 
-#[derive(Clone, Debug)]
+// The largest magnitude for which every int has an exact f64 equivalent.
+const MAX_EXACT_INT: u64 = 1 << 53;
+
+/// Provides a list of values, optionally constrained to a `vtype`.
+/// Null values are stored as `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct List {
     vtype: Option<String>,
     comment: Option<String>,
     values: Vec<Option<Value>>,
 }
+
+impl List {
+    /// Creates a new empty `List` with the given `vtype` and `comment`
+    /// _or_ returns an Err if the `vtype` is invalid.
+    ///
+    /// A `vtype` must be either a built-in vtype (e.g., `int` or `table`;
+    /// see `constants::VTYPES`) or a valid ttype name (which needn't be
+    /// defined yet). An invalid ttype name is reported with the same error
+    /// code as `TClass::new()` would give for it, e.g., `#602` for `1bad`.
+    ///
+    /// A `vtype` of `None` signifies that this `List` will accept values
+    /// of _any_ `Value` type.
+    pub fn new(vtype: Option<&str>, comment: Option<&str>) -> Result<Self> {
+        if let Some(vtype) = vtype {
+            if !VTYPES.contains(&vtype) {
+                util::check_name(vtype)?;
+            }
+        }
+        Ok(List {
+            vtype: vtype.map(|s| s.to_string()),
+            comment: comment.map(|s| s.to_string()),
+            values: vec![],
+        })
+    }
+
+    /// Returns the ``List``'s `vtype` or `None` if its values may be of any
+    /// type.
+    pub fn vtype(&self) -> Option<&str> {
+        match &self.vtype {
+            None => None,
+            Some(vtype) => Some(vtype),
+        }
+    }
+
+    pub(crate) fn set_vtype(&mut self, vtype: Option<&str>) {
+        self.vtype = vtype.map(|s| s.to_string());
+    }
+
+    /// Sets this ``List``'s `vtype` to the type of its values if it is
+    /// untyped and every non-null value is of the same type (using a
+    /// table's ttype for tables); otherwise leaves the `List` unchanged.
+    ///
+    /// This makes output more self-describing, e.g., `[1 2 ? 3]` becomes
+    /// `[int 1 2 ? 3]`, whereas `[1 <two>]` stays untyped. Nested lists
+    /// aren't changed. (See also `Value::simplify()` which does the
+    /// opposite.)
+    pub fn tighten_vtype(&mut self) {
+        if self.vtype.is_none() {
+            if let Some(vtype) =
+                value::common_type_name(self.values.iter().flatten())
+            {
+                self.vtype = Some(vtype.to_string());
+            }
+        }
+    }
+
+    /// Returns the ``List``'s `comment` if it has one.
+    pub fn comment(&self) -> Option<&str> {
+        match &self.comment {
+            None => None,
+            Some(comment) => Some(comment),
+        }
+    }
+
+    /// Sets the ``List``'s `comment` (or removes it if `comment` is
+    /// `None`) _or_ returns an Err if `max_len` is given and the comment
+    /// is longer than `max_len` characters. (See also
+    /// `Uxf::max_comment_len()`.)
+    pub fn set_comment(
+        &mut self,
+        comment: Option<&str>,
+        max_len: Option<usize>,
+    ) -> Result<()> {
+        if let (Some(comment), Some(max_len)) = (comment, max_len) {
+            util::check_comment(comment, max_len)?;
+        }
+        self.comment = comment.map(|s| s.to_string());
+        Ok(())
+    }
+
+    /// Appends the given `value` _or_ returns an Err if this `List` has
+    /// a `vtype` and the value isn't of that type. Nulls are always
+    /// accepted.
+    pub fn push(&mut self, value: Option<Value>) -> Result<()> {
+        self.check_vtype(&value)?;
+        self.values.push(value);
+        Ok(())
+    }
+
+    /// Appends the given `value` like `push()`, except that pushing a real
+    /// into an `int`-typed `List` widens the list's `vtype` to `real`
+    /// (converting its ints to reals) rather than returning an Err.
+    ///
+    /// Returns an Err (leaving this `List` unchanged) if the `value`
+    /// doesn't match the `vtype` and can't be widened to, or if any of the
+    /// existing ints can't be represented exactly as a real (i.e., is
+    /// outside ±2⁵³).
+    pub fn push_widening(&mut self, value: Option<Value>) -> Result<()> {
+        if self.vtype() == Some(VTYPE_NAME_INT)
+            && matches!(value, Some(Value::Real(_)))
+        {
+            for value in self.values.iter().flatten() {
+                match value {
+                    Value::Int(i) if i.unsigned_abs() <= MAX_EXACT_INT => {}
+                    _ => bail!(UxfError::new(
+                        398,
+                        format!(
+                            "can't widen an int list to real: {} can't \
+                              be represented exactly as a real",
+                            value.summary(40)
+                        )
+                    )),
+                }
+            }
+            for value in self.values.iter_mut().flatten() {
+                if let Value::Int(i) = value {
+                    *value = Value::Real(*i as f64);
+                }
+            }
+            self.set_vtype(Some(VTYPE_NAME_REAL));
+        }
+        self.push(value)
+    }
+
+    /// Replaces the values in the given `range` with the `replacement`
+    /// values (of which there may be more or fewer) and returns the
+    /// replaced values _or_ returns an Err (leaving this `List` unchanged)
+    /// if the `range` is out of bounds or if this `List` has a `vtype` and
+    /// any of the replacement values isn't of that type.
+    ///
+    /// For example, `lst.splice(1..3, vec![])` removes the second and third
+    /// values and `lst.splice(0..0, values)` inserts the `values` at the
+    /// start.
+    pub fn splice<R, I>(
+        &mut self,
+        range: R,
+        replacement: I,
+    ) -> Result<Vec<Option<Value>>>
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = Option<Value>>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.values.len(),
+        };
+        if start > end || end > self.values.len() {
+            bail!(UxfError::new(
+                394,
+                format!(
+                    "can't splice {}..{} of a list of {} values",
+                    start,
+                    end,
+                    self.values.len()
+                )
+            ));
+        }
+        let replacement: Vec<Option<Value>> =
+            replacement.into_iter().collect();
+        for value in &replacement {
+            self.check_vtype(value)?;
+        }
+        Ok(self.values.splice(start..end, replacement).collect())
+    }
+
+    fn check_vtype(&self, value: &Option<Value>) -> Result<()> {
+        if let (Some(vtype), Some(value)) = (self.vtype(), value) {
+            if !value.has_vtype(vtype) {
+                bail!(UxfError::new(
+                    376,
+                    value::vtype_mismatch("list", vtype, value)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Like the Python UXF parser, ours treats a value that doesn't match
+    // its list's vtype as a lint rather than an error, so doesn't check.
+    pub(crate) fn push_unchecked(&mut self, value: Option<Value>) {
+        self.values.push(value);
+    }
+
+    /// Returns the number of values (including nulls).
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if this `List` has no values; otherwise returns false.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the value at the given `index` (where `Some(None)` is a
+    /// null) or `None` if the `index` is out of range.
+    pub fn get(&self, index: usize) -> Option<&Option<Value>> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over the values in order (where `None` is a
+    /// null). A `&List` can also be iterated directly, e.g.,
+    /// `for value in &lst`.
+    pub fn iter(&self) -> std::slice::Iter<'_, Option<Value>> {
+        self.values.iter()
+    }
+
+    pub(crate) fn values(&self) -> &[Option<Value>] {
+        &self.values
+    }
+
+    pub(crate) fn values_mut(&mut self) -> &mut Vec<Option<Value>> {
+        &mut self.values
+    }
+
+    /// Retains only the values for which `f` returns true.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Option<Value>) -> bool,
+    {
+        self.values.retain(f);
+    }
+
+    /// Returns how many values this `List` can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.values.capacity()
+    }
+
+    /// Shrinks this ``List``'s capacity to fit its values, e.g., to
+    /// reclaim memory after `retain()` has removed many values.
+    pub fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+}
+
+impl Add for List {
+    type Output = List;
+
+    /// Returns a new `List` holding this ``List``'s values followed by the
+    /// `other` ``List``'s values.
+    ///
+    /// If both lists have the same `vtype` the new list has it too;
+    /// otherwise the new list is untyped (its `vtype` is `None`) so that it
+    /// can hold all the values. The new list has this ``List``'s comment
+    /// (or the `other` ``List``'s if this one has none).
+    fn add(mut self, other: List) -> List {
+        if self.vtype != other.vtype {
+            self.vtype = None;
+        }
+        if self.comment.is_none() {
+            self.comment = other.comment;
+        }
+        self.values.extend(other.values);
+        self
+    }
+}
+
+impl<'a> IntoIterator for &'a List {
+    type Item = &'a Option<Value>;
+    type IntoIter = std::slice::Iter<'a, Option<Value>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for List {
+    /// Writes this `List` as UXF text, e.g., `[#<comment> int 1 2 ?]`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = String::new();
+        output::write_list(&mut s, self, &Format::default());
+        write!(f, "{}", s)
+    }
+}
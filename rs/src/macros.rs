@@ -0,0 +1,105 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::list::List;
+use crate::map::Map;
+use crate::value::{Key, Value};
+
+/// Returns a `Value::List` or `Value::Map` from a UXF-like literal.
+///
+/// Values are separated by whitespace and may be `?` (null), `yes`, `no`,
+/// int and real literals, strs in the form `<word>` or `<"any text">`,
+/// or nested lists `[...]` and maps `{...}`. Map keys must be ints or
+/// strs. Lists and maps are untyped and tables aren't supported.
+///
+/// ```
+/// use uxf::uxf_value;
+/// let v = uxf_value!([1 -2 3.5 <three> <"four five"> ? yes [] {<k> no}]);
+/// let uxo = uxf::parser::parse(
+///     "uxf 1.0\n[1 -2 3.5 <three> <four five> ? yes [] {<k> no}]\n"
+/// ).unwrap();
+/// assert_eq!(&v, uxo.value());
+/// ```
+///
+/// Panics if a map has an odd number of items or a key that isn't an int
+/// or str.
+#[macro_export]
+macro_rules! uxf_value {
+    ([ $($items:tt)* ]) => {{
+        let mut _items: Vec<Option<$crate::value::Value>> = vec![];
+        $crate::__uxf_items!(_items $($items)*);
+        $crate::macros::__list(_items)
+    }};
+    ({ $($items:tt)* }) => {{
+        let mut _items: Vec<Option<$crate::value::Value>> = vec![];
+        $crate::__uxf_items!(_items $($items)*);
+        $crate::macros::__map(_items)
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __uxf_items {
+    ($v:ident) => {};
+    ($v:ident ? $($rest:tt)*) => {
+        $v.push(None);
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident yes $($rest:tt)*) => {
+        $v.push(Some($crate::value::Value::Bool(true)));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident no $($rest:tt)*) => {
+        $v.push(Some($crate::value::Value::Bool(false)));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident [ $($inner:tt)* ] $($rest:tt)*) => {
+        $v.push(Some($crate::uxf_value!([ $($inner)* ])));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident { $($inner:tt)* } $($rest:tt)*) => {
+        $v.push(Some($crate::uxf_value!({ $($inner)* })));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident < $s:ident > $($rest:tt)*) => {
+        $v.push(Some($crate::value::Value::Str(stringify!($s).to_string())));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident < $s:literal > $($rest:tt)*) => {
+        $v.push(Some($crate::value::Value::Str($s.to_string())));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+    ($v:ident $n:literal $($rest:tt)*) => {
+        $v.push(Some($crate::value::Value::from($n)));
+        $crate::__uxf_items!($v $($rest)*);
+    };
+}
+
+#[doc(hidden)]
+pub fn __list(values: Vec<Option<Value>>) -> Value {
+    let mut lst = List::new(None, None).unwrap(); // untyped: can't fail
+    for value in values {
+        lst.push_unchecked(value);
+    }
+    Value::List(lst)
+}
+
+#[doc(hidden)]
+pub fn __map(items: Vec<Option<Value>>) -> Value {
+    if !items.len().is_multiple_of(2) {
+        panic!("uxf_value! map has a key without a value");
+    }
+    let mut m = Map::new(None, None, None).unwrap(); // untyped: can't fail
+    let mut items = items.into_iter();
+    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+        let key = match key {
+            Some(Value::Int(i)) => Key::Int(i),
+            Some(Value::Str(s)) => Key::Str(s),
+            key => {
+                panic!("uxf_value! map keys must be int or str: {:?}", key)
+            }
+        };
+        m.insert_unchecked(key, value);
+    }
+    Value::Map(m)
+}
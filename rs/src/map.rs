@@ -1,13 +1,383 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
-use crate::value::{Key, Value};
+use crate::constants::*;
+use crate::error::UxfError;
+use crate::output::{self, Format};
+use crate::util;
+use crate::value::{self, Key, Value};
+use anyhow::{bail, Result};
+use chrono::prelude::*;
 use std::collections::HashMap;
+use std::fmt;
 
+/// Provides a map of keys to values, optionally constrained to a `ktype`
+/// and a `vtype`. Null values are stored as `None`.
+///
+/// A map may have a single `comment` (written at its start). UXF doesn't
+/// support comments on individual entries, so there are none to store.
+///
+/// A `Map` remembers the order in which its keys were inserted (see
+/// `keys()`), but this order isn't significant: maps with the same items
+/// are equal whatever their order, and maps are always written in
+/// canonical key order (see `Key`). `Value::canonicalize()` puts the keys
+/// into canonical order.
 #[derive(Clone, Debug)]
 pub struct Map {
     ktype: Option<String>,
     vtype: Option<String>,
     comment: Option<String>,
     items: HashMap<Key, Option<Value>>,
+    keys: Vec<Key>, // in insertion order
+}
+
+impl Map {
+    /// Creates a new empty `Map` with the given `ktype`, `vtype`, and
+    /// `comment` _or_ returns an Err if the `ktype` or `vtype` is invalid.
+    ///
+    /// A `ktype` must be one of `constants::KTYPES` (`bytes`, `date`,
+    /// `datetime`, `int`, or `str`); a `ktype` of `None` signifies that
+    /// this `Map` will accept keys of _any_ of these types. A `vtype` is
+    /// validated as for `List::new()` and may only be given if there is a
+    /// `ktype` (since UXF can only express a map's vtype after its ktype,
+    /// e.g., `{str int <a> 1}`).
+    pub fn new(
+        ktype: Option<&str>,
+        vtype: Option<&str>,
+        comment: Option<&str>,
+    ) -> Result<Self> {
+        if let Some(ktype) = ktype {
+            util::check_ktype(ktype)?;
+        }
+        if let Some(vtype) = vtype {
+            if ktype.is_none() {
+                bail!(UxfError::new(
+                    282,
+                    format!(
+                        "a map may only have a vtype if it has a ktype, \
+                          got vtype {}",
+                        vtype
+                    )
+                ));
+            }
+            if !VTYPES.contains(&vtype) {
+                util::check_name(vtype)?;
+            }
+        }
+        Ok(Map {
+            ktype: ktype.map(|s| s.to_string()),
+            vtype: vtype.map(|s| s.to_string()),
+            comment: comment.map(|s| s.to_string()),
+            items: HashMap::new(),
+            keys: vec![],
+        })
+    }
+
+    /// Returns the ``Map``'s `ktype` or `None` if its keys may be of any
+    /// valid key type.
+    pub fn ktype(&self) -> Option<&str> {
+        match &self.ktype {
+            None => None,
+            Some(ktype) => Some(ktype),
+        }
+    }
+
+    /// Returns the ``Map``'s `vtype` or `None` if its values may be of any
+    /// type.
+    pub fn vtype(&self) -> Option<&str> {
+        match &self.vtype {
+            None => None,
+            Some(vtype) => Some(vtype),
+        }
+    }
+
+    pub(crate) fn set_ktype(&mut self, ktype: Option<&str>) {
+        self.ktype = ktype.map(|s| s.to_string());
+    }
+
+    pub(crate) fn set_vtype(&mut self, vtype: Option<&str>) {
+        self.vtype = vtype.map(|s| s.to_string());
+    }
+
+    /// Sets this ``Map``'s `ktype` to the type of its keys if it has no
+    /// `ktype` and all its keys are of the same type, and then sets its
+    /// `vtype` to the type of its values if it has a `ktype` but no
+    /// `vtype` and every non-null value is of the same type (using a
+    /// table's ttype for tables). Otherwise leaves the `Map` unchanged.
+    ///
+    /// A `vtype` is only set if there is a `ktype` since UXF can only
+    /// express a map's vtype after its ktype, e.g., `{int str 1 <a>}`.
+    /// Nested maps aren't changed.
+    pub fn tighten_types(&mut self) {
+        if self.ktype.is_none() {
+            let mut keys = self.items.keys();
+            if let Some(first) = keys.next() {
+                let ktype = first.type_name();
+                if keys.all(|key| key.type_name() == ktype) {
+                    self.ktype = Some(ktype.to_string());
+                }
+            }
+        }
+        if self.ktype.is_some() && self.vtype.is_none() {
+            if let Some(vtype) =
+                value::common_type_name(self.items.values().flatten())
+            {
+                self.vtype = Some(vtype.to_string());
+            }
+        }
+    }
+
+    /// Returns the ``Map``'s `comment` if it has one.
+    pub fn comment(&self) -> Option<&str> {
+        match &self.comment {
+            None => None,
+            Some(comment) => Some(comment),
+        }
+    }
+
+    /// Sets the ``Map``'s `comment` (or removes it if `comment` is
+    /// `None`) _or_ returns an Err if `max_len` is given and the comment
+    /// is longer than `max_len` characters. (See also
+    /// `Uxf::max_comment_len()`.)
+    pub fn set_comment(
+        &mut self,
+        comment: Option<&str>,
+        max_len: Option<usize>,
+    ) -> Result<()> {
+        if let (Some(comment), Some(max_len)) = (comment, max_len) {
+            util::check_comment(comment, max_len)?;
+        }
+        self.comment = comment.map(|s| s.to_string());
+        Ok(())
+    }
+
+    /// Inserts the given `key` and `value` (where `None` is a null) and
+    /// returns the key's previous value (if it was present) _or_ returns an
+    /// Err if the key doesn't match this ``Map``'s `ktype` or the value
+    /// doesn't match its `vtype`. Nulls are always accepted.
+    pub fn insert(
+        &mut self,
+        key: Key,
+        value: Option<Value>,
+    ) -> Result<Option<Option<Value>>> {
+        self.check_ktype(&key)?;
+        if let (Some(vtype), Some(value)) = (self.vtype(), &value) {
+            if !value.has_vtype(vtype) {
+                bail!(UxfError::new(
+                    396,
+                    value::vtype_mismatch("map", vtype, value)
+                ));
+            }
+        }
+        Ok(self.insert_unchecked(key, value))
+    }
+
+    // The parser accepts mismatched values (as does the Python version)
+    pub(crate) fn insert_unchecked(
+        &mut self,
+        key: Key,
+        value: Option<Value>,
+    ) -> Option<Option<Value>> {
+        if !self.items.contains_key(&key) {
+            self.keys.push(key.clone());
+        }
+        self.items.insert(key, value)
+    }
+
+    /// Removes the given `key` and returns its value (where `Some(None)`
+    /// is a null) or `None` if the `key` isn't present.
+    pub fn remove(&mut self, key: &Key) -> Option<Option<Value>> {
+        let value = self.items.remove(key);
+        if value.is_some() {
+            self.keys.retain(|k| k != key);
+        }
+        value
+    }
+
+    /// Returns true if the given `key` is present; otherwise returns false.
+    pub fn contains_key(&self, key: &Key) -> bool {
+        self.items.contains_key(key)
+    }
+
+    /// Returns the number of items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns true if there are no items; otherwise returns false.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub(crate) fn items(&self) -> &HashMap<Key, Option<Value>> {
+        &self.items
+    }
+
+    pub(crate) fn values_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut Option<Value>> {
+        self.items.values_mut()
+    }
+
+    /// Returns an iterator over the keys in the order they were inserted
+    /// (or in canonical order if `Value::canonicalize()` has been called
+    /// since the last insertion).
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.keys.iter()
+    }
+
+    /// Returns an iterator over the items in the same order as `keys()`.
+    /// Each item is a key and its value (where `None` is a null).
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Option<Value>)> {
+        self.keys.iter().map(|key| (key, &self.items[key]))
+    }
+
+    /// Returns an iterator over the items in canonical key order (see
+    /// `Key`), which is the order in which they are written. Each item is
+    /// a key and its value (where `None` is a null).
+    pub fn iter_sorted(
+        &self,
+    ) -> impl Iterator<Item = (&Key, &Option<Value>)> {
+        let mut items: Vec<_> = self.items.iter().collect();
+        items.sort_by_key(|(key, _)| *key);
+        items.into_iter()
+    }
+
+    // Puts the keys into canonical order; see Value::canonicalize().
+    pub(crate) fn sort_keys(&mut self) {
+        self.keys.sort();
+    }
+
+    /// Returns the value for the given `key` (where `Some(None)` is a null)
+    /// or `None` if the `key` isn't present.
+    pub fn get(&self, key: &Key) -> Option<&Option<Value>> {
+        self.items.get(key)
+    }
+
+    /// Returns the value for the given str `key`; see `get()`.
+    pub fn get_str(&self, key: &str) -> Option<&Option<Value>> {
+        self.get(&Key::Str(key.to_string()))
+    }
+
+    /// Returns the value for the given int `key`; see `get()`.
+    pub fn get_int(&self, key: i64) -> Option<&Option<Value>> {
+        self.get(&Key::Int(key))
+    }
+
+    /// Returns the value for the given date `key`; see `get()`.
+    pub fn get_date(&self, key: NaiveDate) -> Option<&Option<Value>> {
+        self.get(&Key::Date(key))
+    }
+
+    /// Returns the value for the given datetime `key`; see `get()`.
+    pub fn get_datetime(
+        &self,
+        key: NaiveDateTime,
+    ) -> Option<&Option<Value>> {
+        self.get(&Key::DateTime(key))
+    }
+
+    /// Returns the value for the given bytes `key`; see `get()`.
+    pub fn get_bytes(&self, key: &[u8]) -> Option<&Option<Value>> {
+        self.get(&Key::Bytes(key.to_vec()))
+    }
+
+    /// Returns the value for the given str `key` converted to type `T`,
+    /// `Ok(None)` if the key isn't present or its value is null, _or_
+    /// returns an Err if the value can't be converted.
+    ///
+    /// This is convenient for reading configuration maps, e.g.,
+    /// `let port = config.get_typed::<i64>("port")?.unwrap_or(8080);`.
+    /// `T` may be any type that implements `TryFrom<Value>`, such as
+    /// `bool`, `Vec<u8>`, `NaiveDate`, `NaiveDateTime`, `i64`, `f64`, or
+    /// `String` (for which a mismatched value gives a `#392` error).
+    pub fn get_typed<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: TryFrom<Value>,
+        T::Error: Into<anyhow::Error>,
+    {
+        match self.get_str(key) {
+            Some(Some(value)) => {
+                T::try_from(value.clone()).map(Some).map_err(Into::into)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Inserts the given `key` with a null value _or_ returns an Err if the
+    /// key doesn't match this ``Map``'s `ktype`. If the key is already
+    /// present its value is set to null.
+    ///
+    /// This supports using a map as a set, e.g., `{str <a> ? <b> ?}`.
+    pub fn insert_key(&mut self, key: Key) -> Result<()> {
+        self.check_ktype(&key)?;
+        self.insert_unchecked(key, None);
+        Ok(())
+    }
+
+    fn check_ktype(&self, key: &Key) -> Result<()> {
+        if let Some(ktype) = self.ktype() {
+            if ktype != key.type_name() {
+                bail!(UxfError::new(
+                    384,
+                    format!(
+                        "can't insert a key of type {} into a map with \
+                          ktype {}",
+                        key.type_name(),
+                        ktype
+                    )
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if every value is null (i.e., this `Map` is being used
+    /// as a set); otherwise returns false. An empty `Map` is a set.
+    pub fn is_set(&self) -> bool {
+        self.items.values().all(|value| value.is_none())
+    }
+
+    /// Retains only the items for which `f` returns true.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Key, &mut Option<Value>) -> bool,
+    {
+        self.items.retain(f);
+        let items = &self.items;
+        self.keys.retain(|key| items.contains_key(key));
+    }
+
+    /// Returns how many items this `Map` can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+
+    /// Shrinks this ``Map``'s capacity to fit its items, e.g., to reclaim
+    /// memory after `retain()` has removed many items.
+    pub fn shrink_to_fit(&mut self) {
+        self.items.shrink_to_fit();
+        self.keys.shrink_to_fit();
+    }
+}
+
+impl PartialEq for Map {
+    // The key order isn't significant so isn't compared
+    fn eq(&self, other: &Self) -> bool {
+        self.ktype == other.ktype
+            && self.vtype == other.vtype
+            && self.comment == other.comment
+            && self.items == other.items
+    }
+}
+
+impl Eq for Map {}
+
+impl fmt::Display for Map {
+    /// Writes this `Map` as UXF text, e.g., `{str int <a> 1 <b> ?}`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = String::new();
+        output::write_map(&mut s, self, &Format::default());
+        write!(f, "{}", s)
+    }
 }
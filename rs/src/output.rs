@@ -1,3 +1,471 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
+use crate::constants::*;
+use crate::error::UxfError;
+use crate::list::List;
+use crate::map::Map;
+use crate::table::Table;
+use crate::tclass::TClass;
+use crate::util;
+use crate::uxf::Uxf;
+use crate::value::{self, Key, Value};
+use anyhow::{bail, Result};
+use std::fmt::Write as _;
+
+/// Specifies how a `Uxf` is written as UXF text.
+///
+/// `align_columns` defaults to `false`; if `true` each table record is
+/// written on its own line with each column padded to the width of its
+/// widest value (so that tables render as aligned grids).
+///
+/// `indent` defaults to `None` which means that the value is written on a
+/// single line (except for aligned tables). If it is `Some` then any list
+/// or map that contains a list, map, or table, and any table with more
+/// than one record or that contains a collection, has each of its items
+/// written on its own line, indented one `Indent` unit per level of
+/// nesting. Use `Format::with_indent()` to create a `Format` from a
+/// string such as `"  "` or `"\t"`.
+///
+/// `wrap_width` defaults to `None` which means that lines aren't wrapped.
+/// If it is `Some` then any item (including a ttype definition's fields)
+/// that would make its line wider than this many characters is written on
+/// a new line instead, indented one `Indent` unit more than the collection
+/// (or ttype definition) it belongs to. An item that is wider than this
+/// by itself is still written whole, and strs are never split.
+///
+/// Use `Format::pretty()` for the reference implementation's layout of a
+/// two-space indent and wrapping at 76 characters. (`Format::default()`
+/// writes everything on one line, as `Uxf::to_string()` does.)
+///
+/// Reals are always written with a decimal point or exponent (e.g., `3.0`,
+/// never `3`) since otherwise they would be read back as ints. (See also
+/// `Value::real_is_integral()` and `util::real_to_uxf()`.)
+#[derive(Clone, Debug, Default)]
+pub struct Format {
+    pub align_columns: bool,
+    pub indent: Option<Indent>,
+    pub wrap_width: Option<usize>,
+}
+
+impl Format {
+    /// Creates a new `Format` that indents using the given `indent` unit
+    /// (which must be at most 32 spaces or a single tab) _or_ returns an
+    /// Err if `indent` is invalid. An empty `indent` means that nested
+    /// collections are written on their own lines but not indented.
+    pub fn with_indent(indent: &str) -> Result<Self> {
+        Ok(Format {
+            indent: Some(Indent::new(indent)?),
+            ..Default::default()
+        })
+    }
+
+    /// Creates a new `Format` that indents using two spaces and wraps
+    /// lines at 76 characters.
+    pub fn pretty() -> Self {
+        Format {
+            indent: Some(Indent::Spaces(2)),
+            wrap_width: Some(WRAP_WIDTH),
+            ..Default::default()
+        }
+    }
+}
+
+/// An indentation unit: a number of spaces or a tab.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Indent {
+    Spaces(usize),
+    Tab,
+}
+
+impl Indent {
+    /// Creates a new `Indent` from the given `indent` (which must be at
+    /// most 32 spaces or a single tab) _or_ returns an Err if `indent` is
+    /// invalid.
+    pub fn new(indent: &str) -> Result<Self> {
+        if !indent.chars().all(char::is_whitespace) {
+            bail!(UxfError::new(
+                720,
+                format!(
+                    "indent may only contain whitespace, got {:?}",
+                    indent
+                )
+            ));
+        }
+        if indent == "\t" {
+            Ok(Indent::Tab)
+        } else if indent.chars().all(|c| c == ' ')
+            && indent.len() <= MAX_INDENT
+        {
+            Ok(Indent::Spaces(indent.len()))
+        } else {
+            bail!(UxfError::new(
+                722,
+                format!(
+                    "indent must be at most {} spaces or a single tab, \
+                      got {:?}",
+                    MAX_INDENT, indent
+                )
+            ));
+        }
+    }
+
+    fn write(&self, s: &mut String, depth: usize) {
+        match self {
+            Indent::Spaces(n) => s.push_str(&" ".repeat(n * depth)),
+            Indent::Tab => s.push_str(&"\t".repeat(depth)),
+        }
+    }
+}
+
+const MAX_INDENT: usize = 32;
+const WRAP_WIDTH: usize = 76;
+
+/// Returns the given `Uxf` as UXF text using the given `Format` _or_
+/// returns an Err if the `Uxf` holds a NaN or infinite real (`#386`),
+/// since UXF text can't represent these.
+pub(crate) fn to_string(uxo: &Uxf, format: &Format) -> Result<String> {
+    for (path, value) in uxo.value().iter_paths() {
+        if let Value::Real(r) = value {
+            if let Err(err) = util::real_to_uxf(*r) {
+                bail!(UxfError::new(
+                    err.code(),
+                    format!(
+                        "{}: {}",
+                        value::path_to_string(&path),
+                        err.message()
+                    )
+                ));
+            }
+        }
+    }
+    Ok(to_string_unchecked(uxo, format))
+}
+
+/// Returns the given `Uxf` as UXF text using the given `Format`, writing
+/// any NaN or infinite reals as `write_real()` does.
+pub(crate) fn to_string_unchecked(uxo: &Uxf, format: &Format) -> String {
+    let mut s = format!("uxf {:.1}", UXF_VERSION);
+    if let Some(custom) = uxo.custom() {
+        s.push(' ');
+        s.push_str(custom);
+    }
+    s.push('\n');
+    if let Some(comment) = uxo.comment() {
+        write_comment(&mut s, comment);
+        s.push('\n');
+    }
+    let mut tclasses: Vec<&TClass> = uxo.tclasses().values().collect();
+    tclasses.sort();
+    for tclass in tclasses {
+        write_tclass(&mut s, tclass, format);
+    }
+    write_value(&mut s, uxo.value(), format);
+    s.push('\n');
+    s
+}
+
+fn write_tclass(s: &mut String, tclass: &TClass, format: &Format) {
+    s.push('=');
+    if let Some(comment) = tclass.comment() {
+        write_comment(s, comment);
+        s.push(' ');
+    }
+    s.push_str(tclass.ttype());
+    for field in tclass.fields() {
+        write_wrapped(s, " ", format, 1, |s| {
+            s.push_str(field.name());
+            if let Some(vtype) = field.vtype() {
+                s.push(':');
+                s.push_str(vtype);
+            }
+        });
+    }
+    s.push('\n');
+}
+
+fn write_comment(s: &mut String, comment: &str) {
+    s.push_str("#<");
+    s.push_str(&util::escape_str(comment));
+    s.push('>');
+}
+
+fn write_opt_value(
+    s: &mut String,
+    value: &Option<Value>,
+    format: &Format,
+    depth: usize,
+) {
+    match value {
+        None => s.push('?'),
+        Some(value) => write_value_at(s, value, format, depth),
+    }
+}
+
+pub(crate) fn write_value(s: &mut String, value: &Value, format: &Format) {
+    write_value_at(s, value, format, 0);
+}
+
+pub(crate) fn write_list(s: &mut String, lst: &List, format: &Format) {
+    write_list_at(s, lst, format, 0);
+}
+
+pub(crate) fn write_map(s: &mut String, m: &Map, format: &Format) {
+    write_map_at(s, m, format, 0);
+}
+
+pub(crate) fn write_table(s: &mut String, t: &Table, format: &Format) {
+    write_table_at(s, t, format, 0);
+}
+
+fn write_value_at(
+    s: &mut String,
+    value: &Value,
+    format: &Format,
+    depth: usize,
+) {
+    match value {
+        #[cfg(feature = "bigint")]
+        Value::BigInt(i) => s.push_str(&i.to_string()),
+        Value::Bool(true) => s.push_str(BOOL_TRUE),
+        Value::Bool(false) => s.push_str(BOOL_FALSE),
+        Value::Bytes(b) => write_bytes(s, b),
+        Value::Date(d) => s.push_str(&d.format(ISO8601_DATE).to_string()),
+        Value::DateTime(dt) => {
+            s.push_str(&dt.format(ISO8601_DATETIME).to_string())
+        }
+        Value::Int(i) => s.push_str(&i.to_string()),
+        Value::List(lst) => write_list_at(s, lst, format, depth),
+        Value::Map(m) => write_map_at(s, m, format, depth),
+        Value::Real(r) => write_real(s, *r),
+        Value::Str(t) => write_str(s, t),
+        Value::Table(t) => write_table_at(s, t, format, depth),
+    }
+}
+
+fn write_list_at(
+    s: &mut String,
+    lst: &List,
+    format: &Format,
+    depth: usize,
+) {
+    s.push('[');
+    let mut sep = write_collection_comment(s, lst.comment());
+    if let Some(vtype) = lst.vtype() {
+        s.push_str(sep);
+        s.push_str(vtype);
+        sep = " ";
+    }
+    let broken =
+        format.indent.is_some() && lst.values().iter().any(is_collection);
+    for value in lst.values() {
+        if broken {
+            write_newline(s, format, depth + 1);
+            write_opt_value(s, value, format, depth + 1);
+        } else {
+            write_wrapped(s, sep, format, depth + 1, |s| {
+                write_opt_value(s, value, format, depth + 1)
+            });
+        }
+        sep = " ";
+    }
+    if broken {
+        write_newline(s, format, depth);
+    }
+    s.push(']');
+}
+
+fn write_map_at(s: &mut String, m: &Map, format: &Format, depth: usize) {
+    s.push('{');
+    let mut sep = write_collection_comment(s, m.comment());
+    if let Some(ktype) = m.ktype() {
+        s.push_str(sep);
+        s.push_str(ktype);
+        sep = " ";
+        if let Some(vtype) = m.vtype() {
+            s.push(' ');
+            s.push_str(vtype);
+        }
+    }
+    let broken =
+        format.indent.is_some() && m.items().values().any(is_collection);
+    // Keys are written in canonical order so that output is deterministic
+    for (key, value) in m.iter_sorted() {
+        let write_item = |s: &mut String| {
+            write_key(s, key);
+            s.push(' ');
+            write_opt_value(s, value, format, depth + 1);
+        };
+        if broken {
+            write_newline(s, format, depth + 1);
+            write_item(s);
+        } else {
+            write_wrapped(s, sep, format, depth + 1, write_item);
+        }
+        sep = " ";
+    }
+    if broken {
+        write_newline(s, format, depth);
+    }
+    s.push('}');
+}
+
+fn write_table_at(
+    s: &mut String,
+    t: &Table,
+    format: &Format,
+    depth: usize,
+) {
+    s.push('(');
+    let sep = write_collection_comment(s, t.comment());
+    s.push_str(sep);
+    s.push_str(t.tclass().ttype());
+    if format.align_columns && !t.records().is_empty() {
+        write_aligned_records(s, t, format, depth);
+    } else {
+        let broken = format.indent.is_some()
+            && (t.records().len() > 1
+                || t.records().iter().flatten().any(is_collection));
+        for record in t.records() {
+            if broken {
+                write_newline(s, format, depth + 1);
+            }
+            // A broken record's wrapped values are indented beneath it
+            let wrap_depth = if broken { depth + 2 } else { depth + 1 };
+            for (column, value) in record.iter().enumerate() {
+                let sep = if !broken || column > 0 { " " } else { "" };
+                write_wrapped(s, sep, format, wrap_depth, |s| {
+                    write_opt_value(s, value, format, depth + 1)
+                });
+            }
+        }
+        if broken {
+            write_newline(s, format, depth);
+        }
+    }
+    s.push(')');
+}
+
+// Writes each record on its own line with every column padded to the
+// width of its widest value.
+fn write_aligned_records(
+    s: &mut String,
+    t: &Table,
+    format: &Format,
+    depth: usize,
+) {
+    let mut widths = vec![0; t.tclass().len()];
+    let mut rows = Vec::with_capacity(t.records().len());
+    for record in t.records() {
+        let mut row = Vec::with_capacity(record.len());
+        for (column, value) in record.iter().enumerate() {
+            let mut cell = String::new();
+            write_opt_value(&mut cell, value, format, depth + 1);
+            widths[column] = widths[column].max(cell.chars().count());
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+    for row in rows {
+        write_newline(s, format, depth + 1);
+        let last = row.len() - 1;
+        for (column, cell) in row.iter().enumerate() {
+            if column == last {
+                s.push_str(cell);
+            } else {
+                let _ =
+                    write!(s, "{:width$} ", cell, width = widths[column]);
+            }
+        }
+    }
+    write_newline(s, format, depth);
+}
+
+// Writes the separator followed by the item that write_item() writes;
+// but if wrapping and the item's first line would go past the wrap width,
+// writes a new line indented for the given depth instead of the
+// separator. (The first item of a line is never wrapped.)
+fn write_wrapped<F>(
+    s: &mut String,
+    sep: &str,
+    format: &Format,
+    depth: usize,
+    write_item: F,
+) where
+    F: FnOnce(&mut String),
+{
+    let start = s.len();
+    s.push_str(sep);
+    write_item(s);
+    if let Some(width) = format.wrap_width {
+        if !sep.is_empty() {
+            let line_start = s[..start].rfind('\n').map_or(0, |i| i + 1);
+            let end = s[start..].find('\n').map_or(s.len(), |i| start + i);
+            if s[line_start..end].chars().count() > width {
+                let mut newline = String::new();
+                write_newline(&mut newline, format, depth);
+                s.replace_range(start..start + sep.len(), &newline);
+            }
+        }
+    }
+}
+
+// Starts a new line indented for the given depth (if indenting).
+fn write_newline(s: &mut String, format: &Format, depth: usize) {
+    s.push('\n');
+    if let Some(indent) = &format.indent {
+        indent.write(s, depth);
+    }
+}
+
+fn is_collection(value: &Option<Value>) -> bool {
+    value.as_ref().is_some_and(Value::is_collection)
+}
+
+// Writes the collection's comment (if any) and returns the separator to
+// use before whatever follows it.
+fn write_collection_comment(
+    s: &mut String,
+    comment: Option<&str>,
+) -> &'static str {
+    match comment {
+        Some(comment) => {
+            write_comment(s, comment);
+            " "
+        }
+        None => "",
+    }
+}
+
+pub(crate) fn write_key(s: &mut String, key: &Key) {
+    match key {
+        Key::Bytes(b) => write_bytes(s, b),
+        Key::Date(d) => s.push_str(&d.format(ISO8601_DATE).to_string()),
+        Key::DateTime(dt) => {
+            s.push_str(&dt.format(ISO8601_DATETIME).to_string())
+        }
+        Key::Int(i) => s.push_str(&i.to_string()),
+        Key::Str(t) => write_str(s, t),
+    }
+}
+
+fn write_bytes(s: &mut String, b: &[u8]) {
+    s.push_str(&util::bytes_to_uxf(b));
+}
+
+fn write_str(s: &mut String, t: &str) {
+    s.push('<');
+    s.push_str(&util::escape_str(t));
+    s.push('>');
+}
+
+// Non-finite reals can't be represented in UXF (and `Value::new_real()`
+// rejects them), so if there are any they are written as `inf`, `-inf`,
+// or `NaN`, which won't parse. (The `to_string()` write path reports them
+// as errors instead; this is only reached by the Display impls.)
+fn write_real(s: &mut String, r: f64) {
+    match util::real_to_uxf(r) {
+        Ok(text) => s.push_str(&text),
+        Err(_) => s.push_str(&r.to_string()),
+    }
+}
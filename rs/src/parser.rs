@@ -1,2 +1,1442 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
+
+use crate::constants::*;
+use crate::error::UxfError;
+use crate::field::Field;
+use crate::list::List;
+use crate::map::Map;
+use crate::table::Table;
+use crate::tclass::TClass;
+use crate::util;
+use crate::uxf::Uxf;
+use crate::value::{Key, PathSegment, Value};
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use unicode_normalization::char::is_combining_mark;
+
+/// Specifies how UXF text is parsed.
+///
+/// `lenient` defaults to `false` (i.e., strict). If `true` some common
+/// mistakes are accepted and normalized rather than reported as errors:
+/// - ints and reals with leading zeros (e.g., `007` is read as `7`);
+/// - lists, maps, and tables that are missing their closing `]`, `}`, or
+///   `)` at the end of the data are closed implicitly (and a `#414`
+///   warning is given for each one);
+/// - several top-level lists, maps, or tables (rather than exactly one)
+///   are wrapped in an implicit untyped list (and a `#406` warning is
+///   given).
+///
+/// Note that ints and reals may have a leading sign (e.g., `+5`, `-5`)
+/// in both strict and lenient modes since the UXF format allows it.
+///
+/// `record_spans` defaults to `false`. If `true` the byte range of every
+/// value in the text is recorded; see `Uxf::span_of()`.
+///
+/// `normalize_names` defaults to `false`, so names (ttypes, field names,
+/// and field vtypes) are used exactly as written, and names containing
+/// combining marks (e.g., `e` followed by U+0301 COMBINING ACUTE ACCENT)
+/// are invalid. If `true` names may contain combining marks and every name
+/// is converted to Unicode Normalization Form C (NFC) before it is checked
+/// and used. This means that names that look the same but are composed
+/// differently (e.g., `é` as one character or as two) are treated as the
+/// same name. (See also `util::normalize_name()`.)
+///
+/// `max_comment_len` defaults to `None` which means that comments may be
+/// of any length. If set, a comment (including a ttype definition's) that
+/// has more than this many characters is reported as a `#182` error;
+/// this guards against very long comments bloating files from untrusted
+/// sources. The parsed ``Uxf``'s `max_comment_len()` is set to this
+/// limit so that its `set_comment()` enforces it too.
+///
+/// `coerce_bools` defaults to `false`. If `true` the values of a table
+/// field whose vtype is `bool` may also be written as `1` or `true` (read
+/// as `yes`) and `0` or `false` (read as `no`), e.g., for data imported
+/// from CSV. In lenient mode these are accepted (and a `#416` warning is
+/// given for each one); in strict mode they are reported as `#418`
+/// errors since UXF only accepts `yes` and `no`.
+///
+/// `on_str` defaults to `None`. If set it is called with the text of
+/// every str value (but not of map keys or comments); if it returns a
+/// `Value` that is used instead of the str, otherwise the str is kept.
+/// This allows applications to recognize custom scalar encodings (e.g.,
+/// UUIDs) that are stored as strs.
+///
+/// `on_warning` defaults to `None` which means that warnings are ignored.
+/// If set it is called with every warning, i.e., with every mistake that
+/// lenient mode has accepted rather than reported as an error, and with
+/// every datetime timezone that has been dropped. (UXF datetimes have no
+/// timezone, so `2022-10-01T09:30:15+01:00` and `2022-10-01T09:30:15Z`
+/// are both read as `2022-10-01T09:30:15`, as Python's UXF does.)
+#[derive(Default)]
+pub struct ParseOptions {
+    pub lenient: bool,
+    pub record_spans: bool,
+    pub normalize_names: bool,
+    pub max_comment_len: Option<usize>,
+    pub coerce_bools: bool,
+    #[allow(clippy::type_complexity)]
+    pub on_str: Option<Box<dyn Fn(&str) -> Option<Value>>>,
+    #[allow(clippy::type_complexity)]
+    pub on_warning: Option<Box<dyn Fn(&UxfError)>>,
+}
+
+impl fmt::Debug for ParseOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("lenient", &self.lenient)
+            .field("record_spans", &self.record_spans)
+            .field("normalize_names", &self.normalize_names)
+            .field("max_comment_len", &self.max_comment_len)
+            .field("coerce_bools", &self.coerce_bools)
+            .field("on_str", &self.on_str.as_ref().map(|_| "Fn"))
+            .field("on_warning", &self.on_warning.as_ref().map(|_| "Fn"))
+            .finish()
+    }
+}
+
+/// A reusable parser that holds the `ParseOptions` and any predefined
+/// ``TClass``es to use for every document it parses.
+///
+/// This is convenient for applications that parse many documents with
+/// the same configuration, e.g., messages that share a schema:
+///
+/// ```
+/// use uxf::parser::{ParseOptions, Parser};
+/// let point = uxf::tclass::TClass::new("Point",
+///     uxf::field::make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+///     None).unwrap();
+/// let mut parser = Parser::new(ParseOptions { lenient: true,
+///     ..Default::default() });
+/// parser.add_tclass(point).unwrap();
+/// let uxo = parser.parse("uxf 1.0\n(Point 1 02)\n").unwrap();
+/// assert_eq!(uxo.to_string(), "uxf 1.0\n=Point x:int y:int\n(Point 1 2)\n");
+/// ```
+///
+/// (UXF imports aren't supported, so there's no import resolver to
+/// configure.)
+#[derive(Debug, Default)]
+pub struct Parser {
+    options: ParseOptions,
+    tclasses: HashMap<String, TClass>,
+}
+
+impl Parser {
+    /// Creates a new `Parser` that parses using the given `options`.
+    pub fn new(options: ParseOptions) -> Self {
+        Parser { options, tclasses: HashMap::new() }
+    }
+
+    /// Returns the ``Parser``'s options.
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Predefines the given `tclass` for every document this `Parser`
+    /// parses (as `parse_with_schema()` does) _or_ returns an Err if a
+    /// different `TClass` with the same ttype is already predefined.
+    pub fn add_tclass(&mut self, tclass: TClass) -> Result<()> {
+        Ok(add_tclass(&mut self.tclasses, tclass)?)
+    }
+
+    /// Returns the predefined ``TClass``es in sorted order.
+    pub fn tclasses(&self) -> Vec<&TClass> {
+        let mut tclasses: Vec<&TClass> = self.tclasses.values().collect();
+        tclasses.sort();
+        tclasses
+    }
+
+    /// Parses the given UXF text and returns the corresponding `Uxf` _or_
+    /// returns an Err if the text isn't valid UXF. The returned ``Uxf``'s
+    /// tclasses include the predefined ones.
+    pub fn parse(&self, text: &str) -> Result<Uxf> {
+        parse_text(text, &self.options, &self.tclasses)
+    }
+}
+
+/// Parses the given UXF text and returns the corresponding `Uxf` _or_
+/// returns an Err if the text isn't valid UXF.
+///
+/// Parsing never depends on the system locale: reals always use `.` as
+/// the decimal separator and numbers may not contain thousands
+/// separators (e.g., `3,14` and `1,000` are errors). Likewise, `Uxf`
+/// output always writes reals with a `.` and no thousands separators.
+///
+/// ```
+/// let uxo = uxf::parser::parse("uxf 1.0\n[1 2 3]\n").unwrap();
+/// assert_eq!(uxo.to_string(), "uxf 1.0\n[1 2 3]\n");
+/// ```
+pub fn parse(text: &str) -> Result<Uxf> {
+    Parser::default().parse(text)
+}
+
+/// Parses the given UXF text using the given `options` and returns the
+/// corresponding `Uxf` _or_ returns an Err if the text isn't valid UXF.
+pub fn parse_with_options(
+    text: &str,
+    options: &ParseOptions,
+) -> Result<Uxf> {
+    parse_text(text, options, &HashMap::new())
+}
+
+/// Parses the given UXF text as `parse()` does, but with the `expected`
+/// ``TClass``es already defined, and returns the corresponding `Uxf` _or_
+/// returns an Err if the text isn't valid UXF.
+///
+/// This allows the text's value to use ttypes that it doesn't define
+/// itself (e.g., for wire protocols where the schema is shared out of
+/// band). The text may also define any of the `expected` ttypes, but
+/// only identically. The returned ``Uxf``'s tclasses include the
+/// `expected` ones. (See also `Parser`.)
+///
+/// ```
+/// let point = uxf::tclass::TClass::new("Point",
+///     uxf::field::make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+///     None).unwrap();
+/// let uxo = uxf::parser::parse_with_schema("uxf 1.0\n(Point 1 2)\n",
+///     &[point]).unwrap();
+/// assert_eq!(uxo.to_string(), "uxf 1.0\n=Point x:int y:int\n(Point 1 2)\n");
+/// ```
+pub fn parse_with_schema(text: &str, expected: &[TClass]) -> Result<Uxf> {
+    let mut parser = Parser::default();
+    for tclass in expected {
+        parser.add_tclass(tclass.clone())?;
+    }
+    parser.parse(text)
+}
+
+fn parse_text(
+    text: &str,
+    options: &ParseOptions,
+    tclasses: &HashMap<String, TClass>,
+) -> Result<Uxf> {
+    let (version, custom, body) = read_header(text)?;
+    let tokens = Lexer::new(body, options).tokenize()?;
+    let mut reader = Reader::new(tokens, options);
+    reader.offset = text.len() - body.len();
+    reader.tclasses = tclasses.clone();
+    Ok(reader.read(version, custom)?.0)
+}
+
+/// Parses as much of the given (possibly truncated) UXF text as possible
+/// and returns the resulting `Uxf` (if any) and whether the text was
+/// truncated.
+///
+/// This is intended for crash recovery, e.g., reading a file that was
+/// only partly written. If the text ends inside a list, map, or table,
+/// the collection (and any that enclose it) is closed after its last
+/// complete value, and any incomplete map item or table record is
+/// dropped. If a token can't be read (e.g., a str without its closing
+/// `>`) the text is treated as if it ended just before that token.
+///
+/// The text counts as truncated only if it ends inside a list, map, or
+/// table. So if the text is invalid anywhere else (including if it ends
+/// in the header, in the ttype definitions, or after the value), the
+/// result is `(None, false)`.
+///
+/// ```
+/// let (uxo, truncated) =
+///     uxf::parser::parse_partial("uxf 1.0\n[1 2 [3 <fo");
+/// assert!(truncated);
+/// assert_eq!(uxo.unwrap().to_string(), "uxf 1.0\n[1 2 [3]]\n");
+/// ```
+pub fn parse_partial(text: &str) -> (Option<Uxf>, bool) {
+    let Ok((version, custom, body)) = read_header(text) else {
+        return (None, false);
+    };
+    let options = ParseOptions::default();
+    let (tokens, lexer_truncated) =
+        Lexer::new(body, &options).tokenize_partial();
+    let mut reader = Reader::new(tokens, &options);
+    reader.partial = true;
+    match reader.read(version, custom) {
+        // The reader is only truncated if Eof closed a collection
+        Ok((uxo, true)) => (Some(uxo), true),
+        // Any unreadable token must be outside the value
+        Ok((uxo, false)) if !lexer_truncated => (Some(uxo), false),
+        _ => (None, false),
+    }
+}
+
+/// Parses just the header and ttype definitions of the given UXF text and
+/// returns the custom text (which is empty if there isn't any) and the
+/// ``TClass``es in sorted order _or_ returns an Err if the header or any
+/// ttype definition is invalid.
+///
+/// The text is not parsed beyond the start of the value, so this is much
+/// faster than `parse()` for large files when only the schema is needed.
+pub fn parse_header(text: &str) -> Result<(String, Vec<TClass>)> {
+    let (_, custom, body) = read_header(text)?;
+    let options = ParseOptions::default();
+    let tokens = Lexer::new(body, &options).tokenize_header()?;
+    let mut reader = Reader::new(tokens, &options);
+    reader.read_preamble()?;
+    let mut tclasses: Vec<TClass> = reader.tclasses.into_values().collect();
+    tclasses.sort();
+    Ok((custom.unwrap_or_default(), tclasses))
+}
+
+// Adds the tclass unless there's already one with the same ttype, in which
+// case they must be identical (although if the new one has a comment it
+// replaces the old one's).
+fn add_tclass(
+    tclasses: &mut HashMap<String, TClass>,
+    tclass: TClass,
+) -> Result<(), UxfError> {
+    if let Some(existing) = tclasses.get(tclass.ttype()) {
+        if existing != &tclass {
+            return Err(UxfError::new(
+                528,
+                format!(
+                    "conflicting ttype definitions for {}",
+                    tclass.ttype()
+                ),
+            ));
+        }
+        if tclass.comment().is_none() {
+            return Ok(()); // harmless duplicate
+        }
+    }
+    tclasses.insert(tclass.ttype().to_string(), tclass);
+    Ok(())
+}
+
+// Returns the version, the custom text (if any), and the text that follows
+// the header. The custom text is everything after the single space that
+// follows the version, kept verbatim.
+fn read_header(text: &str) -> Result<(f64, Option<String>, &str)> {
+    let (header, body) = match text.find('\n') {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => {
+            bail!(error(110, 1, "missing UXF file header or empty file"))
+        }
+    };
+    let header = header.strip_suffix('\r').unwrap_or(header);
+    let mut parts = header.splitn(3, ' ');
+    if parts.next() != Some("uxf") {
+        bail!(error(130, 1, "not a UXF file"));
+    }
+    let version = match parts.next().map(|v| v.parse::<f64>()) {
+        Some(Ok(version)) if version.is_finite() && version >= 0.0 => {
+            if version > UXF_VERSION {
+                bail!(error(
+                    141,
+                    1,
+                    &format!(
+                        "version {} > current {:.1}",
+                        version, UXF_VERSION
+                    )
+                ));
+            }
+            version
+        }
+        _ => bail!(error(151, 1, "failed to read UXF file version number")),
+    };
+    let custom = parts.next().filter(|s| !s.is_empty());
+    Ok((version, custom.map(|s| s.to_string()), body))
+}
+
+// Returns the given error with the given position if it is a UxfError
+// (keeping its own code), or otherwise as a UxfError with the given code.
+fn positioned(
+    err: anyhow::Error,
+    code: u16,
+    lineno: usize,
+    column: usize,
+) -> anyhow::Error {
+    match err.downcast::<UxfError>() {
+        Ok(err) => err.with_lineno(lineno).with_column(column).into(),
+        Err(err) => error_at(code, lineno, column, &err.to_string()).into(),
+    }
+}
+
+fn error(code: u16, lineno: usize, message: &str) -> UxfError {
+    UxfError::new(code, message.to_string()).with_lineno(lineno)
+}
+
+fn error_at(
+    code: u16,
+    lineno: usize,
+    column: usize,
+    message: &str,
+) -> UxfError {
+    error(code, lineno, message).with_column(column)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    TClassBegin,
+    ListBegin,
+    ListEnd,
+    MapBegin,
+    MapEnd,
+    TableBegin,
+    TableEnd,
+    Comment(String),
+    Identifier(String),
+    Type(String),
+    FieldVtype(String),
+    Null,
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Int(i64),
+    #[cfg(feature = "bigint")]
+    BigInt(num_bigint::BigInt),
+    Real(f64),
+    Str(String),
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: Kind,
+    lineno: usize, // the line and column where the token starts
+    column: usize, // counted in chars (not bytes) from 1
+    start: usize,  // byte offsets in the text after the header
+    end: usize,
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+    lineno: usize,
+    // Columns are counted up to the counted byte offset (which is on the
+    // current line), where the column is counted_column.
+    counted: usize,
+    counted_column: usize,
+    token_start: usize,
+    token_lineno: usize,
+    token_column: usize,
+    tokens: Vec<Token>,
+    header_only: bool,
+    options: &'a ParseOptions,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str, options: &'a ParseOptions) -> Self {
+        // The header is line 1 so the text we're given starts at line 2.
+        Lexer {
+            text,
+            pos: 0,
+            lineno: 2,
+            counted: 0,
+            counted_column: 1,
+            token_start: 0,
+            token_lineno: 2,
+            token_column: 1,
+            tokens: vec![],
+            header_only: false,
+            options,
+        }
+    }
+
+    // Tokenizes up to (but excluding) the start of the value.
+    fn tokenize_header(mut self) -> Result<Vec<Token>> {
+        self.header_only = true;
+        self.tokenize()
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>> {
+        self.read_tokens()?;
+        self.add_token(Kind::Eof);
+        Ok(self.tokens)
+    }
+
+    // Tokenizes up to (but excluding) the first token that can't be read
+    // and returns the tokens and whether any text wasn't tokenized.
+    fn tokenize_partial(mut self) -> (Vec<Token>, bool) {
+        let truncated = self.read_tokens().is_err();
+        self.add_token(Kind::Eof);
+        (self.tokens, truncated)
+    }
+
+    fn read_tokens(&mut self) -> Result<()> {
+        loop {
+            self.token_start = self.pos;
+            self.token_lineno = self.lineno;
+            self.token_column = self.column();
+            let Some(c) = self.getch() else {
+                break;
+            };
+            if self.header_only
+                && (c == '['
+                    || c == '{'
+                    || (c == '(' && self.peek() != Some(':')))
+            {
+                break;
+            }
+            match c {
+                '\n' => self.new_line(),
+                c if c.is_whitespace() => (),
+                '=' => self.add_token(Kind::TClassBegin),
+                '[' => self.add_token(Kind::ListBegin),
+                ']' => self.add_token(Kind::ListEnd),
+                '{' => self.add_token(Kind::MapBegin),
+                '}' => self.add_token(Kind::MapEnd),
+                '(' if self.peek() == Some(':') => {
+                    self.pos += 1; // skip the :
+                    self.read_bytes()?;
+                }
+                '(' => self.add_token(Kind::TableBegin),
+                ')' => self.add_token(Kind::TableEnd),
+                '?' => self.add_token(Kind::Null),
+                '#' => self.read_comment()?,
+                '<' => {
+                    let s = self.read_str("str")?;
+                    self.add_token(Kind::Str(s));
+                }
+                ':' => self.read_field_vtype()?,
+                '!' => bail!(self.error(176, "imports are not supported")),
+                c if c.is_ascii_digit() => self.read_number_or_date(c)?,
+                '-' | '+'
+                    if self.peek().is_some_and(|c| c.is_ascii_digit()) =>
+                {
+                    self.read_number_or_date(c)?
+                }
+                c if c == '_' || c.is_alphabetic() => self.read_name(c),
+                _ => bail!(self.error(
+                    170,
+                    &format!("invalid character encountered: {:?}", c)
+                )),
+            }
+        }
+        Ok(())
+    }
+
+    fn getch(&mut self) -> Option<char> {
+        let c = self.text[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    // Must be called immediately after reading a newline.
+    fn new_line(&mut self) {
+        self.lineno += 1;
+        self.start_line(self.pos);
+    }
+
+    // Notes that the current line starts at the given byte offset.
+    fn start_line(&mut self, line_start: usize) {
+        self.counted = line_start;
+        self.counted_column = 1;
+    }
+
+    // Returns the column of the current position, counting multibyte
+    // UTF-8 characters as one column each. Only the characters since the
+    // previous call (on the same line) are counted, so tokenizing a long
+    // line is linear rather than quadratic.
+    fn column(&mut self) -> usize {
+        self.counted_column +=
+            self.text[self.counted..self.pos].chars().count();
+        self.counted = self.pos;
+        self.counted_column
+    }
+
+    // Returns an error positioned at the start of the current token.
+    fn error(&self, code: u16, message: &str) -> UxfError {
+        error_at(code, self.token_lineno, self.token_column, message)
+    }
+
+    fn add_token(&mut self, kind: Kind) {
+        self.tokens.push(Token {
+            kind,
+            lineno: self.token_lineno,
+            column: self.token_column,
+            start: self.token_start,
+            end: self.pos,
+        });
+    }
+
+    // Returns the text up to (but excluding) the target and moves past
+    // the target.
+    fn match_to(&mut self, target: &str, what: &str) -> Result<&'a str> {
+        let text = self.text;
+        match text[self.pos..].find(target) {
+            Some(i) => {
+                let s = &text[self.pos..self.pos + i];
+                if let Some(j) = s.rfind('\n') {
+                    self.lineno += s.matches('\n').count();
+                    self.start_line(self.pos + j + 1);
+                }
+                self.pos += i + target.len();
+                Ok(s)
+            }
+            None => {
+                bail!(self.error(270, &format!("unterminated {}", what)))
+            }
+        }
+    }
+
+    fn read_comment(&mut self) -> Result<()> {
+        if self.peek() != Some('<') {
+            bail!(self.error(
+                180,
+                &format!(
+                    "a str must follow the # comment introducer, got {:?}",
+                    self.peek().unwrap_or(' ')
+                )
+            ));
+        }
+        self.pos += 1; // skip the <
+        let s = self.read_str("comment")?;
+        if let Some(max_len) = self.options.max_comment_len {
+            util::check_comment(&s, max_len).map_err(|err| {
+                err.with_lineno(self.token_lineno)
+                    .with_column(self.token_column)
+            })?;
+        }
+        self.add_token(Kind::Comment(s));
+        Ok(())
+    }
+
+    fn read_str(&mut self, what: &str) -> Result<String> {
+        let s = self.match_to(">", what)?;
+        util::unescape_str(s).map_err(|err| {
+            err.with_lineno(self.token_lineno)
+                .with_column(self.token_column)
+                .into()
+        })
+    }
+
+    fn read_bytes(&mut self) -> Result<()> {
+        let s = self.match_to(":)", "bytes")?;
+        let bytes = util::uxf_to_bytes(s).map_err(|err| {
+            err.with_lineno(self.token_lineno)
+                .with_column(self.token_column)
+        })?;
+        self.add_token(Kind::Bytes(bytes));
+        Ok(())
+    }
+
+    fn read_field_vtype(&mut self) -> Result<()> {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            if self.getch() == Some('\n') {
+                self.new_line();
+            }
+        }
+        let start = self.pos;
+        self.skip_identifier_chars();
+        if start == self.pos {
+            bail!(self.error(260, "expected field vtype"));
+        }
+        let vtype = self.name(start);
+        self.add_token(Kind::FieldVtype(vtype));
+        Ok(())
+    }
+
+    fn read_number_or_date(&mut self, c: char) -> Result<()> {
+        let start = self.pos - c.len_utf8();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || "-+.:eETZ".contains(c) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let text = &self.text[start..self.pos];
+        if !self.options.lenient && has_leading_zero(text) {
+            bail!(self.error(
+                212,
+                &format!(
+                    "numbers may not have leading zeros, got {}",
+                    text
+                )
+            ));
+        }
+        let kind = if is_date_like(text) {
+            if text.contains('T') {
+                let (text, timezone) = split_timezone(text);
+                if let Some(timezone) = timezone {
+                    self.skip_timezone(text, timezone)?;
+                }
+                read_datetime(text)
+                    .map(Kind::DateTime)
+                    .map_err(|err| err.to_string())
+            } else {
+                NaiveDate::parse_from_str(text, ISO8601_DATE)
+                    .map(Kind::Date)
+                    .map_err(|err| err.to_string())
+            }
+        } else if text.contains(['.', 'e', 'E']) {
+            text.parse::<f64>()
+                .map(Kind::Real)
+                .map_err(|err| err.to_string())
+        } else {
+            read_int(text)
+        };
+        match kind {
+            Ok(kind) => {
+                self.add_token(kind);
+                Ok(())
+            }
+            Err(err) => bail!(self.error(
+                220,
+                &format!(
+                    "invalid number or date/time: {:?}: {}",
+                    text, err
+                )
+            )),
+        }
+    }
+
+    // UXF datetimes are naive so any timezone is dropped (with a warning)
+    // as Python's UXF does; but a malformed timezone is an error.
+    fn skip_timezone(&self, text: &str, timezone: &str) -> Result<()> {
+        if !is_timezone(timezone) {
+            bail!(self.error(
+                220,
+                &format!(
+                    "invalid timezone: {:?} in {:?}",
+                    timezone,
+                    format!("{}{}", text, timezone)
+                )
+            ));
+        }
+        if let Some(on_warning) = &self.options.on_warning {
+            on_warning(&self.error(
+                231,
+                &format!(
+                    "skipped timezone data, used {:?}, got {:?}",
+                    text,
+                    format!("{}{}", text, timezone)
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn read_name(&mut self, c: char) {
+        let start = self.pos - c.len_utf8();
+        self.skip_identifier_chars();
+        let name = self.name(start);
+        let name = name.as_str();
+        let kind = if name == BOOL_TRUE {
+            Kind::Bool(true)
+        } else if name == BOOL_FALSE {
+            Kind::Bool(false)
+        } else if VTYPES.contains(&name) {
+            Kind::Type(name.to_string())
+        } else {
+            Kind::Identifier(name.to_string())
+        };
+        self.add_token(kind);
+    }
+
+    // Returns the name from start to the current position (normalized if
+    // required).
+    fn name(&self, start: usize) -> String {
+        let name = &self.text[start..self.pos];
+        if self.options.normalize_names {
+            util::normalize_name(name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn skip_identifier_chars(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '_'
+                || c.is_alphanumeric()
+                || (self.options.normalize_names && is_combining_mark(c))
+            {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+fn read_int(text: &str) -> std::result::Result<Kind, String> {
+    text.parse::<i64>().map(Kind::Int).map_err(|err| err.to_string())
+}
+
+// Ints that are out of i64 range are kept as big ints.
+#[cfg(feature = "bigint")]
+fn read_int(text: &str) -> std::result::Result<Kind, String> {
+    match text.parse::<i64>() {
+        Ok(i) => Ok(Kind::Int(i)),
+        Err(_) => text
+            .parse::<num_bigint::BigInt>()
+            .map(Kind::BigInt)
+            .map_err(|err| err.to_string()),
+    }
+}
+
+// Returns true for numbers like 007 or -01.5 (but not for 0, 0.5, or
+// dates).
+fn has_leading_zero(text: &str) -> bool {
+    let digits = text.trim_start_matches(['-', '+']).as_bytes();
+    !is_date_like(text)
+        && digits.len() > 1
+        && digits[0] == b'0'
+        && digits[1].is_ascii_digit()
+}
+
+// Dates and datetimes start with a four digit year and a hyphen.
+// Accepts the ISO 8601 forms that Python's datetime.fromisoformat() does
+// (timezones are split off beforehand by split_timezone()):
+// YYYY-MM-DDTHH, YYYY-MM-DDTHH:MM, and YYYY-MM-DDTHH:MM:SS with optional
+// fractional seconds. UXF datetimes have 1-second resolution so any
+// fractional seconds are dropped.
+fn read_datetime(text: &str) -> chrono::ParseResult<NaiveDateTime> {
+    let dt = match text.len() {
+        13 => NaiveDateTime::parse_from_str(
+            &format!("{}:00:00", text),
+            ISO8601_DATETIME,
+        ),
+        16 => NaiveDateTime::parse_from_str(
+            &format!("{}:00", text),
+            ISO8601_DATETIME,
+        ),
+        _ => NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f"),
+    }?;
+    Ok(dt.with_nanosecond(0).unwrap_or(dt))
+}
+
+// Splits a datetime's text into the datetime and its timezone suffix (if
+// any), e.g., "2022-10-01T09:30Z" into "2022-10-01T09:30" and "Z".
+fn split_timezone(text: &str) -> (&str, Option<&str>) {
+    if let Some(t) = text.find('T') {
+        if let Some(i) = text[t..].find(['Z', '+', '-']) {
+            return (&text[..t + i], Some(&text[t + i..]));
+        }
+    }
+    (text, None)
+}
+
+// Accepts the ISO 8601 timezone forms: Z, ±HH, ±HHMM, and ±HH:MM.
+fn is_timezone(text: &str) -> bool {
+    if text == "Z" {
+        return true;
+    }
+    let bytes = text.as_bytes();
+    let digits = |b: &[u8]| b.iter().all(|b| b.is_ascii_digit());
+    matches!(bytes.first(), Some(b'+' | b'-'))
+        && match bytes.len() {
+            3 | 5 => digits(&bytes[1..]),
+            6 => {
+                digits(&bytes[1..3])
+                    && bytes[3] == b':'
+                    && digits(&bytes[4..])
+            }
+            _ => false,
+        }
+}
+
+fn is_date_like(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 10
+        && bytes[..4].iter().all(|b| b.is_ascii_digit())
+        && bytes[4] == b'-'
+}
+
+struct Reader<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    tclasses: HashMap<String, TClass>,
+    options: &'a ParseOptions,
+    partial: bool,   // if true, Eof closes any open collections
+    truncated: bool, // set if partial and Eof closed a collection
+    offset: usize,   // the header's length (to make spans absolute)
+    path: Vec<PathSegment>, // the current value's path if recording spans
+    spans: HashMap<Vec<PathSegment>, Range<usize>>,
+    bool_field: bool, // if true, the next value is for a bool field
+}
+
+impl<'a> Reader<'a> {
+    fn new(tokens: Vec<Token>, options: &'a ParseOptions) -> Self {
+        Reader {
+            tokens,
+            pos: 0,
+            tclasses: HashMap::new(),
+            options,
+            partial: false,
+            truncated: false,
+            offset: 0,
+            path: vec![],
+            spans: HashMap::new(),
+            bool_field: false,
+        }
+    }
+
+    // Returns the Uxf and whether Eof closed any collections.
+    fn read(
+        mut self,
+        version: f64,
+        custom: Option<String>,
+    ) -> Result<(Uxf, bool)> {
+        let comment = self.read_preamble()?;
+        let start = self.pos;
+        let token = self.next();
+        let value = match token.kind {
+            Kind::ListBegin => Value::List(self.read_list()?),
+            Kind::MapBegin => Value::Map(self.read_map()?),
+            Kind::TableBegin => Value::Table(self.read_table()?),
+            _ => bail!(error_at(
+                402,
+                token.lineno,
+                token.column,
+                &format!(
+                    "expected a map, list, or table, got {:?}",
+                    token.kind
+                )
+            )),
+        };
+        let value = if self.options.lenient && self.at_collection() {
+            self.wrap_values(start, value)?
+        } else {
+            value
+        };
+        let token = self.next();
+        if token.kind != Kind::Eof {
+            bail!(error_at(
+                404,
+                token.lineno,
+                token.column,
+                &format!(
+                    "expected end of data after the map, list, or table, \
+                     got {:?}",
+                    token.kind
+                )
+            ));
+        }
+        self.record_span(start); // the Eof is at self.pos so is excluded
+        let mut uxo =
+            Uxf::from_parts(custom, comment, self.tclasses, value);
+        uxo.set_version(version);
+        uxo.set_spans(self.spans);
+        uxo.set_max_comment_len(self.options.max_comment_len);
+        Ok((uxo, self.truncated))
+    }
+
+    fn at_collection(&self) -> bool {
+        matches!(
+            self.peek(),
+            Kind::ListBegin | Kind::MapBegin | Kind::TableBegin
+        )
+    }
+
+    // Reads the top-level values that follow the first one (which started
+    // at the token at start) and returns them all wrapped in an untyped
+    // list, giving a warning. Any spans already recorded are moved into
+    // the list.
+    fn wrap_values(&mut self, start: usize, first: Value) -> Result<Value> {
+        if let Some(on_warning) = &self.options.on_warning {
+            let token = &self.tokens[self.pos];
+            on_warning(&error_at(
+                406,
+                token.lineno,
+                token.column,
+                "expected one map, list, or table, got several: wrapped \
+                 in an implicit list",
+            ));
+        }
+        if self.options.record_spans {
+            self.spans = std::mem::take(&mut self.spans)
+                .into_iter()
+                .map(|(mut path, span)| {
+                    path.insert(0, PathSegment::Index(0));
+                    (path, span)
+                })
+                .collect();
+            self.path.push(PathSegment::Index(0));
+            self.record_span(start);
+            self.path.pop();
+        }
+        let mut lst = List::new(None, None)?;
+        lst.push_unchecked(Some(first));
+        while self.at_collection() {
+            let i = lst.len();
+            lst.push_unchecked(
+                self.read_value_at("]", || PathSegment::Index(i))?,
+            );
+        }
+        Ok(Value::List(lst))
+    }
+
+    // Returns true (and notes the truncation) if reading partially and
+    // at Eof.
+    fn at_partial_eof(&mut self) -> bool {
+        if self.partial && self.peek() == &Kind::Eof {
+            self.truncated = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns true if the collection whose closer is given should be
+    // closed because the data has ended: either because reading
+    // partially, or because reading leniently (in which case a warning is
+    // given).
+    fn at_unclosed_eof(&mut self, closer: char) -> bool {
+        if self.at_partial_eof() {
+            return true;
+        }
+        if self.options.lenient && self.peek() == &Kind::Eof {
+            if let Some(on_warning) = &self.options.on_warning {
+                let token = &self.tokens[self.pos];
+                on_warning(&error_at(
+                    414,
+                    token.lineno,
+                    token.column,
+                    &format!(
+                        "missing {:?} at end of data: closed implicitly",
+                        closer
+                    ),
+                ));
+            }
+            return true;
+        }
+        false
+    }
+
+    // Reads the file comment (which is returned) and any ttype
+    // definitions.
+    fn read_preamble(&mut self) -> Result<Option<String>> {
+        let comment = self.read_comment();
+        while self.peek() == &Kind::TClassBegin {
+            self.pos += 1;
+            self.read_tclass()?;
+        }
+        Ok(comment)
+    }
+
+    fn peek(&self) -> &Kind {
+        &self.tokens[self.pos].kind
+    }
+
+    // The last token is always Eof so we never go past it.
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn read_comment(&mut self) -> Option<String> {
+        if let Kind::Comment(comment) = self.peek() {
+            let comment = comment.clone();
+            self.pos += 1;
+            Some(comment)
+        } else {
+            None
+        }
+    }
+
+    fn read_tclass(&mut self) -> Result<()> {
+        let comment = self.read_comment();
+        let token = self.next();
+        let ttype = match token.kind {
+            Kind::Identifier(ttype) => ttype,
+            _ => bail!(error_at(
+                522,
+                token.lineno,
+                token.column,
+                &format!("expected a ttype, got {:?}", token.kind)
+            )),
+        };
+        let mut fields = vec![];
+        while let Kind::Identifier(name) = self.peek() {
+            let name = name.clone();
+            let name_token = &self.tokens[self.pos];
+            let (lineno, column) = (name_token.lineno, name_token.column);
+            self.pos += 1;
+            let field = if let Kind::FieldVtype(vtype) = self.peek() {
+                let vtype = vtype.clone();
+                self.pos += 1;
+                Field::new(&name, &vtype)
+            } else {
+                Field::new_anyvtype(&name)
+            };
+            fields.push(
+                field
+                    .map_err(|err| positioned(err, 524, lineno, column))?,
+            );
+        }
+        let tclass = TClass::new(&ttype, fields, comment.as_deref())
+            .map_err(|err| {
+                positioned(err, 526, token.lineno, token.column)
+            })?;
+        add_tclass(&mut self.tclasses, tclass).map_err(|err| {
+            err.with_lineno(token.lineno).with_column(token.column).into()
+        })
+    }
+
+    // A collection's comment must come first, before its type(s), so a
+    // comment that follows a collection's type is in the wrong place.
+    fn check_comment_order(
+        &self,
+        what: &str,
+        types: &str,
+        example: &str,
+    ) -> Result<()> {
+        let token = &self.tokens[self.pos];
+        if let Kind::Comment(_) = token.kind {
+            bail!(error_at(
+                444,
+                token.lineno,
+                token.column,
+                &format!(
+                    "a {}'s comment must come before its {}, e.g., {}",
+                    what, types, example
+                )
+            ));
+        }
+        Ok(())
+    }
+
+    // Reads a list, map, or table vtype, either built-in or a ttype.
+    fn read_vtype(
+        &mut self,
+        code: u16,
+        what: &str,
+    ) -> Result<Option<String>> {
+        let token = self.tokens[self.pos].clone();
+        match token.kind {
+            Kind::Type(vtype) => {
+                self.pos += 1;
+                Ok(Some(vtype))
+            }
+            Kind::Identifier(vtype) => {
+                if !self.tclasses.contains_key(&vtype) {
+                    bail!(error_at(
+                        code,
+                        token.lineno,
+                        token.column,
+                        &format!("expected {} vtype, got {}", what, vtype)
+                    ));
+                }
+                self.pos += 1;
+                Ok(Some(vtype))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn read_list(&mut self) -> Result<List> {
+        let comment = self.read_comment();
+        let vtype = self.read_vtype(446, "list")?;
+        if vtype.is_some() {
+            self.check_comment_order("list", "vtype", "[#<c> int ...]")?;
+        }
+        let mut lst = List::new(vtype.as_deref(), comment.as_deref())?;
+        loop {
+            if self.peek() == &Kind::ListEnd {
+                self.pos += 1;
+                break;
+            }
+            if self.at_unclosed_eof(']') {
+                break;
+            }
+            let i = lst.len();
+            lst.push_unchecked(
+                self.read_value_at("]", || PathSegment::Index(i))?,
+            );
+        }
+        Ok(lst)
+    }
+
+    fn read_map(&mut self) -> Result<Map> {
+        let comment = self.read_comment();
+        let token = self.tokens[self.pos].clone();
+        let ktype = match token.kind {
+            Kind::Type(ktype) => {
+                util::check_ktype(&ktype).map_err(|err| {
+                    err.with_lineno(token.lineno).with_column(token.column)
+                })?;
+                self.pos += 1;
+                Some(ktype)
+            }
+            _ => None,
+        };
+        let vtype = if ktype.is_some() {
+            let vtype = self.read_vtype(442, "map")?;
+            self.check_comment_order(
+                "map",
+                "ktype and vtype",
+                "{#<c> str int ...}",
+            )?;
+            vtype
+        } else {
+            None
+        };
+        let mut m = Map::new(
+            ktype.as_deref(),
+            vtype.as_deref(),
+            comment.as_deref(),
+        )?;
+        loop {
+            if self.at_unclosed_eof('}') {
+                break;
+            }
+            let token = self.next();
+            let key = match token.kind {
+                Kind::MapEnd => break,
+                Kind::Bytes(b) => Key::Bytes(b),
+                Kind::Date(d) => Key::Date(d),
+                Kind::DateTime(dt) => Key::DateTime(dt),
+                Kind::Int(i) => Key::Int(i),
+                Kind::Str(s) => Key::Str(s),
+                Kind::Comment(_) => bail!(error_at(
+                    440,
+                    token.lineno,
+                    token.column,
+                    "comments may only be put at the beginning of a map, \
+                     list, or table"
+                )),
+                Kind::Eof => bail!(error_at(
+                    410,
+                    token.lineno,
+                    token.column,
+                    "unexpected end of data: expected '}'"
+                )),
+                _ => bail!(error_at(
+                    294,
+                    token.lineno,
+                    token.column,
+                    &format!(
+                        "map keys may only be of type bytes, date, \
+                         datetime, int, or str, got {:?}",
+                        token.kind
+                    )
+                )),
+            };
+            if self.at_partial_eof() {
+                break; // drop the key since it has no value
+            }
+            if self.peek() == &Kind::MapEnd {
+                bail!(error_at(
+                    296,
+                    token.lineno,
+                    token.column,
+                    &format!("missing value for map key {:?}", key)
+                ));
+            }
+            let value =
+                self.read_value_at("}", || PathSegment::Key(key.clone()))?;
+            m.insert_unchecked(key, value);
+        }
+        Ok(m)
+    }
+
+    fn read_table(&mut self) -> Result<Table> {
+        let comment = self.read_comment();
+        let token = self.next();
+        let tclass = match &token.kind {
+            Kind::Identifier(ttype) => match self.tclasses.get(ttype) {
+                Some(tclass) => tclass.clone(),
+                None => bail!(error_at(
+                    450,
+                    token.lineno,
+                    token.column,
+                    &format!("expected table ttype, got {}", ttype)
+                )),
+            },
+            _ => bail!(error_at(
+                450,
+                token.lineno,
+                token.column,
+                &format!("expected table ttype, got {:?}", token.kind)
+            )),
+        };
+        self.check_comment_order("table", "ttype", "(#<c> Point ...)")?;
+        let mut values = vec![];
+        loop {
+            if self.peek() == &Kind::TableEnd {
+                self.pos += 1;
+                break;
+            }
+            if self.at_unclosed_eof(')') {
+                if self.partial && !tclass.is_fieldless() {
+                    // drop any incomplete record
+                    values.truncate(
+                        values.len() - values.len() % tclass.len(),
+                    );
+                }
+                break;
+            }
+            let (row, column) = if tclass.is_fieldless() {
+                (0, 0) // an error anyway; see below
+            } else {
+                (values.len() / tclass.len(), values.len() % tclass.len())
+            };
+            self.bool_field = self.options.coerce_bools
+                && !tclass.is_fieldless()
+                && tclass.fields()[column].vtype() == Some(VTYPE_NAME_BOOL);
+            let value = self.read_value_at(")", || {
+                // A fieldless table has no field names (but is an error)
+                let name = tclass
+                    .fields()
+                    .get(column)
+                    .map(|field| field.name().to_string())
+                    .unwrap_or_default();
+                PathSegment::Cell(row, name)
+            })?;
+            values.push(value);
+        }
+        if tclass.is_fieldless() && !values.is_empty() {
+            bail!(error_at(
+                452,
+                token.lineno,
+                token.column,
+                &format!(
+                    "a fieldless table may not have values, got {} for {}",
+                    values.len(),
+                    tclass.ttype()
+                )
+            ));
+        }
+        let columns = tclass.len();
+        if columns > 0 && values.len() % columns != 0 {
+            bail!(error_at(
+                454,
+                token.lineno,
+                token.column,
+                &format!(
+                    "expected a multiple of {} values for a {} table, \
+                     got {}",
+                    columns,
+                    tclass.ttype(),
+                    values.len()
+                )
+            ));
+        }
+        let mut table = Table::new(tclass);
+        // The lexer has already checked the comment's length
+        table.set_comment(comment.as_deref(), None)?;
+        let mut values = values.into_iter();
+        while values.len() > 0 {
+            table.push_record(values.by_ref().take(columns).collect())?;
+        }
+        Ok(table)
+    }
+
+    // Reads a 1, 0, true, or false as a bool in lenient mode (with a
+    // warning) or reports it as an error in strict mode; returns None
+    // (and reads nothing) for any other value.
+    fn read_bool_alias(&mut self) -> Result<Option<bool>> {
+        let token = &self.tokens[self.pos];
+        let (b, text) = match &token.kind {
+            Kind::Int(1) => (true, "1"),
+            Kind::Int(0) => (false, "0"),
+            Kind::Identifier(name) if name == "true" => (true, "true"),
+            Kind::Identifier(name) if name == "false" => (false, "false"),
+            _ => return Ok(None),
+        };
+        let bool_text = if b { BOOL_TRUE } else { BOOL_FALSE };
+        if !self.options.lenient {
+            bail!(error_at(
+                418,
+                token.lineno,
+                token.column,
+                &format!(
+                    "expected {} or {} for a bool field, got {}",
+                    BOOL_TRUE, BOOL_FALSE, text
+                )
+            ));
+        }
+        if let Some(on_warning) = &self.options.on_warning {
+            on_warning(&error_at(
+                416,
+                token.lineno,
+                token.column,
+                &format!("read {} as {} for a bool field", text, bool_text),
+            ));
+        }
+        self.pos += 1;
+        Ok(Some(b))
+    }
+
+    // Reads a value as read_value() does, and if recording spans, records
+    // the value's span using the path segment returned by segment().
+    fn read_value_at<F>(
+        &mut self,
+        closer: &str,
+        segment: F,
+    ) -> Result<Option<Value>>
+    where
+        F: FnOnce() -> PathSegment,
+    {
+        if !self.options.record_spans {
+            return self.read_value(closer);
+        }
+        self.path.push(segment());
+        let start = self.pos;
+        let value = self.read_value(closer)?;
+        self.record_span(start);
+        self.path.pop();
+        Ok(value)
+    }
+
+    // Records the span from the token at start to the last token read as
+    // the span of the value at the current path (if recording spans).
+    fn record_span(&mut self, start: usize) {
+        if self.options.record_spans && self.pos > start {
+            let span = self.offset + self.tokens[start].start
+                ..self.offset + self.tokens[self.pos - 1].end;
+            self.spans.insert(self.path.clone(), span);
+        }
+    }
+
+    fn read_value(&mut self, closer: &str) -> Result<Option<Value>> {
+        if std::mem::take(&mut self.bool_field) {
+            if let Some(b) = self.read_bool_alias()? {
+                return Ok(Some(Value::Bool(b)));
+            }
+        }
+        let token = self.next();
+        Ok(Some(match token.kind {
+            Kind::Null => return Ok(None),
+            Kind::Bool(b) => Value::Bool(b),
+            Kind::Bytes(b) => Value::Bytes(b),
+            Kind::Date(d) => Value::Date(d),
+            Kind::DateTime(dt) => Value::DateTime(dt),
+            Kind::Int(i) => Value::Int(i),
+            #[cfg(feature = "bigint")]
+            Kind::BigInt(i) => Value::BigInt(i),
+            Kind::Real(r) => Value::Real(r),
+            Kind::Str(s) => match &self.options.on_str {
+                Some(on_str) => on_str(&s).unwrap_or(Value::Str(s)),
+                None => Value::Str(s),
+            },
+            Kind::ListBegin => Value::List(self.read_list()?),
+            Kind::MapBegin => Value::Map(self.read_map()?),
+            Kind::TableBegin => Value::Table(self.read_table()?),
+            Kind::Comment(_) => bail!(error_at(
+                440,
+                token.lineno,
+                token.column,
+                "comments may only be put at the beginning of a map, \
+                 list, or table"
+            )),
+            Kind::Eof => bail!(error_at(
+                410,
+                token.lineno,
+                token.column,
+                &format!("unexpected end of data: expected {:?}", closer)
+            )),
+            kind => bail!(error_at(
+                412,
+                token.lineno,
+                token.column,
+                &format!(
+                    "expected a value or {:?}, got {:?}",
+                    closer, kind
+                )
+            )),
+        }))
+    }
+}
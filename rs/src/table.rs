@@ -1,18 +1,701 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
+use crate::constants::*;
+use crate::error::UxfError;
+use crate::field::Field;
+use crate::output::{self, Format};
 use crate::tclass::TClass;
-use crate::value::Value;
+use crate::util;
+use crate::value::{self, Value};
+use anyhow::{bail, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
 
-#[derive(Clone, Debug)]
+/// Holds summary statistics for a numeric table column; see
+/// `Table::column_stats()`. `count` is the number of non-null values;
+/// `min`, `max`, and `mean` are `None` if `count` is `0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnStats {
+    pub count: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub sum: f64,
+    pub mean: Option<f64>,
+}
+
+/// Provides a table of records whose fields are defined by a `TClass`.
+///
+/// Comparing two large tables is O(n), so each `Table` lazily caches a
+/// hash of its content (its ttype and records) the first time it is
+/// compared. Tables whose hashes differ are unequal, so comparisons
+/// short-circuit; otherwise they are compared field by field. Every
+/// method that can change the ttype or the records (`push_record()`,
+/// etc.) discards the cached hash, so it is recomputed when next needed.
+#[derive(Clone)]
 pub struct Table {
-    tclass: TClass,
+    tclass: Arc<TClass>, // may be shared with other tables; see intern()
     comment: Option<String>,
     records: Vec<Vec<Option<Value>>>,
+    hash: OnceLock<u64>,
 }
 
 impl Table {
+    /// Creates a new empty `Table` of the given `tclass`.
+    ///
+    /// If the `tclass` is fieldless the table represents an enumeration
+    /// constant (e.g., `(StateReady)`) and can never hold any records, so
+    /// `push_record()`, `append()`, etc., return an Err. Conversely only
+    /// fieldless tables have `enum_values()`.
     pub fn new(tclass: TClass) -> Self {
-        Table { tclass, comment: None, records: vec![] }
+        Table {
+            tclass: Arc::new(tclass),
+            comment: None,
+            records: vec![],
+            hash: OnceLock::new(),
+        }
+    }
+
+    /// Returns the ``Table``'s `TClass` (its ttype, fields, and the
+    /// tclass's comment).
+    pub fn tclass(&self) -> &TClass {
+        &self.tclass
+    }
+
+    pub(crate) fn set_tclass(&mut self, tclass: TClass) {
+        self.hash = OnceLock::new();
+        self.tclass = Arc::new(tclass);
+    }
+
+    // Shares the registry's copy of this ``Table``'s tclass if it has an
+    // identical one (including its comment); otherwise adds this
+    // ``Table``'s tclass to the registry. Does the same for any nested
+    // tables and returns how many tables now share a tclass they didn't
+    // share before. (The records are unchanged so the cached hash is
+    // kept.)
+    pub(crate) fn intern(
+        &mut self,
+        registry: &mut HashMap<String, Vec<Arc<TClass>>>,
+    ) -> usize {
+        let mut count = 0;
+        let tclasses =
+            registry.entry(self.tclass.ttype().to_string()).or_default();
+        match tclasses.iter().find(|tclass| {
+            **tclass == self.tclass
+                && tclass.comment() == self.tclass.comment()
+        }) {
+            Some(tclass) => {
+                if !Arc::ptr_eq(tclass, &self.tclass) {
+                    self.tclass = Arc::clone(tclass);
+                    count += 1;
+                }
+            }
+            None => tclasses.push(Arc::clone(&self.tclass)),
+        }
+        for value in self.records.iter_mut().flatten().flatten() {
+            count += value.intern(registry);
+        }
+        count
+    }
+
+    /// Returns the ``Table``'s `comment` if it has one. (This is the
+    /// comment written in the table itself, e.g., `(#<c> Point ...)`; see
+    /// `TClass::comment()` for the ttype definition's comment.)
+    pub fn comment(&self) -> Option<&str> {
+        match &self.comment {
+            None => None,
+            Some(comment) => Some(comment),
+        }
+    }
+
+    /// Sets the ``Table``'s `comment` (or removes it if `comment` is
+    /// `None`) _or_ returns an Err if `max_len` is given and the comment
+    /// is longer than `max_len` characters. (See also
+    /// `Uxf::max_comment_len()`.)
+    pub fn set_comment(
+        &mut self,
+        comment: Option<&str>,
+        max_len: Option<usize>,
+    ) -> Result<()> {
+        if let (Some(comment), Some(max_len)) = (comment, max_len) {
+            util::check_comment(comment, max_len)?;
+        }
+        self.comment = comment.map(|s| s.to_string());
+        Ok(())
+    }
+
+    /// Appends the given `record` to this ``Table``'s records _or_ returns
+    /// an Err if the table is fieldless or if the record has the wrong
+    /// number of fields. (See also `TClass::record_of_nulls()`.)
+    pub fn push_record(
+        &mut self,
+        record: Vec<Option<Value>>,
+    ) -> Result<()> {
+        if self.tclass.is_fieldless() {
+            bail!(UxfError::new(
+                368,
+                format!(
+                    "can't add a record to fieldless table {}",
+                    self.tclass.ttype()
+                )
+            ));
+        }
+        if record.len() != self.tclass.len() {
+            bail!(UxfError::new(
+                370,
+                format!(
+                    "expected a record of {} fields for a {} table, got {}",
+                    self.tclass.len(),
+                    self.tclass.ttype(),
+                    record.len()
+                )
+            ));
+        }
+        self.hash = OnceLock::new();
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// Appends a record of nulls (see `TClass::record_of_nulls()`) _or_
+    /// returns an Err if the table is fieldless.
+    pub fn push_empty_record(&mut self) -> Result<()> {
+        let record = self.tclass.record_of_nulls()?;
+        self.push_record(record)
+    }
+
+    /// Returns the number of records.
+    pub fn records_len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns the record at the given `index` or `None` if the `index` is
+    /// out of range.
+    pub fn record(&self, index: usize) -> Option<&[Option<Value>]> {
+        self.records.get(index).map(|record| record.as_slice())
+    }
+
+    /// Returns `Some(true)` if the cell at the given `row` and `col` is a
+    /// null (`?`), `Some(false)` if it holds a value (even an empty one
+    /// such as `<>` or `[]`), or `None` if `row` or `col` is out of range.
+    pub fn is_cell_null(&self, row: usize, col: usize) -> Option<bool> {
+        self.records
+            .get(row)
+            .and_then(|record| record.get(col))
+            .map(|value| value.is_none())
+    }
+
+    /// Returns all the ``Table``'s records (each with one value per field,
+    /// where `None` is a null).
+    pub fn records(&self) -> &[Vec<Option<Value>>] {
+        &self.records
+    }
+
+    pub(crate) fn records_mut(&mut self) -> &mut Vec<Vec<Option<Value>>> {
+        self.hash = OnceLock::new();
+        &mut self.records
+    }
+
+    /// Retains only the records for which `f` returns true.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Vec<Option<Value>>) -> bool,
+    {
+        self.hash = OnceLock::new();
+        self.records.retain(f);
+    }
+
+    /// Returns how many records `predicate` returns true for (without
+    /// copying any records).
+    pub fn count_records(
+        &self,
+        predicate: impl Fn(&[Option<Value>]) -> bool,
+    ) -> usize {
+        self.records.iter().filter(|record| predicate(record)).count()
+    }
+
+    /// Returns how many records this `Table` can hold without
+    /// reallocating.
+    pub fn capacity(&self) -> usize {
+        self.records.capacity()
+    }
+
+    /// Shrinks this ``Table``'s capacity to fit its records, e.g., to
+    /// reclaim memory after `retain()` has removed many records.
+    pub fn shrink_to_fit(&mut self) {
+        self.records.shrink_to_fit();
+    }
+
+    /// Returns a hash of this ``Table``'s ttype and records. The hash is
+    /// cached until the table is next changed.
+    pub fn content_hash(&self) -> u64 {
+        *self.hash.get_or_init(|| {
+            let mut state = DefaultHasher::new();
+            self.tclass.ttype().hash(&mut state);
+            self.records.len().hash(&mut state);
+            for record in &self.records {
+                for value in record {
+                    value::hash_opt_value(value, &mut state);
+                }
+            }
+            state.finish()
+        })
+    }
+
+    /// Returns a new `Table` that is the inner join of this table with
+    /// `other`, matching records where this table's `left_field` value
+    /// equals the other table's `right_field` value _or_ returns an Err if
+    /// either field doesn't exist. Null values never match.
+    ///
+    /// The new ``Table``'s ttype is the concatenation of the two ttypes and
+    /// its fields are this table's fields followed by the other table's
+    /// fields. Any of the other table's fields whose name is already in use
+    /// is renamed by prefixing it with the other table's ttype and an
+    /// underscore (plus a number if that is also in use). Since names may
+    /// be at most `MAX_IDENTIFIER_LEN` characters long, a derived ttype or
+    /// field name that would be longer is truncated (before any number).
+    pub fn join(
+        &self,
+        other: &Table,
+        left_field: &str,
+        right_field: &str,
+    ) -> Result<Table> {
+        let left = self.column(left_field)?;
+        let right = other.column(right_field)?;
+        let mut fields: Vec<Field> = self.tclass.fields().to_vec();
+        for field in other.tclass.fields() {
+            let mut name = field.name().to_string();
+            let mut n = 1;
+            while fields.iter().any(|f| f.name() == name) {
+                let prefixed =
+                    format!("{}_{}", other.tclass.ttype(), field.name());
+                name = if n == 1 {
+                    fit_name(&prefixed, "")
+                } else {
+                    fit_name(&prefixed, &n.to_string())
+                };
+                n += 1;
+            }
+            fields.push(match field.vtype() {
+                Some(vtype) => Field::new(&name, vtype)?,
+                None => Field::new_anyvtype(&name)?,
+            });
+        }
+        let ttype = fit_name(
+            &format!("{}{}", self.tclass.ttype(), other.tclass.ttype()),
+            "",
+        );
+        let mut table = Table::new(TClass::new(&ttype, fields, None)?);
+        for lrecord in &self.records {
+            if lrecord[left].is_none() {
+                continue;
+            }
+            for rrecord in &other.records {
+                if lrecord[left] == rrecord[right] {
+                    let mut record = lrecord.clone();
+                    record.extend(rrecord.iter().cloned());
+                    table.push_record(record)?;
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// Inserts the given `field` as a new column at the given `index` (so
+    /// `0` inserts it first and `tclass.len()` appends it), and sets the
+    /// new column in every existing record to `default` _or_ returns an
+    /// Err if the `index` is out of range, the table already has a field
+    /// of that name, or `default` doesn't match the field's vtype.
+    ///
+    /// The table's tclass is replaced by one with the new field (with the
+    /// same ttype and comment).
+    pub fn insert_column(
+        &mut self,
+        index: usize,
+        field: Field,
+        default: Option<Value>,
+    ) -> Result<()> {
+        if index > self.tclass.len() {
+            bail!(UxfError::new(
+                378,
+                format!(
+                    "can't insert column {} at index {} in a {} table \
+                      with {} fields",
+                    field.name(),
+                    index,
+                    self.tclass.ttype(),
+                    self.tclass.len()
+                )
+            ));
+        }
+        if self.tclass.fields().iter().any(|f| f.name() == field.name()) {
+            bail!(UxfError::new(
+                388,
+                format!(
+                    "table {} already has a field called {}",
+                    self.tclass.ttype(),
+                    field.name()
+                )
+            ));
+        }
+        if let Some(value) = &default {
+            let path = format!("{}.{}", self.tclass.ttype(), field.name());
+            validate_value(value, field.vtype(), &path)?;
+        }
+        let mut fields = self.tclass.fields().to_vec();
+        fields.insert(index, field);
+        let tclass = TClass::new(
+            self.tclass.ttype(),
+            fields,
+            self.tclass.comment(),
+        )?;
+        self.set_tclass(tclass);
+        for record in &mut self.records {
+            record.insert(index, default.clone());
+        }
+        Ok(())
+    }
+
+    /// Removes the column for the field called `name` from the tclass and
+    /// from every record _or_ returns an Err if there's no such field or if
+    /// it is the only field (since removing it would make the table
+    /// fieldless, and fieldless tables can't have records).
+    ///
+    /// The table's tclass is replaced by one without the field (with the
+    /// same ttype and comment).
+    pub fn remove_column(&mut self, name: &str) -> Result<()> {
+        let index = self.column(name)?;
+        if self.tclass.len() == 1 {
+            bail!(UxfError::new(
+                390,
+                format!(
+                    "can't remove {} since it is table {}'s only field",
+                    name,
+                    self.tclass.ttype()
+                )
+            ));
+        }
+        let mut fields = self.tclass.fields().to_vec();
+        fields.remove(index);
+        let tclass = TClass::new(
+            self.tclass.ttype(),
+            fields,
+            self.tclass.comment(),
+        )?;
+        self.set_tclass(tclass);
+        for record in &mut self.records {
+            record.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Returns this fieldless table's enumeration values in order _or_
+    /// returns an Err if this table has fields.
+    ///
+    /// In UXF an enumeration is modelled by a fieldless tclass per member
+    /// (e.g., `=StateReady`, `=StateWaiting`), and a fieldless table
+    /// (e.g., `(StateReady)`) is one of its values. Since a fieldless
+    /// table may not hold any values of its own, its only enumeration
+    /// value is the constant that it names, i.e., its ttype as a
+    /// `Value::Str`. This is suitable for mapping to the variants of a
+    /// Rust enum.
+    pub fn enum_values(&self) -> Result<Vec<Value>> {
+        if !self.tclass.is_fieldless() {
+            bail!(UxfError::new(
+                362,
+                format!(
+                    "only fieldless tables have enum values, {} has {} \
+                      fields",
+                    self.tclass.ttype(),
+                    self.tclass.len()
+                )
+            ));
+        }
+        Ok(vec![Value::Str(self.tclass.ttype().to_string())])
+    }
+
+    /// Sorts this ``Table``'s records by the named field and then removes
+    /// every record whose value for that field duplicates an earlier
+    /// record's, keeping the first _or_ returns an Err if there is no such
+    /// field.
+    ///
+    /// The sort is stable, so of the records that share a value, the one
+    /// that came first is kept. Values are ordered as by
+    /// `Value::canonicalize()` (nulls first); all nulls count as
+    /// duplicates of each other.
+    pub fn dedup_by_key(&mut self, name: &str) -> Result<()> {
+        let column = self.column(name)?;
+        let records = self.records_mut();
+        records
+            .sort_by(|a, b| value::canonical_cmp(&a[column], &b[column]));
+        records.dedup_by(|a, b| a[column] == b[column]);
+        Ok(())
+    }
+
+    /// Returns the statistics for the named column's non-null values _or_
+    /// returns an Err if there is no such field, or if the field's vtype
+    /// isn't `int` or `real`, or if the field has no vtype and holds a
+    /// non-numeric value. Ints (including big ints with the `bigint`
+    /// feature) are converted to `f64` as by `Value::as_number()`.
+    pub fn column_stats(&self, name: &str) -> Result<ColumnStats> {
+        let column = self.column(name)?;
+        if let Some(vtype) = self.tclass.fields()[column].vtype() {
+            if vtype != VTYPE_NAME_INT && vtype != VTYPE_NAME_REAL {
+                bail!(UxfError::new(
+                    364,
+                    format!(
+                        "can only compute stats for int or real fields, \
+                          {} is {}",
+                        name, vtype
+                    )
+                ));
+            }
+        }
+        let mut stats = ColumnStats {
+            count: 0,
+            min: None,
+            max: None,
+            sum: 0.0,
+            mean: None,
+        };
+        for (row, record) in self.records.iter().enumerate() {
+            let x = match &record[column] {
+                None => continue,
+                Some(value) => value.as_number(),
+            };
+            let Some(x) = x else {
+                bail!(UxfError::new(
+                    366,
+                    format!(
+                        "can only compute stats for numeric values, \
+                          record {} field {} is not numeric",
+                        row, name
+                    )
+                ));
+            };
+            stats.count += 1;
+            stats.sum += x;
+            stats.min = Some(stats.min.map_or(x, |min| min.min(x)));
+            stats.max = Some(stats.max.map_or(x, |max| max.max(x)));
+        }
+        if stats.count > 0 {
+            stats.mean = Some(stats.sum / stats.count as f64);
+        }
+        Ok(stats)
+    }
+
+    /// Returns this table as a JSON array with one object per record, each
+    /// keyed by field name. Nulls, bools, ints, reals, and strs map to
+    /// their JSON equivalents; bytes become base64 strings; dates and
+    /// datetimes become ISO 8601 strings; lists become arrays; maps
+    /// become objects (with keys converted to strings); and nested tables
+    /// become nested arrays of objects.
+    ///
+    /// Returns an Err (`#730`) if two keys of a map convert to the same
+    /// string, e.g., the int `1` and the str `<1>`, since one would
+    /// silently overwrite the other.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(crate::json::table_to_json(self)?)
+    }
+
+    /// Appends the given `record` to this ``Table``'s records _or_ returns
+    /// an Err if `push_record()` would fail or if any of the record's
+    /// values (including those nested inside it) doesn't match its
+    /// field's vtype; see `validate()`.
+    ///
+    /// Each value must match its field's vtype, e.g., a field with vtype
+    /// `int` only accepts ints, and a field with a custom vtype (e.g.,
+    /// `Point`) only accepts tables of that ttype. Nulls and the values of
+    /// fields with no vtype are always accepted. A mismatch is reported
+    /// with the field's name, e.g., `#358:field 'CID' expected int, got
+    /// str`.
+    pub fn append(&mut self, record: Vec<Option<Value>>) -> Result<()> {
+        let row = self.records.len();
+        for (field, value) in self.tclass.fields().iter().zip(&record) {
+            if let Some(value) = value {
+                if let Some(vtype) = field.vtype() {
+                    if !value.has_vtype(vtype) {
+                        bail!(UxfError::new(
+                            358,
+                            format!(
+                                "field '{}' expected {}, got {}",
+                                field.name(),
+                                vtype,
+                                value.vtype_name()
+                            )
+                        ));
+                    }
+                }
+                let path = format!(
+                    "{}[{}].{}",
+                    self.tclass.ttype(),
+                    row,
+                    field.name()
+                );
+                validate_nested(value, &path)?;
+            }
+        }
+        self.push_record(record)
+    }
+
+    /// Returns `Ok(())` if every value in this table matches its field's
+    /// vtype _or_ returns an Err that pinpoints the first mismatch.
+    ///
+    /// Validation recurses into nested tables, lists, and maps, so (for
+    /// example) if a `Line` table's `end` field holds a `Point` table whose
+    /// first record's `y` field should be an int but is a str, the error
+    /// is `#372:Line[0].end > Point[0].y: expected int, got str`.
+    /// A vtype of `table` accepts any table; a custom vtype (e.g., `Point`)
+    /// only accepts tables of that ttype. Nulls are always valid.
+    pub fn validate(&self) -> Result<()> {
+        self.validate_at("")
+    }
+
+    fn validate_at(&self, prefix: &str) -> Result<()> {
+        for (row, record) in self.records.iter().enumerate() {
+            for (field, value) in self.tclass.fields().iter().zip(record) {
+                if let Some(value) = value {
+                    let path = format!(
+                        "{}{}[{}].{}",
+                        prefix,
+                        self.tclass.ttype(),
+                        row,
+                        field.name()
+                    );
+                    validate_value(value, field.vtype(), &path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the index of the field with the given name.
+    fn column(&self, name: &str) -> Result<usize> {
+        match self.tclass.fields().iter().position(|f| f.name() == name) {
+            Some(index) => Ok(index),
+            None => bail!(UxfError::new(
+                360,
+                format!(
+                    "table {} has no field called {}",
+                    self.tclass.ttype(),
+                    name
+                )
+            )),
+        }
+    }
+}
+
+// Returns the name followed by the suffix, with the name truncated if
+// necessary so that the result is at most MAX_IDENTIFIER_LEN characters.
+fn fit_name(name: &str, suffix: &str) -> String {
+    let len = MAX_IDENTIFIER_LEN - suffix.chars().count();
+    let mut name: String = name.chars().take(len).collect();
+    name.push_str(suffix);
+    name
+}
+
+// Checks that the value matches the vtype (if any) and recursively checks
+// any values nested inside it. The path says where the value is.
+fn validate_value(
+    value: &Value,
+    vtype: Option<&str>,
+    path: &str,
+) -> Result<()> {
+    if let Some(vtype) = vtype {
+        if !value.has_vtype(vtype) {
+            bail!(UxfError::new(
+                372,
+                format!(
+                    "{}: expected {}, got {}",
+                    path,
+                    vtype,
+                    value.vtype_name()
+                )
+            ));
+        }
+    }
+    validate_nested(value, path)
+}
+
+// Recursively checks the values nested inside the value (but not the value
+// itself). The path says where the value is.
+fn validate_nested(value: &Value, path: &str) -> Result<()> {
+    match value {
+        Value::List(lst) => {
+            for (i, value) in lst.values().iter().enumerate() {
+                if let Some(value) = value {
+                    let path = format!("{}[{}]", path, i);
+                    validate_value(value, lst.vtype(), &path)?;
+                }
+            }
+        }
+        Value::Map(m) => {
+            for (key, value) in m.items() {
+                if let Some(ktype) = m.ktype() {
+                    if ktype != key.type_name() {
+                        bail!(UxfError::new(
+                            374,
+                            format!(
+                                "{}: expected {} keys, got {}",
+                                path,
+                                ktype,
+                                key.type_name()
+                            )
+                        ));
+                    }
+                }
+                if let Some(value) = value {
+                    let path = format!(
+                        "{}{{{}}}",
+                        path,
+                        Value::from(key.clone()).summary(20)
+                    );
+                    validate_value(value, m.vtype(), &path)?;
+                }
+            }
+        }
+        Value::Table(t) => t.validate_at(&format!("{} > ", path))?,
+        _ => (),
+    }
+    Ok(())
+}
+
+impl PartialEq for Table {
+    fn eq(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        if self.content_hash() != other.content_hash() {
+            return false;
+        }
+        (Arc::ptr_eq(&self.tclass, &other.tclass)
+            || self.tclass == other.tclass)
+            && self.comment == other.comment
+            && self.records == other.records
+    }
+}
+
+impl Eq for Table {}
+
+impl fmt::Debug for Table {
+    // The cached hash is an implementation detail so isn't shown
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Table")
+            .field("tclass", &self.tclass)
+            .field("comment", &self.comment)
+            .field("records", &self.records)
+            .finish()
+    }
+}
+
+impl fmt::Display for Table {
+    /// Writes this `Table` as UXF text, e.g., `(Point 1 2 3 4)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = String::new();
+        output::write_table(&mut s, self, &Format::default());
+        write!(f, "{}", s)
     }
 }
@@ -1,6 +1,7 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
+use crate::error::UxfError;
 use crate::field::Field;
 use crate::util;
 use crate::value::Value;
@@ -12,6 +13,12 @@ use std::{cmp::Ordering, fmt};
 /// for use in ``Table``s.
 ///
 /// ``TClass``es are immutable.
+///
+/// A ``TClass``'s identity is its ttype and fields: its comment isn't
+/// significant. So two ``TClass``es that differ only in their comments
+/// are equal (`==`) and compare as `Ordering::Equal`. This allows a ttype
+/// to be defined (or imported or merged) more than once with different
+/// comments.
 #[derive(Clone, Debug, Eq)]
 pub struct TClass {
     ttype: String,
@@ -21,15 +28,31 @@ pub struct TClass {
 
 impl TClass {
     /// Creates a new `TClass` with the given `name`, `fields`, and
-    /// `commment` _or_ returns an Err if the `name` is invalid.
+    /// `commment` _or_ returns an Err if the `name` is invalid or if two
+    /// fields have the same name.
     /// See `Field::make_fields()` for a function that can generate a
     /// suitable vector of fields.
+    ///
+    /// Field names are compared case-sensitively (as are all UXF names),
+    /// so `x` and `X` are different fields.
     pub fn new(
         ttype: &str,
         fields: Vec<Field>,
         comment: Option<&str>,
     ) -> Result<Self> {
         util::check_name(ttype)?;
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].iter().any(|f| f.name() == field.name()) {
+                bail!(UxfError::new(
+                    336,
+                    format!(
+                        "duplicate field name {} in tclass {}",
+                        field.name(),
+                        ttype
+                    )
+                ));
+            }
+        }
         Ok(TClass {
             ttype: ttype.to_string(),
             comment: comment.map(|s| s.to_string()),
@@ -71,26 +94,126 @@ impl TClass {
 
     /// Returns how many fields this ``TClass`` has; this will be `0` for a
     /// fieldless `TClass`.
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.fields.len()
     }
 
+    /// Returns this ``TClass``'s fields in order (which is empty for a
+    /// fieldless `TClass`).
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Returns the field at the given `index` or `None` if `index` is out
+    /// of range.
+    pub fn field(&self, index: usize) -> Option<&Field> {
+        self.fields.get(index)
+    }
+
+    /// Returns the field called `name` or `None` if there isn't one.
+    /// (Field names are case-sensitive.)
+    pub fn field_by_name(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name() == name)
+    }
+
     /// Returns a record with `TClass.len()` (i.e., `fields.len()`) fields,
     /// each holding an `Option<Value>` whose value is `None`.
     /// This is a helper for adding new rows to ``Table``s.
     pub fn record_of_nulls(&self) -> Result<Vec<Option<Value>>> {
         if self.is_fieldless() {
-            bail!(
-                "#352:can't create a record of nulls for a fieldless \
+            bail!(UxfError::new(
+                352,
+                "can't create a record of nulls for a fieldless \
                   table's tclass"
-            );
+                    .to_string()
+            ));
         }
-        let mut record = Vec::with_capacity(self.len());
-        record.fill(None);
-        Ok(record)
+        Ok(vec![None; self.len()])
     }
+
+    /// Returns the differences between this ``TClass``'s fields and the
+    /// `other` ``TClass``'s fields, e.g., to migrate data from an old
+    /// version of a schema (`self`) to a new one (`other`). The ttypes and
+    /// comments aren't compared.
+    ///
+    /// Fields are matched by name. The differences are given in this
+    /// ``TClass``'s field order (removed, retyped, or moved fields)
+    /// followed by the `other` ``TClass``'s added fields in its order. A
+    /// field is only `Moved` if its position relative to the fields that
+    /// both ``TClass``es have changed, so adding or removing a field
+    /// doesn't make the fields after it count as moved.
+    ///
+    /// ```
+    /// use uxf::field::make_fields;
+    /// use uxf::tclass::{FieldDiff, TClass};
+    /// let old = TClass::new("Point",
+    ///     make_fields(&[("x", "int"), ("y", "int")]).unwrap(), None)
+    ///     .unwrap();
+    /// let new = TClass::new("Point",
+    ///     make_fields(&[("x", "real"), ("y", "int"), ("z", "real")])
+    ///     .unwrap(), None).unwrap();
+    /// assert_eq!(old.diff(&new).len(), 2); // x retyped and z added
+    /// assert!(old.diff(&old).is_empty());
+    /// ```
+    pub fn diff(&self, other: &TClass) -> Vec<FieldDiff> {
+        let common = |tclass: &TClass, other: &TClass| -> Vec<String> {
+            tclass
+                .fields
+                .iter()
+                .filter(|field| other.field_by_name(field.name()).is_some())
+                .map(|field| field.name().to_string())
+                .collect()
+        };
+        let (acommon, bcommon) = (common(self, other), common(other, self));
+        let mut diffs = vec![];
+        for (old, field) in self.fields.iter().enumerate() {
+            let name = field.name();
+            let Some(new) =
+                other.fields.iter().position(|f| f.name() == name)
+            else {
+                diffs.push(FieldDiff::Removed(field.clone()));
+                continue;
+            };
+            let ofield = &other.fields[new];
+            if field.vtype() != ofield.vtype() {
+                diffs.push(FieldDiff::Retyped {
+                    name: name.to_string(),
+                    old: field.vtype().map(|s| s.to_string()),
+                    new: ofield.vtype().map(|s| s.to_string()),
+                });
+            }
+            if acommon.iter().position(|n| n == name)
+                != bcommon.iter().position(|n| n == name)
+            {
+                diffs.push(FieldDiff::Moved {
+                    name: name.to_string(),
+                    old,
+                    new,
+                });
+            }
+        }
+        for field in &other.fields {
+            if self.field_by_name(field.name()).is_none() {
+                diffs.push(FieldDiff::Added(field.clone()));
+            }
+        }
+        diffs
+    }
+}
+
+/// Describes one difference between the fields of two ``TClass``es; see
+/// `TClass::diff()`. Vtypes of `None` signify fields that accept any
+/// type, and positions are field indexes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldDiff {
+    Added(Field),
+    Removed(Field),
+    Retyped { name: String, old: Option<String>, new: Option<String> },
+    Moved { name: String, old: usize, new: usize },
 }
 
+// Ignores the comment; see the TClass docs.
 impl Ord for TClass {
     fn cmp(&self, other: &Self) -> Ordering {
         let attype = self.ttype.to_uppercase();
@@ -113,6 +236,7 @@ impl PartialOrd for TClass {
     }
 }
 
+// Ignores the comment; see the TClass docs.
 impl PartialEq for TClass {
     fn eq(&self, other: &Self) -> bool {
         self.ttype == other.ttype && self.fields == other.fields
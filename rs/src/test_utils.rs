@@ -2,7 +2,174 @@
 // License: GPLv3
 
 use crate::constants::*;
-use crate::value::Value;
+use crate::output::Format;
+use crate::parser;
+use crate::util;
+use crate::uxf::Uxf;
+use crate::value::{self, PathSegment, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+/// Serializes `uxo` to UXF text, parses the text back, and asserts that
+/// the result equals `uxo`. On failure the panic message identifies which
+/// part of the document differs and includes the UXF text.
+pub fn assert_roundtrip(uxo: &Uxf) {
+    let text = match uxo.to_string_with_format(&Format::default()) {
+        Ok(text) => text,
+        Err(err) => panic!("failed to write: {}", err),
+    };
+    let reparsed = match parser::parse(&text) {
+        Ok(reparsed) => reparsed,
+        Err(err) => panic!("failed to reparse: {}\n{}", err, text),
+    };
+    assert_eq!(
+        uxo.custom(),
+        reparsed.custom(),
+        "custom differs after roundtrip\n{}",
+        text
+    );
+    assert_eq!(
+        uxo.comment(),
+        reparsed.comment(),
+        "comment differs after roundtrip\n{}",
+        text
+    );
+    assert_eq!(
+        uxo.tclasses(),
+        reparsed.tclasses(),
+        "tclasses differ after roundtrip\n{}",
+        text
+    );
+    assert_eq!(
+        uxo.value(),
+        reparsed.value(),
+        "value differs after roundtrip\n{}",
+        text
+    );
+    assert!(uxo == &reparsed, "roundtrip mismatch\n{}", text);
+}
+
+/// Returns a description of where the two values differ (one line per
+/// difference, each starting with the path of the differing value), or
+/// an empty string if they are equal. This makes it easier to see why a
+/// roundtrip test failed, e.g.,
+/// `assert!(a == b, "{}", value_diff(&a, &b));`.
+///
+/// Paths are written from the root (`value`) using `[index]` for list
+/// items, `{key}` for map items, and `[record].field` for table cells,
+/// e.g., `value{<points>}[2].x`. A nested value that's present on one side
+/// but not the other is described as `null or absent` since the path
+/// iterator doesn't yield nulls. Values nested inside values of differing
+/// types aren't compared.
+pub fn value_diff(a: &Value, b: &Value) -> String {
+    let b_values: HashMap<Vec<PathSegment>, &Value> =
+        b.iter_paths().collect();
+    let mut a_paths = HashSet::new();
+    let mut mismatched: HashSet<Vec<PathSegment>> = HashSet::new();
+    // A path is skipped if it is, or is inside, a mismatched value
+    let is_skipped = |mismatched: &HashSet<Vec<PathSegment>>,
+                      path: &[PathSegment]| {
+        (0..=path.len()).any(|n| mismatched.contains(&path[..n]))
+    };
+    let mut diffs = vec![];
+    for (path, a_value) in a.iter_paths() {
+        if is_skipped(&mismatched, &path) {
+            continue;
+        }
+        match b_values.get(&path) {
+            None => diffs.push(format!(
+                "{}: {} != null or absent",
+                value::path_to_string(&path),
+                a_value.summary(40)
+            )),
+            Some(b_value) => {
+                if !diff_one(&path, a_value, b_value, &mut diffs) {
+                    mismatched.insert(path.clone());
+                }
+            }
+        }
+        a_paths.insert(path);
+    }
+    for (path, b_value) in b.iter_paths() {
+        if !a_paths.contains(&path) && !is_skipped(&mismatched, &path) {
+            diffs.push(format!(
+                "{}: null or absent != {}",
+                value::path_to_string(&path),
+                b_value.summary(40)
+            ));
+        }
+    }
+    diffs.join("\n")
+}
+
+// Adds a line to diffs for each difference between the two values
+// themselves (ignoring any nested values) and returns false if they are
+// of different types.
+fn diff_one(
+    path: &[PathSegment],
+    a: &Value,
+    b: &Value,
+    diffs: &mut Vec<String>,
+) -> bool {
+    let mut diff = |what: &str, x: &dyn Display, y: &dyn Display| {
+        diffs.push(format!(
+            "{}: {}{} != {}",
+            value::path_to_string(path),
+            what,
+            x,
+            y
+        ))
+    };
+    let opt = |s: Option<&str>| s.unwrap_or("none").to_string();
+    match (a, b) {
+        (Value::List(x), Value::List(y)) => {
+            if x.vtype() != y.vtype() {
+                diff("vtype ", &opt(x.vtype()), &opt(y.vtype()));
+            }
+            if x.comment() != y.comment() {
+                diff("comment ", &opt(x.comment()), &opt(y.comment()));
+            }
+            if x.len() != y.len() {
+                diff("length ", &x.len(), &y.len());
+            }
+        }
+        (Value::Map(x), Value::Map(y)) => {
+            if x.ktype() != y.ktype() {
+                diff("ktype ", &opt(x.ktype()), &opt(y.ktype()));
+            }
+            if x.vtype() != y.vtype() {
+                diff("vtype ", &opt(x.vtype()), &opt(y.vtype()));
+            }
+            if x.comment() != y.comment() {
+                diff("comment ", &opt(x.comment()), &opt(y.comment()));
+            }
+            if x.len() != y.len() {
+                diff("length ", &x.len(), &y.len());
+            }
+        }
+        (Value::Table(x), Value::Table(y)) => {
+            if x.tclass() != y.tclass() {
+                diff("tclass ", x.tclass(), y.tclass());
+            }
+            if x.comment() != y.comment() {
+                diff("comment ", &opt(x.comment()), &opt(y.comment()));
+            }
+            if x.records_len() != y.records_len() {
+                diff("records ", &x.records_len(), &y.records_len());
+            }
+        }
+        _ => {
+            if a.type_name() != b.type_name() {
+                diff("", &a.summary(40), &b.summary(40));
+                return false;
+            }
+            if a != b {
+                diff("", &a.summary(40), &b.summary(40));
+            }
+        }
+    }
+    true
+}
 
 pub fn opt_value_to_str(v: Option<Value>) -> String {
     match v {
@@ -13,19 +180,19 @@ pub fn opt_value_to_str(v: Option<Value>) -> String {
 
 pub fn value_to_str(v: Value) -> String {
     match v {
-        // TODO better output for List, Map, Table: once I've implemented
-        // Display for them change "{:?}" to "{}".
+        #[cfg(feature = "bigint")]
+        Value::BigInt(i) => format!("{}", i),
         Value::Bool(true) => "yes".to_string(),
         Value::Bool(false) => "no".to_string(),
         Value::Bytes(b) => format!("{:?}", b),
         Value::Date(d) => d.format(ISO8601_DATE).to_string(),
         Value::DateTime(dt) => dt.format(ISO8601_DATETIME).to_string(),
         Value::Int(i) => format!("{}", i),
-        Value::List(lst) => format!("{:?}", lst),
-        Value::Map(m) => format!("{:?}", m),
-        Value::Real(r) => format!("{}", r),
+        Value::List(lst) => format!("{}", lst),
+        Value::Map(m) => format!("{}", m),
+        Value::Real(r) => util::real_to_uxf(r).unwrap_or(r.to_string()),
         Value::Str(s) => s,
-        Value::Table(t) => format!("{:?}", t),
+        Value::Table(t) => format!("{}", t),
     }
 }
 
@@ -2,52 +2,220 @@
 // License: GPLv3
 
 use crate::constants::*;
-use crate::value::Value;
-use anyhow::{bail, Result};
+use crate::error::UxfError;
+use std::fmt::Write as _;
+use unicode_normalization::UnicodeNormalization;
 
-pub(crate) fn check_name(name: &str) -> Result<()> {
+/// Returns the given `name` converted to Unicode Normalization Form C
+/// (NFC), e.g., `e` followed by U+0301 COMBINING ACUTE ACCENT becomes `é`.
+/// Use this to create names that match those parsed with
+/// `ParseOptions::normalize_names` set.
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Returns the given real as UXF text _or_ returns an Err if `r` is NaN
+/// or infinite (since UXF can't represent these).
+///
+/// The text always has a decimal point or an exponent so that it is read
+/// back as a real rather than an int, e.g., `5.0` (never `5`), `0.25`,
+/// `1e16`, and `1e-7`. It is the shortest text that reads back as
+/// exactly the same `f64`.
+pub fn real_to_uxf(r: f64) -> Result<String, UxfError> {
+    if r.is_finite() {
+        // Debug (unlike Display) always includes a `.` or an exponent
+        Ok(format!("{:?}", r))
+    } else {
+        Err(UxfError::new(386, format!("reals must be finite, got {}", r)))
+    }
+}
+
+/// Returns the given bytes as UXF text, e.g., `(:48656C6C6F:)` for
+/// `b"Hello"` and `(::)` for no bytes.
+pub fn bytes_to_uxf(b: &[u8]) -> String {
+    let mut s = String::with_capacity(4 + (b.len() * 2));
+    s.push_str("(:");
+    for byte in b {
+        let _ = write!(s, "{:02X}", byte);
+    }
+    s.push_str(":)");
+    s
+}
+
+/// Returns the bytes that the given UXF bytes text represents _or_
+/// returns an Err if the text has an odd number of hex digits (`#200`) or
+/// any character that isn't a hex digit or whitespace (`#202`).
+///
+/// The text may be given with or without its `(:` and `:)` delimiters.
+/// Whitespace is ignored and hex digits may be upper- or lowercase, so
+/// `(:48 65 6c\n6C 6F:)` and `48656C6C6F` both give `b"Hello"`.
+pub fn uxf_to_bytes(text: &str) -> Result<Vec<u8>, UxfError> {
+    let hex = text
+        .strip_prefix("(:")
+        .and_then(|t| t.strip_suffix(":)"))
+        .unwrap_or(text);
+    let mut digits = Vec::with_capacity(hex.len());
+    for c in hex.chars().filter(|c| !c.is_whitespace()) {
+        match c.to_digit(16) {
+            Some(digit) => digits.push(digit as u8),
+            None => {
+                return Err(UxfError::new(
+                    202,
+                    format!(
+                        "bytes may only contain hex digits, got {:?} in \
+                          {:?}",
+                        c, text
+                    ),
+                ))
+            }
+        }
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(UxfError::new(
+            200,
+            format!(
+                "expected an even number of hex digits, got {:?}",
+                text
+            ),
+        ));
+    }
+    Ok(digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect())
+}
+
+/// Returns the given text escaped for use in a UXF str or comment, i.e.,
+/// with `&` replaced by `&amp;`, `<` by `&lt;`, and `>` by `&gt;`.
+pub fn escape_str(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Returns the given UXF str or comment text unescaped, i.e., with
+/// `&amp;` replaced by `&`, `&lt;` by `<`, and `&gt;` by `>`, _or_
+/// returns an Err (`#272`) if the text has an `&` that doesn't start one
+/// of these entities.
+///
+/// Each entity is unescaped exactly once, so `&amp;lt;` gives `&lt;`.
+pub fn unescape_str(text: &str) -> Result<String, UxfError> {
+    let mut s = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        s.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (c, entity) = if rest.starts_with("&amp;") {
+            ('&', "&amp;")
+        } else if rest.starts_with("&lt;") {
+            ('<', "&lt;")
+        } else if rest.starts_with("&gt;") {
+            ('>', "&gt;")
+        } else {
+            let bad: String = rest.chars().take(6).collect();
+            return Err(UxfError::new(
+                272,
+                format!(
+                    "a str's & must start &amp;, &lt;, or &gt;, got {:?}",
+                    bad
+                ),
+            ));
+        };
+        s.push(c);
+        rest = &rest[entity.len()..];
+    }
+    s.push_str(rest);
+    Ok(s)
+}
+
+pub(crate) fn check_comment(
+    comment: &str,
+    max_len: usize,
+) -> Result<(), UxfError> {
+    let len = comment.chars().count();
+    if len > max_len {
+        return Err(UxfError::new(
+            182,
+            format!(
+                "comments may be at most {} characters long, got {} \
+                  characters",
+                max_len, len
+            ),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_ktype(name: &str) -> Result<(), UxfError> {
+    if !KTYPES.contains(&name) {
+        return Err(UxfError::new(
+            610,
+            format!(
+                "a map's ktype must be one of {}, got {}",
+                KTYPES.join(" "),
+                name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_name(name: &str) -> Result<(), UxfError> {
     check_type_name(name)?;
     if RESERVED_WORDS.contains(&name) {
-        bail!(
-            "#304:names cannot be the same as built-in type names or \
-              constants, got {}",
-            name
-        );
+        return Err(UxfError::new(
+            304,
+            format!(
+                "names cannot be the same as built-in type names or \
+                  constants, got {}",
+                name
+            ),
+        ));
     }
     Ok(())
 }
 
-pub(crate) fn check_type_name(name: &str) -> Result<()> {
+pub(crate) fn check_type_name(name: &str) -> Result<(), UxfError> {
     if name.is_empty() {
-        bail!("#600:type names must be nonempty");
+        return Err(UxfError::new(
+            600,
+            "type names must be nonempty".to_string(),
+        ));
     }
     let first = name.chars().next().unwrap(); // safe because nonempty
     if !(first == '_' || first.is_alphabetic()) {
-        bail!(
-            "#602:type names must start with a letter or underscore, \
-              got {}",
-            name
-        );
+        return Err(UxfError::new(
+            602,
+            format!(
+                "type names must start with a letter or underscore, \
+                  got {}",
+                name
+            ),
+        ));
     }
     if name == BOOL_TRUE || name == BOOL_FALSE {
-        bail!("#604:type names may not be yes or no got {}", name);
+        return Err(UxfError::new(
+            604,
+            format!("type names may not be yes or no got {}", name),
+        ));
     }
     for (i, c) in name.chars().enumerate() {
         if i == MAX_IDENTIFIER_LEN {
-            bail!(
-                "#606:type names may be at most {} characters long, \
-                  got {} ({} characters)",
-                MAX_IDENTIFIER_LEN,
-                name,
-                i + 1
-            );
+            return Err(UxfError::new(
+                606,
+                format!(
+                    "type names may be at most {} characters long, \
+                      got {} ({} characters)",
+                    MAX_IDENTIFIER_LEN,
+                    name,
+                    i + 1
+                ),
+            ));
         }
         if !(c == '_' || c.is_alphanumeric()) {
-            bail!(
-                "#608:type names may only contain letters, digits, or \
-                  underscores, got {}",
-                name
-            );
+            return Err(UxfError::new(
+                608,
+                format!(
+                    "type names may only contain letters, digits, or \
+                      underscores, got {}",
+                    name
+                ),
+            ));
         }
     }
     Ok(())
@@ -0,0 +1,897 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+#[cfg(feature = "cache")]
+use crate::cache;
+use crate::constants::*;
+use crate::error::{UxfError, Warning};
+use crate::field::Field;
+use crate::list::List;
+use crate::map::Map;
+use crate::output::{self, Format};
+use crate::table::Table;
+use crate::tclass::TClass;
+use crate::util;
+use crate::value::{self, PathSegment, Value};
+use crate::visitor::Visitor;
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write as _;
+use std::ops::Range;
+
+/// Specifies how `Uxf::add_tclass_merge()` reconciles a `TClass` with an
+/// existing `TClass` of the same ttype.
+///
+/// With `Strict` the fields must match exactly (in the same order); with
+/// `AllowReorder` the fields must be the same but may be in a different
+/// order, in which case the existing ``TClass``'s field order is kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    Strict,
+    AllowReorder,
+}
+
+/// A problem found by `Uxf::validate_all()`.
+///
+/// - `Mismatch` holds a value or map key that doesn't match its declared
+///   type (as reported by `Uxf::validate()`).
+/// - `MissingTType` is a ttype that is used but isn't one of the
+///   ``Uxf``'s tclasses, with where it is used: a value's path (e.g.,
+///   `value[2]`) for a table's ttype or a list's or map's vtype, or a
+///   ttype and field name (e.g., `Line.end`) for a field's vtype.
+/// - `DuplicateField` is a field name that a tclass has more than once.
+///
+/// Each displays as an `UxfError` would, e.g., `#694:value[2]: undefined
+/// ttype Point`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    Mismatch(UxfError),
+    MissingTType { path: String, ttype: String },
+    DuplicateField { ttype: String, name: String },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::Mismatch(err) => write!(f, "{}", err),
+            ValidationError::MissingTType { path, ttype } => {
+                write!(f, "#694:{}: undefined ttype {}", path, ttype)
+            }
+            ValidationError::DuplicateField { ttype, name } => {
+                write!(
+                    f,
+                    "#336:duplicate field name {} in tclass {}",
+                    name, ttype
+                )
+            }
+        }
+    }
+}
+
+/// Holds a whole UXF document: the optional `custom` header text, the
+/// optional file-level `comment`, the ttype definitions (``TClass``es),
+/// and the root `value` (which is always a `List`, `Map`, or `Table`).
+///
+/// A `Uxf` may also hold the source spans of its values if it was parsed
+/// with `ParseOptions::record_spans` set; see `span_of()`. Spans (and the
+/// maximum comment length) are ignored when comparing ``Uxf``s.
+#[derive(Clone, Debug)]
+pub struct Uxf {
+    version: f64,
+    custom: Option<String>,
+    comment: Option<String>,
+    tclasses: HashMap<String, TClass>,
+    value: Value,
+    spans: HashMap<Vec<PathSegment>, Range<usize>>,
+    max_comment_len: Option<usize>,
+}
+
+impl Uxf {
+    /// Creates a new `Uxf` with the given root `value` (which should be a
+    /// `List`, `Map`, or `Table`), no custom text, and no comment.
+    ///
+    /// The tclasses of any tables in `value` (including nested ones) are
+    /// added to the ``Uxf``'s tclasses so that it can be written as UXF
+    /// text. (If two tables have different tclasses with the same ttype
+    /// the first one found is used.)
+    pub fn new(value: Value) -> Self {
+        let mut tclasses = HashMap::new();
+        for (_, v) in value.iter_paths() {
+            if let Value::Table(t) = v {
+                tclasses
+                    .entry(t.tclass().ttype().to_string())
+                    .or_insert_with(|| t.tclass().clone());
+            }
+        }
+        Uxf {
+            version: UXF_VERSION,
+            custom: None,
+            comment: None,
+            tclasses,
+            value,
+            spans: HashMap::new(),
+            max_comment_len: None,
+        }
+    }
+
+    pub(crate) fn from_parts(
+        custom: Option<String>,
+        comment: Option<String>,
+        tclasses: HashMap<String, TClass>,
+        value: Value,
+    ) -> Self {
+        Uxf {
+            version: UXF_VERSION,
+            custom,
+            comment,
+            tclasses,
+            value,
+            spans: HashMap::new(),
+            max_comment_len: None,
+        }
+    }
+
+    pub(crate) fn set_version(&mut self, version: f64) {
+        self.version = version;
+    }
+
+    pub(crate) fn set_spans(
+        &mut self,
+        spans: HashMap<Vec<PathSegment>, Range<usize>>,
+    ) {
+        self.spans = spans;
+    }
+
+    /// Returns the byte range in the parsed text of the value (or null) at
+    /// the given `path` (as returned by `Value::iter_paths()`, so `&[]` is
+    /// the root value), or `None` if there's no such value or if spans
+    /// weren't recorded (see `ParseOptions::record_spans`).
+    ///
+    /// Spans describe the text as it was parsed, so they aren't updated if
+    /// the value is changed.
+    pub fn span_of(&self, path: &[PathSegment]) -> Option<Range<usize>> {
+        self.spans.get(path).cloned()
+    }
+
+    /// Returns the UXF version read from the header (e.g., `1.0` for
+    /// `uxf 1.0`), or `UXF_VERSION` if this `Uxf` wasn't parsed.
+    ///
+    /// A `Uxf` is always written with the current `UXF_VERSION`, and the
+    /// version is ignored when comparing ``Uxf``s.
+    pub fn version(&self) -> f64 {
+        self.version
+    }
+
+    /// Returns the ``Uxf``'s custom header text (if any).
+    pub fn custom(&self) -> Option<&str> {
+        match &self.custom {
+            None => None,
+            Some(custom) => Some(custom),
+        }
+    }
+
+    /// Sets the ``Uxf``'s custom header text _or_ returns an Err if it
+    /// contains a newline (since the header must be a single line).
+    /// The text is kept verbatim (including any leading, trailing, or
+    /// internal whitespace), and an empty `custom` is treated as `None`.
+    ///
+    /// The header is written as `uxf 1.0` followed by a single space and
+    /// then the custom text, which is how the parser reads it back.
+    pub fn set_custom(&mut self, custom: Option<&str>) -> Result<()> {
+        let custom = custom.filter(|s| !s.is_empty());
+        if let Some(custom) = custom {
+            if custom.contains(['\n', '\r']) {
+                bail!(UxfError::new(
+                    114,
+                    "custom header text may not contain newlines"
+                        .to_string()
+                ));
+            }
+        }
+        self.custom = custom.map(|s| s.to_string());
+        Ok(())
+    }
+
+    /// Returns the ``Uxf``'s file-level comment (if any).
+    pub fn comment(&self) -> Option<&str> {
+        match &self.comment {
+            None => None,
+            Some(comment) => Some(comment),
+        }
+    }
+
+    /// Sets the ``Uxf``'s file-level `comment` (or removes it if
+    /// `comment` is `None`) _or_ returns an Err if the comment is longer
+    /// than the ``Uxf``'s `max_comment_len()`.
+    pub fn set_comment(&mut self, comment: Option<&str>) -> Result<()> {
+        if let (Some(comment), Some(max_len)) =
+            (comment, self.max_comment_len)
+        {
+            util::check_comment(comment, max_len)?;
+        }
+        self.comment = comment.map(|s| s.to_string());
+        Ok(())
+    }
+
+    /// Returns the maximum comment length (in characters) that
+    /// `set_comment()` accepts, or `None` (the default) if comments are
+    /// unbounded. For a parsed `Uxf` this is the
+    /// `ParseOptions::max_comment_len` it was parsed with.
+    ///
+    /// The limit is also a convenient one to pass to the `List`, `Map`,
+    /// and `Table` `set_comment()` methods for values in this `Uxf`.
+    pub fn max_comment_len(&self) -> Option<usize> {
+        self.max_comment_len
+    }
+
+    /// Sets the maximum comment length (in characters) that
+    /// `set_comment()` accepts, or removes the limit if `max_len` is
+    /// `None`. (This doesn't check the existing comment.)
+    pub fn set_max_comment_len(&mut self, max_len: Option<usize>) {
+        self.max_comment_len = max_len;
+    }
+
+    /// Returns the ``Uxf``'s tclasses (ttype definitions) keyed by ttype.
+    pub fn tclasses(&self) -> &HashMap<String, TClass> {
+        &self.tclasses
+    }
+
+    /// Returns the `TClass` with the given `ttype` (if any).
+    pub fn tclass(&self, ttype: &str) -> Option<&TClass> {
+        self.tclasses.get(ttype)
+    }
+
+    /// Adds the given `tclass` to this ``Uxf``'s tclasses _or_ returns an
+    /// Err if a different `TClass` with the same ttype already exists.
+    /// This is the same as `add_tclass_merge()` with `MergePolicy::Strict`.
+    pub fn add_tclass(&mut self, tclass: TClass) -> Result<()> {
+        self.add_tclass_merge(tclass, MergePolicy::Strict)
+    }
+
+    /// Returns the ``Uxf``'s root `value` (a `List`, `Map`, or `Table`).
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Returns a mutable reference to the ``Uxf``'s root `value`.
+    pub fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
+    /// Adds the given `tclass` to this ``Uxf``'s tclasses _or_ returns an
+    /// Err if a `TClass` with the same ttype already exists and the two
+    /// can't be reconciled according to the given `policy`.
+    /// Adding an identical `TClass` is harmless.
+    pub fn add_tclass_merge(
+        &mut self,
+        tclass: TClass,
+        policy: MergePolicy,
+    ) -> Result<()> {
+        if let Some(existing) = self.tclasses.get(tclass.ttype()) {
+            if existing == &tclass {
+                return Ok(());
+            }
+            let mut efields = existing.fields().to_vec();
+            let mut fields = tclass.fields().to_vec();
+            efields.sort();
+            fields.sort();
+            if efields != fields {
+                bail!(UxfError::new(
+                    528,
+                    format!(
+                        "conflicting ttype definitions for {}",
+                        tclass.ttype()
+                    )
+                ));
+            }
+            if policy == MergePolicy::Strict {
+                bail!(UxfError::new(
+                    692,
+                    format!(
+                        "ttype definitions for {} have the same fields \
+                          but in a different order",
+                        tclass.ttype()
+                    )
+                ));
+            }
+            return Ok(()); // AllowReorder: keep the existing field order
+        }
+        self.tclasses.insert(tclass.ttype().to_string(), tclass);
+        Ok(())
+    }
+
+    /// Renames the `old` ttype to `new` throughout this `Uxf` _or_ returns
+    /// an Err if `new` is invalid, or if there is no `old` ttype, or if
+    /// there is already a `new` ttype.
+    ///
+    /// Every reference to `old` is updated: the ttype registry, any field
+    /// vtypes, and the ttypes of tables and the vtypes of lists and maps
+    /// anywhere in the document.
+    pub fn rename_ttype(&mut self, old: &str, new: &str) -> Result<()> {
+        util::check_name(new)?;
+        if !self.tclasses.contains_key(old) {
+            bail!(UxfError::new(
+                696,
+                format!("cannot rename nonexistent ttype {}", old)
+            ));
+        }
+        if self.tclasses.contains_key(new) {
+            bail!(UxfError::new(
+                698,
+                format!(
+                    "cannot rename ttype {} to existing ttype {}",
+                    old, new
+                )
+            ));
+        }
+        let mut tclasses = HashMap::with_capacity(self.tclasses.len());
+        for tclass in self.tclasses.values() {
+            let tclass = renamed_tclass(tclass, old, new)?;
+            tclasses.insert(tclass.ttype().to_string(), tclass);
+        }
+        self.tclasses = tclasses;
+        rename_in_value(&mut self.value, old, new)
+    }
+
+    /// Checks every typed list, map, and table in this ``Uxf``'s value
+    /// against its declared types and returns a warning for each value
+    /// (or key) that doesn't match, or an empty vector if there are none
+    /// _or_ returns an Err if the value uses a ttype (as a table's ttype
+    /// or a list's or map's vtype) that isn't one of the ``Uxf``'s
+    /// tclasses, since then it can't be written as valid UXF text.
+    ///
+    /// The parser accepts mismatched values (as does the Python UXF
+    /// implementation, which reports them when linting), so this allows
+    /// them to be found after parsing. Each warning's message starts with
+    /// the path of the offending value (e.g., `value[1]`, `value{<k>}`, or
+    /// `value[0].x` for a table cell); if the `Uxf` was parsed with
+    /// `ParseOptions::record_spans` set it ends with the value's byte
+    /// range. Mismatched values have code `#372` and mismatched map keys
+    /// `#374` (as for `Table::validate()`). Nulls always match. Map items
+    /// are checked in canonical key order. An undefined ttype is a `#694`
+    /// error (see also `validate_all()`, which reports them all).
+    pub fn validate(&self) -> Result<Vec<Warning>> {
+        if let Some((path, ttype)) = self.undefined_ttypes().first() {
+            bail!(UxfError::new(
+                694,
+                format!("{}: undefined ttype {}", path, ttype)
+            ));
+        }
+        Ok(self.mismatches())
+    }
+
+    // Returns a warning for every value (or key) that doesn't match its
+    // declared type; see validate().
+    fn mismatches(&self) -> Vec<Warning> {
+        let mut warnings = vec![];
+        for (path, value) in self.value.iter_paths() {
+            // Returns the path of the child with the given segment
+            let child = |segment: PathSegment| {
+                let mut path = path.clone();
+                path.push(segment);
+                path
+            };
+            match value {
+                Value::List(lst) => {
+                    for (i, value) in lst.values().iter().enumerate() {
+                        let path = child(PathSegment::Index(i));
+                        warnings.extend(self.check(
+                            &path,
+                            lst.vtype(),
+                            value,
+                        ));
+                    }
+                }
+                Value::Map(m) => {
+                    for (key, value) in m.iter_sorted() {
+                        let path = child(PathSegment::Key(key.clone()));
+                        if let Some(ktype) = m.ktype() {
+                            if ktype != key.type_name() {
+                                let message = format!(
+                                    "expected {} key, got {}",
+                                    ktype,
+                                    key.type_name()
+                                );
+                                warnings.push(
+                                    self.warning(374, &path, &message),
+                                );
+                            }
+                        }
+                        warnings.extend(self.check(
+                            &path,
+                            m.vtype(),
+                            value,
+                        ));
+                    }
+                }
+                Value::Table(t) => {
+                    let fields = t.tclass().fields();
+                    for (row, record) in t.records().iter().enumerate() {
+                        for (field, value) in fields.iter().zip(record) {
+                            let name = field.name().to_string();
+                            let path = child(PathSegment::Cell(row, name));
+                            warnings.extend(self.check(
+                                &path,
+                                field.vtype(),
+                                value,
+                            ));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        warnings
+    }
+
+    /// Checks this whole `Uxf` and returns every problem found, or an
+    /// empty vector if there are none.
+    ///
+    /// Unlike `validate()`, which stops at the first undefined ttype in
+    /// the value, this reports every ttype that is used (by a table, as a
+    /// vtype, or as a field's vtype) but not defined, and every tclass
+    /// with duplicate field names. Problems are reported tclasses first (in
+    /// sorted order), then undefined ttypes in the value (in the order
+    /// that `Value::iter_paths()` visits them), then mismatches (as
+    /// `validate()` gives them).
+    pub fn validate_all(&self) -> Vec<ValidationError> {
+        let mut errors = vec![];
+        let mut tclasses: Vec<&TClass> = self.tclasses.values().collect();
+        tclasses.sort();
+        for tclass in tclasses {
+            let fields = tclass.fields();
+            for (i, field) in fields.iter().enumerate() {
+                if fields[..i].iter().any(|f| f.name() == field.name()) {
+                    errors.push(ValidationError::DuplicateField {
+                        ttype: tclass.ttype().to_string(),
+                        name: field.name().to_string(),
+                    });
+                }
+                if let Some(vtype) =
+                    field.vtype().filter(|v| self.is_undefined(v))
+                {
+                    errors.push(ValidationError::MissingTType {
+                        path: format!(
+                            "{}.{}",
+                            tclass.ttype(),
+                            field.name()
+                        ),
+                        ttype: vtype.to_string(),
+                    });
+                }
+            }
+        }
+        errors.extend(self.undefined_ttypes().into_iter().map(
+            |(path, ttype)| ValidationError::MissingTType { path, ttype },
+        ));
+        errors.extend(
+            self.mismatches().into_iter().map(ValidationError::Mismatch),
+        );
+        errors
+    }
+
+    // Returns the path and ttype of every value that uses a ttype (as a
+    // table's ttype or a list's or map's vtype) that isn't defined, in the
+    // order that Value::iter_paths() visits them.
+    fn undefined_ttypes(&self) -> Vec<(String, String)> {
+        let mut undefined = vec![];
+        for (path, value) in self.value.iter_paths() {
+            let ttype = match value {
+                Value::List(lst) => lst.vtype(),
+                Value::Map(m) => m.vtype(),
+                Value::Table(t) => Some(t.tclass().ttype()),
+                _ => None,
+            };
+            if let Some(ttype) =
+                ttype.filter(|ttype| self.is_undefined(ttype))
+            {
+                undefined.push((
+                    value::path_to_string(&path),
+                    ttype.to_string(),
+                ));
+            }
+        }
+        undefined
+    }
+
+    fn is_undefined(&self, ttype: &str) -> bool {
+        !VTYPES.contains(&ttype) && !self.tclasses.contains_key(ttype)
+    }
+
+    // Returns a warning if the value at the path doesn't match the vtype.
+    fn check(
+        &self,
+        path: &[PathSegment],
+        vtype: Option<&str>,
+        value: &Option<Value>,
+    ) -> Option<Warning> {
+        match (vtype, value) {
+            (Some(vtype), Some(value)) if !value.has_vtype(vtype) => {
+                let message = format!(
+                    "expected {}, got {}",
+                    vtype,
+                    value.vtype_name()
+                );
+                Some(self.warning(372, path, &message))
+            }
+            _ => None,
+        }
+    }
+
+    // Returns a warning whose message starts with the path and ends with
+    // the span (if known).
+    fn warning(
+        &self,
+        code: u16,
+        path: &[PathSegment],
+        message: &str,
+    ) -> Warning {
+        let mut message =
+            format!("{}: {}", value::path_to_string(path), message);
+        if let Some(span) = self.span_of(path) {
+            let _ =
+                write!(message, " (bytes {}..{})", span.start, span.end);
+        }
+        UxfError::new(code, message)
+    }
+
+    /// Returns every reference cycle among this ``Uxf``'s ttypes.
+    ///
+    /// A ttype references another if one of its fields has that ttype as
+    /// its vtype. Cycles are legal (e.g., `=Node value:int child:Node`
+    /// defines a tree) but code that recursively follows ttype
+    /// definitions must allow for them. Each cycle is listed once, as the
+    /// ttypes in reference order starting from the alphabetically first,
+    /// and the cycles are sorted; so `=Node value:int child:Node` gives
+    /// `[["Node"]]`.
+    pub fn ttype_cycles(&self) -> Vec<Vec<String>> {
+        let mut ttypes: Vec<&str> =
+            self.tclasses.keys().map(|s| s.as_str()).collect();
+        ttypes.sort();
+        let mut cycles = vec![];
+        for start in &ttypes {
+            let mut path = vec![*start];
+            self.find_cycles(start, &mut path, &mut cycles);
+        }
+        cycles.sort();
+        cycles
+    }
+
+    // Adds every cycle that leads back to start via ttypes that sort
+    // after start (so that each cycle is only found from its first ttype).
+    fn find_cycles<'a>(
+        &'a self,
+        start: &str,
+        path: &mut Vec<&'a str>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        let current = path[path.len() - 1];
+        let mut references: Vec<&str> = self.tclasses[current]
+            .fields()
+            .iter()
+            .filter_map(|field| field.vtype())
+            .filter(|vtype| self.tclasses.contains_key(*vtype))
+            .collect();
+        references.sort();
+        references.dedup();
+        for next in references {
+            if next == start {
+                cycles.push(path.iter().map(|s| s.to_string()).collect());
+            } else if next > start && !path.contains(&next) {
+                path.push(next);
+                self.find_cycles(start, path, cycles);
+                path.pop();
+            }
+        }
+    }
+
+    /// Returns how many values of each type the document holds (including
+    /// the root value and every nested value), keyed by vtype name (e.g.,
+    /// `"int"` or `"list"`), with nulls counted as `"null"`. Every table
+    /// is counted as a `"table"` whatever its ttype. Types that don't
+    /// occur aren't present.
+    pub fn value_type_histogram(&self) -> HashMap<&'static str, usize> {
+        let mut histogram = TypeHistogram::default();
+        self.value.accept(&mut histogram);
+        histogram.counts
+    }
+
+    /// Makes all the tables that have identical tclasses share a single
+    /// copy of the tclass, and returns how many tables now share a tclass
+    /// that they didn't share before.
+    ///
+    /// Each table normally has its own copy of its tclass (including its
+    /// ttype and field names), so a document with thousands of tables of
+    /// the same ttype holds thousands of identical copies. Interning
+    /// saves that memory and makes comparing the tclasses of tables that
+    /// share one (e.g., tables in the same document, or in a clone of it)
+    /// a pointer comparison rather than a string-by-string one.
+    ///
+    /// The tradeoffs are that interning takes a pass over the whole
+    /// value, and that it is a snapshot: tables added afterwards, or
+    /// whose tclass is replaced (e.g., by `Table::insert_column()`), have
+    /// their own copies until this is called again. Interning never
+    /// changes the document's content, output, or equality.
+    pub fn intern(&mut self) -> usize {
+        self.value.intern(&mut HashMap::new())
+    }
+
+    /// Returns this `Uxf` as UXF text written using the given `Format`
+    /// _or_ returns an Err if it holds a NaN or infinite real (`#386`),
+    /// since UXF text can't represent these and so couldn't be read back.
+    ///
+    /// (`to_string()` uses the default `Format` and never fails, but
+    /// writes any NaN or infinite reals as `NaN`, `inf`, or `-inf`, which
+    /// `parser::parse()` rejects.)
+    pub fn to_string_with_format(&self, format: &Format) -> Result<String> {
+        output::to_string(self, format)
+    }
+
+    /// Returns this `Uxf` encoded in a compact binary cache format that
+    /// `from_cache_bytes()` can read back much faster than `parse()` can
+    /// read UXF text.
+    ///
+    /// This is intended for applications that load the same large UXF
+    /// file repeatedly: parse the text once and keep the cache bytes
+    /// alongside it. The cache format is _not_ part of the UXF format: it
+    /// is private to this crate and may change between versions (in which
+    /// case `from_cache_bytes()` returns an Err and the cache must be
+    /// rebuilt). Source spans (see `span_of()`) aren't cached. (Requires
+    /// the `cache` feature.)
+    #[cfg(feature = "cache")]
+    pub fn to_cache_bytes(&self) -> Vec<u8> {
+        cache::to_cache_bytes(self)
+    }
+
+    /// Returns the `Uxf` encoded in the given `bytes` (as returned by
+    /// `to_cache_bytes()`) _or_ returns an Err if the bytes aren't cache
+    /// data (`#800`), were written by an incompatible version of this
+    /// crate (`#802`), are truncated or corrupt (`#804`), or are nested
+    /// too deeply (`#808`). (Requires the `cache` feature.)
+    #[cfg(feature = "cache")]
+    pub fn from_cache_bytes(bytes: &[u8]) -> Result<Self> {
+        cache::from_cache_bytes(bytes)
+    }
+
+    /// Returns this `Uxf` as an indented tree (in the style of the `tree`
+    /// command) for human inspection; this is _not_ UXF text.
+    ///
+    /// The first line is the header, then there is one line per value,
+    /// showing its position in its parent (list index, map key, or table
+    /// record index and field name), its type, and (for scalars) its value
+    /// truncated to at most 40 characters. Nulls are shown as `?`.
+    pub fn to_tree_string(&self) -> String {
+        let mut s = format!("uxf {:.1}", UXF_VERSION);
+        if let Some(custom) = self.custom() {
+            s.push(' ');
+            s.push_str(custom);
+        }
+        s.push('\n');
+        write_tree_node(&mut s, "", true, "", Some(&self.value));
+        s
+    }
+}
+
+const TREE_VALUE_LEN: usize = 40;
+
+// Writes one tree line for the given value and then its children (if
+// any). The prefix holds the connectors inherited from its ancestors.
+fn write_tree_node(
+    s: &mut String,
+    prefix: &str,
+    last: bool,
+    label: &str,
+    value: Option<&Value>,
+) {
+    s.push_str(prefix);
+    s.push_str(if last { "└── " } else { "├── " });
+    s.push_str(label);
+    if !label.is_empty() {
+        s.push(' ');
+    }
+    let mut children: Vec<(String, Option<&Value>)> = vec![];
+    match value {
+        None => s.push('?'),
+        Some(value) => {
+            match value {
+                Value::List(_) | Value::Map(_) | Value::Table(_) => (),
+                _ => {
+                    s.push_str(value.type_name());
+                    s.push(' ');
+                }
+            }
+            s.push_str(&value.summary(TREE_VALUE_LEN));
+            match value {
+                Value::List(lst) => {
+                    for (i, v) in lst.values().iter().enumerate() {
+                        children.push((format!("[{}]", i), v.as_ref()));
+                    }
+                }
+                Value::Map(m) => {
+                    for (key, v) in m.iter_sorted() {
+                        let key = Value::from(key.clone()).summary(20);
+                        children.push((key, v.as_ref()));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    s.push('\n');
+    let prefix =
+        format!("{}{}", prefix, if last { "    " } else { "│   " });
+    if let Some(Value::Table(t)) = value {
+        for (row, record) in t.records().iter().enumerate() {
+            let last_record = row + 1 == t.records().len();
+            s.push_str(&prefix);
+            s.push_str(if last_record {
+                "└── "
+            } else {
+                "├── "
+            });
+            s.push_str(&format!("[{}]\n", row));
+            let record_prefix = format!(
+                "{}{}",
+                prefix,
+                if last_record { "    " } else { "│   " }
+            );
+            let fields = t.tclass().fields();
+            for (column, (field, v)) in
+                fields.iter().zip(record).enumerate()
+            {
+                let last_field = column + 1 == fields.len();
+                write_tree_node(
+                    s,
+                    &record_prefix,
+                    last_field,
+                    field.name(),
+                    v.as_ref(),
+                );
+            }
+        }
+    }
+    let n = children.len();
+    for (i, (label, v)) in children.into_iter().enumerate() {
+        write_tree_node(s, &prefix, i + 1 == n, &label, v);
+    }
+}
+
+// Returns a copy of the given tclass with old renamed to new in its ttype
+// and field vtypes.
+fn renamed_tclass(tclass: &TClass, old: &str, new: &str) -> Result<TClass> {
+    let rename =
+        |name: &str| if name == old { new } else { name }.to_string();
+    let mut fields = Vec::with_capacity(tclass.len());
+    for field in tclass.fields() {
+        fields.push(match field.vtype() {
+            Some(vtype) => Field::new(field.name(), &rename(vtype))?,
+            None => field.clone(),
+        });
+    }
+    let ttype = rename(tclass.ttype());
+    if tclass.is_fieldless() {
+        TClass::new_fieldless(&ttype, tclass.comment())
+    } else {
+        TClass::new(&ttype, fields, tclass.comment())
+    }
+}
+
+fn rename_in_value(value: &mut Value, old: &str, new: &str) -> Result<()> {
+    match value {
+        Value::List(lst) => {
+            if lst.vtype() == Some(old) {
+                lst.set_vtype(Some(new));
+            }
+            for value in lst.values_mut().iter_mut().flatten() {
+                rename_in_value(value, old, new)?;
+            }
+        }
+        Value::Map(m) => {
+            if m.vtype() == Some(old) {
+                m.set_vtype(Some(new));
+            }
+            for value in m.values_mut().flatten() {
+                rename_in_value(value, old, new)?;
+            }
+        }
+        Value::Table(t) => {
+            t.set_tclass(renamed_tclass(t.tclass(), old, new)?);
+            for record in t.records_mut() {
+                for value in record.iter_mut().flatten() {
+                    rename_in_value(value, old, new)?;
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+impl PartialEq for Uxf {
+    fn eq(&self, other: &Self) -> bool {
+        self.custom == other.custom
+            && self.comment == other.comment
+            && self.tclasses == other.tclasses
+            && self.value == other.value
+    }
+}
+
+impl Eq for Uxf {}
+
+// Counts values by type for Uxf::value_type_histogram().
+#[derive(Default)]
+struct TypeHistogram {
+    counts: HashMap<&'static str, usize>,
+}
+
+impl TypeHistogram {
+    fn count(&mut self, name: &'static str) {
+        *self.counts.entry(name).or_default() += 1;
+    }
+}
+
+impl Visitor for TypeHistogram {
+    #[cfg(feature = "bigint")]
+    fn visit_bigint(&mut self, _i: &num_bigint::BigInt) {
+        self.count(VTYPE_NAME_INT);
+    }
+
+    fn visit_bool(&mut self, _b: bool) {
+        self.count(VTYPE_NAME_BOOL);
+    }
+
+    fn visit_bytes(&mut self, _b: &[u8]) {
+        self.count(VTYPE_NAME_BYTES);
+    }
+
+    fn visit_date(&mut self, _d: &NaiveDate) {
+        self.count(VTYPE_NAME_DATE);
+    }
+
+    fn visit_datetime(&mut self, _dt: &NaiveDateTime) {
+        self.count(VTYPE_NAME_DATETIME);
+    }
+
+    fn visit_int(&mut self, _i: i64) {
+        self.count(VTYPE_NAME_INT);
+    }
+
+    fn visit_null(&mut self) {
+        self.count(VALUE_NAME_NULL);
+    }
+
+    fn visit_real(&mut self, _r: f64) {
+        self.count(VTYPE_NAME_REAL);
+    }
+
+    fn visit_str(&mut self, _s: &str) {
+        self.count(VTYPE_NAME_STR);
+    }
+
+    fn visit_list(&mut self, _lst: &List) {
+        self.count(VTYPE_NAME_LIST);
+    }
+
+    fn visit_map(&mut self, _m: &Map) {
+        self.count(VTYPE_NAME_MAP);
+    }
+
+    fn visit_table(&mut self, _t: &Table) {
+        self.count(VTYPE_NAME_TABLE);
+    }
+}
+
+impl fmt::Display for Uxf {
+    /// Writes this `Uxf` as UXF text which `parser::parse()` can read
+    /// (unless it holds a NaN or infinite real; see
+    /// `to_string_with_format()`).
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            output::to_string_unchecked(self, &Format::default())
+        )
+    }
+}
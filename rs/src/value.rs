@@ -1,28 +1,962 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
+use crate::constants::*;
+use crate::error::UxfError;
 use crate::list::List;
 use crate::map::Map;
+use crate::output::{self, Format};
 use crate::table::Table;
+use crate::tclass::TClass;
+use crate::visitor::Visitor;
+use anyhow::{bail, Result};
 use chrono::prelude::*;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 // See also Michael-F-Bryan's replies in
 // https://users.rust-lang.org/t/how-do-i-create-an-enum-that-subsumes-others/78232/8?u=mark
 
 #[derive(Clone, Debug)]
 pub enum Value {
+    #[cfg(feature = "bigint")]
+    BigInt(num_bigint::BigInt),
     Bool(bool),
     Bytes(Vec<u8>),
     Date(NaiveDate),
     DateTime(NaiveDateTime),
+    /// An int may be any `i64` (i.e., from `i64::MIN` to `i64::MAX`
+    /// inclusive); see also `BigInt` (with the `bigint` feature).
     Int(i64),
     List(List),
     Map(Map),
+    /// A real should be finite since UXF text can't represent NaN or
+    /// infinity; use `Value::new_real()` to ensure this.
     Real(f64),
     Str(String),
     Table(Table),
 }
 
+impl Value {
+    /// Returns a new `Value::Int`; every `i64` is a valid UXF int.
+    pub fn new_int(i: i64) -> Self {
+        Value::Int(i)
+    }
+
+    /// Returns true if this is a `Value::Real` that holds a (finite) whole
+    /// number, e.g., `3.0` or `-0.0`; otherwise returns false (including
+    /// for ints).
+    ///
+    /// Such reals are still written as reals (e.g., `3.0`, never `3`) so
+    /// that the int/real distinction survives a round trip; this method
+    /// allows tools to detect them (e.g., to offer converting them to
+    /// ints).
+    pub fn real_is_integral(&self) -> bool {
+        match self {
+            Value::Real(r) => r.is_finite() && r.fract() == 0.0,
+            _ => false,
+        }
+    }
+
+    /// Returns this value as an `f64` if it is an int or a real; otherwise
+    /// returns `None`. This is convenient for numeric aggregation that
+    /// doesn't care whether values are ints or reals.
+    ///
+    /// Ints are converted with `as`, so those outside ±2⁵³ may lose
+    /// precision (e.g., `9007199254740993` gives `9007199254740992.0`).
+    /// (With the `bigint` feature, a `BigInt` too large for an `f64` gives
+    /// infinity.)
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            #[cfg(feature = "bigint")]
+            Value::BigInt(i) => i.to_string().parse().ok(),
+            Value::Int(i) => Some(*i as f64),
+            Value::Real(r) => Some(*r),
+            _ => None,
+        }
+    }
+
+    /// Returns the bool if this is a `Value::Bool`; otherwise `None`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes if this is a `Value::Bytes`; otherwise `None`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Returns the date if this is a `Value::Date`; otherwise `None`.
+    pub fn as_date(&self) -> Option<NaiveDate> {
+        match self {
+            Value::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// Returns the datetime if this is a `Value::DateTime`; otherwise
+    /// `None`.
+    pub fn as_datetime(&self) -> Option<NaiveDateTime> {
+        match self {
+            Value::DateTime(dt) => Some(*dt),
+            _ => None,
+        }
+    }
+
+    /// Returns the int if this is a `Value::Int`; otherwise `None`. (Reals
+    /// aren't converted; see `as_number()`.)
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the real if this is a `Value::Real`; otherwise `None`.
+    /// (Ints aren't converted; see `as_number()`.)
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Value::Real(r) => Some(*r),
+            _ => None,
+        }
+    }
+
+    /// Returns the str if this is a `Value::Str`; otherwise `None`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the list if this is a `Value::List`; otherwise `None`.
+    pub fn as_list(&self) -> Option<&List> {
+        match self {
+            Value::List(lst) => Some(lst),
+            _ => None,
+        }
+    }
+
+    /// Returns the map if this is a `Value::Map`; otherwise `None`.
+    pub fn as_map(&self) -> Option<&Map> {
+        match self {
+            Value::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    /// Returns the table if this is a `Value::Table`; otherwise `None`.
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Returns a new `Value::Real` _or_ returns an Err if `r` is NaN or
+    /// infinite (since these can't be written as UXF text).
+    pub fn new_real(r: f64) -> Result<Self> {
+        if !r.is_finite() {
+            bail!(UxfError::new(
+                386,
+                format!("reals must be finite, got {}", r)
+            ));
+        }
+        Ok(Value::Real(r))
+    }
+
+    /// Returns an iterator over this value and every value nested inside
+    /// it, each paired with its path from this value. The first item is
+    /// this value itself with an empty path. Values are yielded depth
+    /// first in document order (except that map items are in canonical key
+    /// order); null values are skipped since there is no `Value` to yield.
+    pub fn iter_paths(
+        &self,
+    ) -> impl Iterator<Item = (Vec<PathSegment>, &Value)> {
+        PathIter { stack: vec![(vec![], self)] }
+    }
+
+    /// Calls the `Visitor` method that matches this value's type, and
+    /// for collections, recursively visits every nested value too. See
+    /// `Visitor` for the order in which methods are called.
+    pub fn accept(&self, v: &mut dyn Visitor) {
+        match self {
+            #[cfg(feature = "bigint")]
+            Value::BigInt(i) => v.visit_bigint(i),
+            Value::Bool(b) => v.visit_bool(*b),
+            Value::Bytes(b) => v.visit_bytes(b),
+            Value::Date(d) => v.visit_date(d),
+            Value::DateTime(dt) => v.visit_datetime(dt),
+            Value::Int(i) => v.visit_int(*i),
+            Value::List(lst) => {
+                v.visit_list(lst);
+                for value in lst.values() {
+                    accept_opt_value(value, v);
+                }
+                v.end_list(lst);
+            }
+            Value::Map(m) => {
+                v.visit_map(m);
+                for (key, value) in m.iter_sorted() {
+                    v.visit_key(key);
+                    accept_opt_value(value, v);
+                }
+                v.end_map(m);
+            }
+            Value::Real(r) => v.visit_real(*r),
+            Value::Str(s) => v.visit_str(s),
+            Value::Table(t) => {
+                v.visit_table(t);
+                for record in t.records() {
+                    for value in record {
+                        accept_opt_value(value, v);
+                    }
+                }
+                v.end_table(t);
+            }
+        }
+    }
+
+    /// Walks this value, calling the given `visitor`'s `on_` methods: for
+    /// collections, `on_list_start()` (etc.), then recursively each
+    /// nested value, then `on_list_end()` (etc.); and for scalars,
+    /// `on_scalar()`. See `Visitor` for the order in which methods are
+    /// called.
+    pub fn visit<V: Visitor>(&self, visitor: &mut V) {
+        match self {
+            Value::List(lst) => {
+                visitor.on_list_start(lst);
+                for value in lst.values() {
+                    visit_opt_value(value, visitor);
+                }
+                visitor.on_list_end(lst);
+            }
+            Value::Map(m) => {
+                visitor.on_map_start(m);
+                for (_, value) in m.iter_sorted() {
+                    visit_opt_value(value, visitor);
+                }
+                visitor.on_map_end(m);
+            }
+            Value::Table(t) => {
+                visitor.on_table_start(t);
+                for record in t.records() {
+                    for value in record {
+                        visit_opt_value(value, visitor);
+                    }
+                }
+                visitor.on_table_end(t);
+            }
+            _ => visitor.on_scalar(Some(self)),
+        }
+    }
+
+    // Interns the tclasses of every table in this value; see Uxf::intern().
+    pub(crate) fn intern(
+        &mut self,
+        registry: &mut HashMap<String, Vec<Arc<TClass>>>,
+    ) -> usize {
+        match self {
+            Value::List(lst) => lst
+                .values_mut()
+                .iter_mut()
+                .flatten()
+                .map(|value| value.intern(registry))
+                .sum(),
+            Value::Map(m) => m
+                .values_mut()
+                .flatten()
+                .map(|value| value.intern(registry))
+                .sum(),
+            Value::Table(t) => t.intern(registry),
+            _ => 0,
+        }
+    }
+
+    /// Drops redundant type declarations from this value and from every
+    /// value nested inside it.
+    ///
+    /// A type declaration is redundant if it carries no constraint because
+    /// there is nothing for it to constrain. So:
+    /// - a list's vtype is dropped if the list is empty or every value is
+    ///   null;
+    /// - a map's ktype and vtype are dropped if the map is empty;
+    /// - a map's vtype (but not its ktype) is dropped if every value is
+    ///   null.
+    ///
+    /// Table ttypes are never dropped since every table must have one.
+    pub fn simplify(&mut self) {
+        match self {
+            Value::List(lst) => {
+                for value in lst.values_mut().iter_mut().flatten() {
+                    value.simplify();
+                }
+                if lst.values().iter().all(|v| v.is_none()) {
+                    lst.set_vtype(None);
+                }
+            }
+            Value::Map(m) => {
+                for value in m.values_mut().flatten() {
+                    value.simplify();
+                }
+                if m.items().is_empty() {
+                    m.set_ktype(None);
+                }
+                if m.items().values().all(|v| v.is_none()) {
+                    m.set_vtype(None);
+                }
+            }
+            Value::Table(t) => {
+                for record in t.records_mut() {
+                    for value in record.iter_mut().flatten() {
+                        value.simplify();
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Normalizes this value and every value nested inside it into a
+    /// canonical form, so that equal values have identical in-memory
+    /// forms and serializations.
+    ///
+    /// Reals of `-0.0` become `0.0` and every map's keys are put into
+    /// canonical order (see `Key` and `Map::keys()`). If `lists` is
+    /// `ListOrder::Sort`, every list's values are sorted: nulls first,
+    /// then by vtype name (with a table's ttype as its vtype name), then
+    /// by value (with ints compared numerically, even if big ints, and
+    /// other collections compared by their UXF text). Table records are
+    /// never reordered since their order is usually significant.
+    pub fn canonicalize(&mut self, lists: ListOrder) {
+        match self {
+            Value::Real(r) if *r == 0.0 => *r = 0.0,
+            Value::List(lst) => {
+                for value in lst.values_mut().iter_mut().flatten() {
+                    value.canonicalize(lists);
+                }
+                if lists == ListOrder::Sort {
+                    lst.values_mut().sort_by(canonical_cmp);
+                }
+            }
+            Value::Map(m) => {
+                for value in m.values_mut().flatten() {
+                    value.canonicalize(lists);
+                }
+                m.sort_keys();
+            }
+            Value::Table(t) => {
+                for record in t.records_mut() {
+                    for value in record.iter_mut().flatten() {
+                        value.canonicalize(lists);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns true if this value is an empty list, map, table, str, or
+    /// bytes; otherwise returns false. Bools, dates, datetimes, ints, and
+    /// reals always have content so are never empty. (A table is empty if
+    /// it has no records.)
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Value::Bytes(b) => b.is_empty(),
+            Value::List(lst) => lst.values().is_empty(),
+            Value::Map(m) => m.items().is_empty(),
+            Value::Str(s) => s.is_empty(),
+            Value::Table(t) => t.records().is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a scalar (a bool, bytes, date,
+    /// datetime, int, real, or str); otherwise (i.e., for a list, map, or
+    /// table) returns false.
+    pub fn is_scalar(&self) -> bool {
+        !self.is_collection()
+    }
+
+    /// Returns true if this value is a collection (a list, map, or
+    /// table); otherwise returns false.
+    pub fn is_collection(&self) -> bool {
+        matches!(self, Value::List(_) | Value::Map(_) | Value::Table(_))
+    }
+
+    /// Returns true if this value may be used as a map key (i.e., it is
+    /// bytes, a date, a datetime, an int, or a str; see `Key`); otherwise
+    /// returns false. (Big ints can't be keys.)
+    pub fn can_be_key(&self) -> bool {
+        matches!(
+            self,
+            Value::Bytes(_)
+                | Value::Date(_)
+                | Value::DateTime(_)
+                | Value::Int(_)
+                | Value::Str(_)
+        )
+    }
+
+    /// Returns the sum of this value and `other` _or_ returns an Err if
+    /// either isn't numeric or if the addition overflows.
+    ///
+    /// Adding two ints gives an int; adding an int and a real, or two
+    /// reals, gives a real. (With the `bigint` feature, adding a
+    /// `BigInt` and an int or `BigInt` gives a `BigInt`.) Adding two ints
+    /// overflows if the sum is outside the `i64` range; adding finite
+    /// reals overflows if the sum is infinite (since UXF can't represent
+    /// it; see `new_real()`).
+    pub fn try_add(&self, other: &Value) -> Result<Value> {
+        let real = |a: f64, b: f64| {
+            let sum = a + b;
+            if !sum.is_finite() && a.is_finite() && b.is_finite() {
+                bail!(UxfError::new(
+                    380,
+                    format!("real overflow adding {} and {}", a, b)
+                ));
+            }
+            Ok(Value::Real(sum))
+        };
+        Ok(match (self, other) {
+            (Value::Int(a), Value::Int(b)) => match a.checked_add(*b) {
+                Some(i) => Value::Int(i),
+                None => bail!(UxfError::new(
+                    380,
+                    format!("int overflow adding {} and {}", a, b)
+                )),
+            },
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => Value::BigInt(a + b),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::Int(b)) => Value::BigInt(a + b),
+            #[cfg(feature = "bigint")]
+            (Value::Int(a), Value::BigInt(b)) => Value::BigInt(a + b),
+            (Value::Int(a), Value::Real(b)) => real(*a as f64, *b)?,
+            (Value::Real(a), Value::Int(b)) => real(*a, *b as f64)?,
+            (Value::Real(a), Value::Real(b)) => real(*a, *b)?,
+            _ => bail!(UxfError::new(
+                382,
+                format!(
+                    "can only add int or real values, got {} and {}",
+                    self.type_name(),
+                    other.type_name()
+                )
+            )),
+        })
+    }
+
+    /// Returns the name of this value's type, e.g., `int`, `str`, or
+    /// `table`. (Big ints are `int`s.) See also `opt_type_name()` for
+    /// values that may be null, and `vtype_name()` for a table's ttype.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => VTYPE_NAME_INT,
+            Value::Bool(_) => VTYPE_NAME_BOOL,
+            Value::Bytes(_) => VTYPE_NAME_BYTES,
+            Value::Date(_) => VTYPE_NAME_DATE,
+            Value::DateTime(_) => VTYPE_NAME_DATETIME,
+            Value::Int(_) => VTYPE_NAME_INT,
+            Value::List(_) => VTYPE_NAME_LIST,
+            Value::Map(_) => VTYPE_NAME_MAP,
+            Value::Real(_) => VTYPE_NAME_REAL,
+            Value::Str(_) => VTYPE_NAME_STR,
+            Value::Table(_) => VTYPE_NAME_TABLE,
+        }
+    }
+
+    /// Returns the vtype that this value has, i.e., its ttype if it is a
+    /// table, e.g., `Point`, and otherwise its `type_name()`.
+    pub fn vtype_name(&self) -> &str {
+        match self {
+            Value::Table(t) => t.tclass().ttype(),
+            _ => self.type_name(),
+        }
+    }
+
+    // Returns true if this value is acceptable where `vtype` is expected.
+    pub(crate) fn has_vtype(&self, vtype: &str) -> bool {
+        match self {
+            Value::Table(t) => {
+                vtype == VTYPE_NAME_TABLE || vtype == t.tclass().ttype()
+            }
+            _ => vtype == self.type_name(),
+        }
+    }
+
+    /// Returns a one-line description of this value of at most `max_len`
+    /// characters, suitable for log lines and tree views.
+    ///
+    /// Collections are described by their size rather than their content,
+    /// e.g., `list[100 items]`, `map[1 item]`, or `(Point 3 records)`.
+    /// Scalars are shown as UXF text with any newlines replaced by spaces.
+    /// If the description is longer than `max_len` it is truncated and
+    /// ends with `…` (and for strs, the closing `>` is kept if there's
+    /// room, e.g., `<hello wo…>`).
+    pub fn summary(&self, max_len: usize) -> String {
+        let plural = |n: usize, what: &str| {
+            format!("{} {}{}", n, what, if n == 1 { "" } else { "s" })
+        };
+        let text = match self {
+            Value::List(lst) => {
+                format!("list[{}]", plural(lst.values().len(), "item"))
+            }
+            Value::Map(m) => {
+                format!("map[{}]", plural(m.items().len(), "item"))
+            }
+            Value::Table(t) => format!(
+                "({} {})",
+                t.tclass().ttype(),
+                plural(t.records().len(), "record")
+            ),
+            _ => {
+                let mut s = String::new();
+                output::write_value(&mut s, self, &Format::default());
+                s.replace(['\n', '\r'], " ")
+            }
+        };
+        if text.chars().count() <= max_len {
+            return text;
+        }
+        if matches!(self, Value::Str(_)) && max_len >= 3 {
+            let mut s: String = text.chars().take(max_len - 2).collect();
+            s.push_str("…>");
+            s
+        } else if max_len > 0 {
+            let mut s: String = text.chars().take(max_len - 1).collect();
+            s.push('…');
+            s
+        } else {
+            String::new()
+        }
+    }
+
+    // Feeds this value's content into the given hasher such that equal
+    // values always produce equal hashes. (Map items are hashed
+    // independently of their order; collection comments aren't hashed.)
+    pub(crate) fn hash_into<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            #[cfg(feature = "bigint")]
+            Value::BigInt(i) => i.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::Date(d) => d.hash(state),
+            Value::DateTime(dt) => dt.hash(state),
+            Value::Int(i) => i.hash(state),
+            // 0.0 == -0.0 and NaN == NaN so each pair must hash the same
+            Value::Real(r) => {
+                let r = if *r == 0.0 {
+                    0.0
+                } else if r.is_nan() {
+                    f64::NAN
+                } else {
+                    *r
+                };
+                r.to_bits().hash(state)
+            }
+            Value::Str(s) => s.hash(state),
+            Value::List(lst) => {
+                lst.vtype().hash(state);
+                lst.values().len().hash(state);
+                for value in lst.values() {
+                    hash_opt_value(value, state);
+                }
+            }
+            Value::Map(m) => {
+                m.ktype().hash(state);
+                m.vtype().hash(state);
+                let mut items: u64 = 0;
+                for (key, value) in m.items() {
+                    let mut item_state = DefaultHasher::new();
+                    key.hash(&mut item_state);
+                    hash_opt_value(value, &mut item_state);
+                    items = items.wrapping_add(item_state.finish());
+                }
+                m.items().len().hash(state);
+                items.hash(state);
+            }
+            Value::Table(t) => t.content_hash().hash(state),
+        }
+    }
+}
+
+/// Specifies whether `Value::canonicalize()` sorts the values of lists
+/// (`Sort`) or keeps them in their current order (`Preserve`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListOrder {
+    Preserve,
+    Sort,
+}
+
+// Orders values for Value::canonicalize() and Table::dedup_by_key().
+pub(crate) fn canonical_cmp(
+    a: &Option<Value>,
+    b: &Option<Value>,
+) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(a), Some(b)) => canonical_cmp_values(a, b),
+    }
+}
+
+fn canonical_cmp_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Bytes(x), Value::Bytes(y)) => x.cmp(y),
+        (Value::Date(x), Value::Date(y)) => x.cmp(y),
+        (Value::DateTime(x), Value::DateTime(y)) => x.cmp(y),
+        (Value::Int(x), Value::Int(y)) => x.cmp(y),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(x), Value::BigInt(y)) => x.cmp(y),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(x), Value::Int(y)) => x.cmp(&(*y).into()),
+        #[cfg(feature = "bigint")]
+        (Value::Int(x), Value::BigInt(y)) => {
+            num_bigint::BigInt::from(*x).cmp(y)
+        }
+        (Value::Real(x), Value::Real(y)) => x.total_cmp(y),
+        (Value::Str(x), Value::Str(y)) => x.cmp(y),
+        _ => a.vtype_name().cmp(b.vtype_name()).then_with(|| {
+            let mut x = String::new();
+            let mut y = String::new();
+            output::write_value(&mut x, a, &Format::default());
+            output::write_value(&mut y, b, &Format::default());
+            x.cmp(&y)
+        }),
+    }
+}
+
+/// Returns the name of the given value's vtype as `Value::type_name()`
+/// does, or `null` if the value is `None`.
+pub fn opt_type_name(value: &Option<Value>) -> &'static str {
+    match value {
+        Some(value) => value.type_name(),
+        None => VALUE_NAME_NULL,
+    }
+}
+
+// Returns the message for a value that doesn't match the vtype of the
+// list or map (what) that it is being added to. If the vtype is one of the
+// collection vtypes the message says what kind of collection was expected
+// (and what kind of value was given).
+pub(crate) fn vtype_mismatch(
+    what: &str,
+    vtype: &str,
+    value: &Value,
+) -> String {
+    if ![VTYPE_NAME_LIST, VTYPE_NAME_MAP, VTYPE_NAME_TABLE].contains(&vtype)
+    {
+        return format!(
+            "expected a {} value of type {}, got {}",
+            what,
+            vtype,
+            value.vtype_name()
+        );
+    }
+    let got = match value {
+        Value::Table(t) => format!("a {} table", t.tclass().ttype()),
+        Value::List(_) | Value::Map(_) => {
+            format!("a {}", value.type_name())
+        }
+        _ => value.type_name().to_string(),
+    };
+    format!("expected a {} value that is a {}, got {}", what, vtype, got)
+}
+
+// Returns the type name that all the values share (a table's ttype for
+// tables), or None if there are no values or they differ.
+pub(crate) fn common_type_name<'a>(
+    mut values: impl Iterator<Item = &'a Value>,
+) -> Option<&'a str> {
+    let name = values.next()?.vtype_name();
+    values.all(|value| value.vtype_name() == name).then_some(name)
+}
+
+pub(crate) fn hash_opt_value<H: Hasher>(
+    value: &Option<Value>,
+    state: &mut H,
+) {
+    match value {
+        None => 0u8.hash(state),
+        Some(value) => {
+            1u8.hash(state);
+            value.hash_into(state);
+        }
+    }
+}
+
+/// Wraps a `Value` so that it can be used as the key of a `BTreeMap` (or
+/// anywhere else that needs `Ord`), e.g., `BTreeMap<ValueKey, usize>`.
+///
+/// ``ValueKey``s are ordered canonically (as `Value::canonicalize()` sorts
+/// list values): values of the same type by value, and values of
+/// different types by type name. Ints and big ints compare numerically.
+/// Reals are compared using `f64::total_cmp()`, so every real has a
+/// consistent place in the order: `-0.0` is less than `0.0`, and NaN is
+/// greater than infinity (and equal to itself).
+///
+/// (Clippy's `mutable_key_type` lint flags `ValueKey` keys because a
+/// `Table` caches its hash; but the cache doesn't affect the order.)
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use uxf::value::{Value, ValueKey};
+/// let mut counts = BTreeMap::new();
+/// for r in [2.5, -1.0, f64::NAN, 2.5] {
+///     *counts.entry(ValueKey(Value::Real(r))).or_insert(0) += 1;
+/// }
+/// assert_eq!(counts.len(), 3);
+/// assert_eq!(counts[&ValueKey(Value::Real(2.5))], 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ValueKey(pub Value);
+
+impl Ord for ValueKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        canonical_cmp_values(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for ValueKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ValueKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ValueKey {}
+
+impl From<Value> for ValueKey {
+    fn from(value: Value) -> Self {
+        ValueKey(value)
+    }
+}
+
+/// Identifies one step in the path from a value to a value nested inside
+/// it: an index into a `List`, a key into a `Map`, or a record index and
+/// field name into a `Table`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Index(usize),
+    Key(Key),
+    Cell(usize, String),
+}
+
+// Returns the path as text starting from `value`, e.g., `value{<a>}[2]`
+// or `value[0][1].x`.
+pub(crate) fn path_to_string(path: &[PathSegment]) -> String {
+    let mut s = "value".to_string();
+    for segment in path {
+        match segment {
+            PathSegment::Index(i) => s.push_str(&format!("[{}]", i)),
+            PathSegment::Key(key) => {
+                s.push('{');
+                output::write_key(&mut s, key);
+                s.push('}');
+            }
+            PathSegment::Cell(row, name) => {
+                s.push_str(&format!("[{}].{}", row, name))
+            }
+        }
+    }
+    s
+}
+
+fn accept_opt_value(value: &Option<Value>, v: &mut dyn Visitor) {
+    match value {
+        None => v.visit_null(),
+        Some(value) => value.accept(v),
+    }
+}
+
+fn visit_opt_value<V: Visitor>(value: &Option<Value>, visitor: &mut V) {
+    match value {
+        None => visitor.on_scalar(None),
+        Some(value) => value.visit(visitor),
+    }
+}
+
+struct PathIter<'a> {
+    stack: Vec<(Vec<PathSegment>, &'a Value)>,
+}
+
+impl<'a> Iterator for PathIter<'a> {
+    type Item = (Vec<PathSegment>, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, value) = self.stack.pop()?;
+        let mut children = vec![];
+        match value {
+            Value::List(lst) => {
+                for (i, v) in lst.values().iter().enumerate() {
+                    if let Some(v) = v {
+                        children.push((PathSegment::Index(i), v));
+                    }
+                }
+            }
+            Value::Map(m) => {
+                for (k, v) in m.iter_sorted() {
+                    if let Some(v) = v {
+                        children.push((PathSegment::Key(k.clone()), v));
+                    }
+                }
+            }
+            Value::Table(t) => {
+                let fields = t.tclass().fields();
+                for (row, record) in t.records().iter().enumerate() {
+                    for (field, v) in fields.iter().zip(record) {
+                        if let Some(v) = v {
+                            let name = field.name().to_string();
+                            children
+                                .push((PathSegment::Cell(row, name), v));
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+        // Pushed in reverse so that they are popped in document order
+        for (segment, child) in children.into_iter().rev() {
+            let mut child_path = path.clone();
+            child_path.push(segment);
+            self.stack.push((child_path, child));
+        }
+        Some((path, value))
+    }
+}
+
+impl PartialEq for Value {
+    /// Returns true if the two values are of the same type and have equal
+    /// content, comparing collections deeply (including their vtypes and
+    /// comments); otherwise returns false.
+    ///
+    /// Reals are compared as `f64`s except that NaN is considered equal to
+    /// NaN (whatever its bit pattern), so that every value equals itself
+    /// and `Value` can be `Eq`. (As with `f64`, `0.0 == -0.0`.) Note that
+    /// an int never equals a real, even if they're numerically equal.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Date(a), Value::Date(b)) => a == b,
+            (Value::DateTime(a), Value::DateTime(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Real(a), Value::Real(b)) => {
+                a == b || (a.is_nan() && b.is_nan())
+            }
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Table(a), Value::Table(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(r: f64) -> Self {
+        Value::Real(r)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Self {
+        Value::Bytes(b)
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(d: NaiveDate) -> Self {
+        Value::Date(d)
+    }
+}
+
+impl From<NaiveDateTime> for Value {
+    fn from(dt: NaiveDateTime) -> Self {
+        Value::DateTime(dt)
+    }
+}
+
+// Implements TryFrom<Value> for a Rust type that corresponds to exactly
+// one Value variant.
+macro_rules! impl_try_from_value {
+    ($t:ty, $variant:ident, $vtype:expr) => {
+        impl TryFrom<Value> for $t {
+            type Error = UxfError;
+
+            fn try_from(
+                value: Value,
+            ) -> std::result::Result<Self, UxfError> {
+                match value {
+                    Value::$variant(v) => Ok(v),
+                    _ => Err(UxfError::new(
+                        392,
+                        format!(
+                            "expected {}, got {}",
+                            $vtype,
+                            value.type_name()
+                        ),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_value!(bool, Bool, VTYPE_NAME_BOOL);
+impl_try_from_value!(Vec<u8>, Bytes, VTYPE_NAME_BYTES);
+impl_try_from_value!(NaiveDate, Date, VTYPE_NAME_DATE);
+impl_try_from_value!(NaiveDateTime, DateTime, VTYPE_NAME_DATETIME);
+impl_try_from_value!(i64, Int, VTYPE_NAME_INT);
+impl_try_from_value!(f64, Real, VTYPE_NAME_REAL);
+impl_try_from_value!(String, Str, VTYPE_NAME_STR);
+
 impl From<Scalar> for Value {
     fn from(scalar: Scalar) -> Self {
         match scalar {
@@ -38,12 +972,37 @@ impl From<Key> for Value {
         match key {
             Key::Bytes(b) => Value::Bytes(b),
             Key::Date(d) => Value::Date(d),
+            Key::DateTime(dt) => Value::DateTime(dt),
             Key::Int(i) => Value::Int(i),
             Key::Str(s) => Value::Str(s),
         }
     }
 }
 
+/// Converts a bytes, date, datetime, int, or str `Value` into the
+/// corresponding `Key` (see `Value::can_be_key()`) _or_ returns an Err.
+impl TryFrom<Value> for Key {
+    type Error = UxfError;
+
+    fn try_from(value: Value) -> std::result::Result<Self, UxfError> {
+        match value {
+            Value::Bytes(b) => Ok(Key::Bytes(b)),
+            Value::Date(d) => Ok(Key::Date(d)),
+            Value::DateTime(dt) => Ok(Key::DateTime(dt)),
+            Value::Int(i) => Ok(Key::Int(i)),
+            Value::Str(s) => Ok(Key::Str(s)),
+            _ => Err(UxfError::new(
+                356,
+                format!(
+                    "a map key must be bytes, date, datetime, int, or str, \
+                     got {}",
+                    value.type_name()
+                ),
+            )),
+        }
+    }
+}
+
 impl From<Collection> for Value {
     fn from(collection: Collection) -> Self {
         match collection {
@@ -61,14 +1020,34 @@ pub enum Scalar {
     Real(f64),
 }
 
-#[derive(Clone, Debug)]
+/// A map key. Keys are ordered canonically: first by type (bytes, date,
+/// datetime, int, str), then by value: bytes lexicographically, dates and
+/// datetimes chronologically, ints numerically, and strs by Unicode code
+/// point. This is the order in which map items are written.
+///
+/// (The variants are declared in type order so `Ord` is derived.)
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Key {
     Bytes(Vec<u8>),
     Date(NaiveDate),
+    DateTime(NaiveDateTime),
     Int(i64),
     Str(String),
 }
 
+impl Key {
+    /// Returns the name of this key's ktype, e.g., `int` or `str`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Key::Bytes(_) => VTYPE_NAME_BYTES,
+            Key::Date(_) => VTYPE_NAME_DATE,
+            Key::DateTime(_) => VTYPE_NAME_DATETIME,
+            Key::Int(_) => VTYPE_NAME_INT,
+            Key::Str(_) => VTYPE_NAME_STR,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Collection {
     List(List),
@@ -0,0 +1,111 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::list::List;
+use crate::map::Map;
+use crate::table::Table;
+use crate::value::{Key, Value};
+use chrono::prelude::*;
+
+/// Receives callbacks for the values visited by `Value::accept()` or by
+/// `Value::visit()`.
+///
+/// Every method has a default do-nothing implementation, so implementors
+/// need only override the methods they care about.
+///
+/// Both walks are depth first in document order (with map items in
+/// canonical key order), and descend into every list's values, map's
+/// values, and table's records.
+///
+/// `accept()` calls the `visit_` methods: first `visit_list()`,
+/// `visit_map()`, or `visit_table()` is called, then each of the
+/// collection's values is visited (each map value is preceded by a call
+/// to `visit_key()`), and finally `end_list()`, `end_map()`, or
+/// `end_table()` is called. Scalars are visited by the method for their
+/// type, and nulls by `visit_null()`.
+///
+/// `visit()` calls the `on_` methods: `on_list_start()`,
+/// `on_map_start()`, or `on_table_start()`, then each of the collection's
+/// values, then `on_list_end()`, `on_map_end()`, or `on_table_end()`.
+/// Every scalar value is passed to `on_scalar()`, as is every null (as
+/// `None`). (Map keys aren't values so aren't passed to `on_scalar()`.)
+///
+/// For example, this visitor counts the ints and lists in a value:
+///
+/// ```
+/// use uxf::list::List;
+/// use uxf::value::Value;
+/// use uxf::visitor::Visitor;
+///
+/// #[derive(Default)]
+/// struct Counter {
+///     ints: usize,
+///     lists: usize,
+/// }
+///
+/// impl Visitor for Counter {
+///     fn on_scalar(&mut self, value: Option<&Value>) {
+///         if let Some(Value::Int(_)) = value {
+///             self.ints += 1;
+///         }
+///     }
+///
+///     fn on_list_start(&mut self, _lst: &List) {
+///         self.lists += 1;
+///     }
+/// }
+///
+/// let uxo = uxf::parser::parse("uxf 1.0\n[1 <a> [2 3.5 {<b> 4}]]\n")
+///     .unwrap();
+/// let mut counter = Counter::default();
+/// uxo.value().visit(&mut counter);
+/// assert_eq!((counter.ints, counter.lists), (3, 2));
+/// ```
+pub trait Visitor {
+    #[cfg(feature = "bigint")]
+    fn visit_bigint(&mut self, _i: &num_bigint::BigInt) {}
+
+    fn visit_bool(&mut self, _b: bool) {}
+
+    fn visit_bytes(&mut self, _b: &[u8]) {}
+
+    fn visit_date(&mut self, _d: &NaiveDate) {}
+
+    fn visit_datetime(&mut self, _dt: &NaiveDateTime) {}
+
+    fn visit_int(&mut self, _i: i64) {}
+
+    fn visit_null(&mut self) {}
+
+    fn visit_real(&mut self, _r: f64) {}
+
+    fn visit_str(&mut self, _s: &str) {}
+
+    fn visit_list(&mut self, _lst: &List) {}
+
+    fn end_list(&mut self, _lst: &List) {}
+
+    fn visit_map(&mut self, _m: &Map) {}
+
+    fn visit_key(&mut self, _key: &Key) {}
+
+    fn end_map(&mut self, _m: &Map) {}
+
+    fn visit_table(&mut self, _t: &Table) {}
+
+    fn end_table(&mut self, _t: &Table) {}
+
+    fn on_scalar(&mut self, _value: Option<&Value>) {}
+
+    fn on_list_start(&mut self, _lst: &List) {}
+
+    fn on_list_end(&mut self, _lst: &List) {}
+
+    fn on_map_start(&mut self, _m: &Map) {}
+
+    fn on_map_end(&mut self, _m: &Map) {}
+
+    fn on_table_start(&mut self, _t: &Table) {}
+
+    fn on_table_end(&mut self, _t: &Table) {}
+}
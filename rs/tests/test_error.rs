@@ -0,0 +1,76 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+#[cfg(test)]
+mod tests {
+    use uxf::error::UxfError;
+    use uxf::field::Field;
+    use uxf::parser::parse;
+    use uxf::tclass::TClass;
+
+    #[test]
+    fn t_uxf_error() {
+        let err = TClass::new_fieldless("yes", None).unwrap_err();
+        let uxf_err = err.downcast_ref::<UxfError>().unwrap();
+        assert_eq!(uxf_err.code(), 604);
+        assert_eq!(
+            uxf_err.message(),
+            "type names may not be yes or no got yes"
+        );
+        assert_eq!(uxf_err.lineno(), None);
+        assert_eq!(
+            err.to_string(),
+            "#604:type names may not be yes or no got yes"
+        );
+        for (name, code) in
+            [("", 600), ("1st", 602), ("a-b", 608), ("int", 304)]
+        {
+            let err = Field::new_anyvtype(name).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<UxfError>().unwrap().code(),
+                code
+            );
+        }
+        // Parse errors carry their line number
+        let err = parse("uxf 1.0\n=Pair a b\n=Pair a c\n[]\n").unwrap_err();
+        let uxf_err = err.downcast_ref::<UxfError>().unwrap();
+        assert_eq!(uxf_err.code(), 528);
+        assert_eq!(uxf_err.lineno(), Some(3));
+        assert_eq!(
+            uxf_err.to_string(),
+            "#528:conflicting ttype definitions for Pair (line 3, col 2)"
+        );
+        let err = parse("uxf 1.0\n[1 2\n<unterminated\n").unwrap_err();
+        let uxf_err = err.downcast_ref::<UxfError>().unwrap();
+        assert_eq!(uxf_err.lineno(), Some(3));
+        assert_eq!(err.to_string(), uxf_err.to_string());
+    }
+
+    #[test]
+    fn t_uxf_error_codes() {
+        let mut uxo = parse("uxf 1.0\n=Point x y\n(Point 1 2)\n").unwrap();
+        let code = |err: anyhow::Error| {
+            err.downcast_ref::<UxfError>().map(|err| err.code())
+        };
+        assert_eq!(
+            code(uxo.rename_ttype("Size", "Area").unwrap_err()),
+            Some(696)
+        );
+        assert_eq!(
+            code(uxo.set_custom(Some("a\nb")).unwrap_err()),
+            Some(114)
+        );
+        assert_eq!(
+            code(uxf::output::Format::with_indent("x").unwrap_err()),
+            Some(720)
+        );
+        let mut lst = uxf::list::List::new(Some("int"), None).unwrap();
+        assert_eq!(
+            code(lst.push(Some("x".into())).unwrap_err()),
+            Some(376)
+        );
+        let mut m = uxf::map::Map::new(Some("int"), None, None).unwrap();
+        let key = uxf::value::Key::Str("k".to_string());
+        assert_eq!(code(m.insert(key, None).unwrap_err()), Some(384));
+    }
+}
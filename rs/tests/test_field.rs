@@ -136,4 +136,29 @@ mod tests {
             check_error_code(&e.to_string(), code, vtype);
         }
     }
+
+    #[test]
+    fn t_field_type_compatible_with() {
+        let a = Field::new("a", VTYPE_NAME_INT).unwrap();
+        let b = Field::new("b", VTYPE_NAME_INT).unwrap();
+        let c = Field::new("a", VTYPE_NAME_STR).unwrap();
+        let d = Field::new("d", "Point").unwrap();
+        let e = Field::new_anyvtype("e").unwrap();
+        let f = Field::new_anyvtype("a").unwrap();
+        // Matching vtypes (names are ignored)
+        assert!(a.type_compatible_with(&a));
+        assert!(a.type_compatible_with(&b));
+        assert!(b.type_compatible_with(&a));
+        assert!(d.type_compatible_with(&d));
+        // anyvtype on either or both sides
+        assert!(a.type_compatible_with(&e));
+        assert!(e.type_compatible_with(&a));
+        assert!(d.type_compatible_with(&f));
+        assert!(e.type_compatible_with(&f));
+        // Differing vtypes
+        assert!(!a.type_compatible_with(&c));
+        assert!(!c.type_compatible_with(&a));
+        assert!(!a.type_compatible_with(&d));
+        assert!(!d.type_compatible_with(&c));
+    }
 }
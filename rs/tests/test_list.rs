@@ -3,10 +3,327 @@
 
 #[cfg(test)]
 mod tests {
+    use uxf::constants::VTYPES;
+    use uxf::error::UxfError;
     use uxf::list::List;
+    use uxf::parser::parse;
+    use uxf::value::Value;
+    use uxf::Uxf;
 
     #[test]
     fn t_list() {
-        // TODO
+        let mut lst = List::new(Some("int"), Some("ids")).unwrap();
+        assert!(lst.is_empty());
+        lst.push(Some(Value::Int(1))).unwrap();
+        lst.push(None).unwrap();
+        lst.push(Some(Value::Int(-3))).unwrap();
+        let err =
+            lst.push(Some(Value::Str("four".to_string()))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value of type int, got str"
+        );
+        assert!(lst.push(Some(Value::Real(4.0))).is_err());
+        assert_eq!(lst.len(), 3);
+        assert!(!lst.is_empty());
+        assert_eq!(lst.get(0), Some(&Some(Value::Int(1))));
+        assert_eq!(lst.get(1), Some(&None));
+        assert_eq!(lst.get(3), None);
+        assert_eq!(
+            Uxf::new(Value::List(lst)).to_string(),
+            "uxf 1.0\n[#<ids> int 1 ? -3]\n"
+        );
+        // Untyped lists accept anything
+        let mut lst = List::new(None, None).unwrap();
+        lst.push(Some(Value::Str("one".to_string()))).unwrap();
+        lst.push(Some(Value::Bool(true))).unwrap();
+        assert_eq!(lst.len(), 2);
+        // Tables must match the ttype (or any ttype for "table")
+        let uxo = parse("uxf 1.0\n=P x\n=Q y\n[(P 1) (Q 2)]\n").unwrap();
+        let tables: Vec<Value> = uxo
+            .value()
+            .iter_paths()
+            .skip(1)
+            .step_by(2)
+            .map(|(_, v)| v.clone())
+            .collect();
+        let mut lst = List::new(Some("P"), None).unwrap();
+        lst.push(Some(tables[0].clone())).unwrap();
+        let err = lst.push(Some(tables[1].clone())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value of type P, got Q"
+        );
+        let mut lst = List::new(Some("table"), None).unwrap();
+        lst.push(Some(tables[0].clone())).unwrap();
+        lst.push(Some(tables[1].clone())).unwrap();
+        // Invalid vtypes are rejected
+        for vtype in ["", "1st", "yes"] {
+            assert!(List::new(Some(vtype), None).is_err(), "{}", vtype);
+        }
+    }
+
+    #[test]
+    fn t_list_shrink_to_fit() {
+        let text = format!(
+            "uxf 1.0\n[{}]\n",
+            (0..1000).map(|i| i.to_string()).collect::<Vec<_>>().join(" ")
+        );
+        let mut lst: List = match parse(&text).unwrap().value() {
+            Value::List(lst) => lst.clone(),
+            _ => panic!("expected a list"),
+        };
+        let mut lst2 = lst.clone();
+        lst.retain(|v| matches!(v, Some(Value::Int(i)) if i % 100 == 0));
+        let capacity = lst.capacity();
+        assert!(capacity >= 1000, "{}", capacity);
+        lst.shrink_to_fit();
+        assert!(lst.capacity() < capacity);
+        assert!(lst.capacity() >= 10);
+        let expected =
+            parse("uxf 1.0\n[0 100 200 300 400 500 600 700 800 900]\n")
+                .unwrap();
+        assert_eq!(&Value::List(lst), expected.value());
+        // Shrinking an unfiltered list loses nothing
+        let lst3 = lst2.clone();
+        lst2.shrink_to_fit();
+        assert_eq!(lst2, lst3);
+    }
+
+    #[test]
+    fn t_list_new_vtypes() {
+        // Built-in vtypes
+        for vtype in VTYPES {
+            let lst = List::new(Some(vtype), None).unwrap();
+            assert!(lst.is_empty());
+        }
+        // Valid custom vtypes (ttypes)
+        for vtype in ["Point", "_private", "a_y_47ĕặæ_", "Int"] {
+            assert!(List::new(Some(vtype), None).is_ok(), "{}", vtype);
+        }
+        // Invalid vtypes
+        for (vtype, code) in [
+            ("1bad", 602),
+            ("", 600),
+            ("yes", 604),
+            ("bad-name", 608),
+            ("null", 304),
+        ] {
+            let err = List::new(Some(vtype), None).unwrap_err();
+            let uxf_err = err.downcast_ref::<UxfError>().unwrap();
+            assert_eq!(uxf_err.code(), code, "{}", vtype);
+        }
+        let err = List::new(Some("1bad"), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#602:type names must start with a letter or underscore, got \
+            1bad"
+        );
+    }
+
+    fn list_of(text: &str) -> List {
+        match parse(&format!("uxf 1.0\n{}\n", text)).unwrap().value() {
+            Value::List(lst) => lst.clone(),
+            _ => panic!("expected a list"),
+        }
+    }
+
+    fn text_of(lst: List) -> String {
+        Uxf::new(Value::List(lst)).to_string()
+    }
+
+    #[test]
+    fn t_list_add() {
+        let ints = list_of("[int 1 2]") + list_of("[int 3 ?]");
+        assert_eq!(text_of(ints), "uxf 1.0\n[int 1 2 3 ?]\n");
+        let mixed = list_of("[int 1 2]") + list_of("[str <three>]");
+        assert_eq!(text_of(mixed), "uxf 1.0\n[1 2 <three>]\n");
+        let mixed =
+            list_of("[#<first> int 1]") + list_of("[#<second> 2.5]");
+        assert_eq!(text_of(mixed), "uxf 1.0\n[#<first> 1 2.5]\n");
+        let empty = List::new(None, None).unwrap();
+        let lst = empty + list_of("[#<only> 1]");
+        assert_eq!(text_of(lst), "uxf 1.0\n[#<only> 1]\n");
+    }
+
+    #[test]
+    fn t_list_splice() {
+        let mut lst = list_of("[int 1 2 3 4 5]");
+        let removed = lst
+            .splice(1..3, vec![Some(Value::Int(20)), None, Some(30.into())])
+            .unwrap();
+        assert_eq!(removed, [Some(Value::Int(2)), Some(Value::Int(3))]);
+        assert_eq!(text_of(lst.clone()), "uxf 1.0\n[int 1 20 ? 30 4 5]\n");
+        let removed = lst.splice(..2, vec![]).unwrap();
+        assert_eq!(removed.len(), 2);
+        lst.splice(3.., vec![Some(6.into())]).unwrap();
+        lst.splice(0..=0, vec![]).unwrap();
+        assert_eq!(text_of(lst.clone()), "uxf 1.0\n[int 30 4 6]\n");
+        // Errors leave the list unchanged
+        let err = lst.splice(1..2, vec![Some("x".into())]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value of type int, got str"
+        );
+        let err = lst.splice(2..5, vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#394:can't splice 2..5 of a list of 3 values"
+        );
+        assert_eq!(text_of(lst), "uxf 1.0\n[int 30 4 6]\n");
+    }
+
+    #[test]
+    fn t_list_push_widening() {
+        let mut lst = list_of("[int 1 2]");
+        lst.push_widening(Some(Value::Int(3))).unwrap();
+        assert_eq!(lst.vtype(), Some("int"));
+        lst.push_widening(Some(Value::Real(3.5))).unwrap();
+        assert_eq!(
+            text_of(lst.clone()),
+            "uxf 1.0\n[real 1.0 2.0 3.0 3.5]\n"
+        );
+        lst.push_widening(None).unwrap();
+        lst.push_widening(Some(Value::Real(4.5))).unwrap();
+        let err = lst.push_widening(Some(Value::Int(5))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value of type real, got int"
+        );
+        let mut lst = list_of("[int 1 2]");
+        lst.push_widening(Some(Value::Real(3.5))).unwrap();
+        assert_eq!(text_of(lst), "uxf 1.0\n[real 1.0 2.0 3.5]\n");
+        // Only int lists are widened
+        let mut lst = list_of("[str <a>]");
+        let err = lst.push_widening(Some(Value::Real(3.5))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value of type str, got real"
+        );
+        // Ints beyond ±2**53 can't be widened exactly
+        let mut lst = list_of("[int 1 9007199254740993]");
+        let err = lst.push_widening(Some(Value::Real(3.5))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#398:can't widen an int list to real: 9007199254740993 can't \
+             be represented exactly as a real"
+        );
+        assert_eq!(text_of(lst), "uxf 1.0\n[int 1 9007199254740993]\n");
+    }
+
+    #[test]
+    fn t_list_tighten_vtype() {
+        let mut lst = list_of("[1 2 ? 3]");
+        lst.tighten_vtype();
+        assert_eq!(lst.vtype(), Some("int"));
+        assert_eq!(lst.to_string(), "[int 1 2 ? 3]");
+        for text in ["[1 <two> 3]", "[1 2.0]", "[]", "[? ?]"] {
+            let mut lst = list_of(text);
+            lst.tighten_vtype();
+            assert_eq!(lst.vtype(), None, "{}", text);
+            assert_eq!(lst.to_string(), text);
+        }
+        // A typed list keeps its vtype
+        let mut lst = list_of("[real]");
+        lst.tighten_vtype();
+        assert_eq!(lst.vtype(), Some("real"));
+        // Tables give their ttype and nested lists aren't changed
+        let mut lst = list_of("=P x\n[(P 1) (P [2 3])]");
+        lst.tighten_vtype();
+        assert_eq!(lst.to_string(), "[P (P 1) (P [2 3])]");
+    }
+
+    #[test]
+    fn t_list_iter() {
+        let uxo = parse("uxf 1.0\n[int 1 ? 20 ? ? 300]\n").unwrap();
+        let Value::List(lst) = uxo.value() else {
+            panic!("expected a list");
+        };
+        let int = |value: &Option<Value>| match value {
+            Some(Value::Int(i)) => *i,
+            _ => 0,
+        };
+        assert_eq!(lst.iter().map(int).sum::<i64>(), 321);
+        let mut total = 0;
+        let mut nulls = 0;
+        for value in lst {
+            match value {
+                Some(Value::Int(i)) => total += i,
+                None => nulls += 1,
+                _ => panic!("expected an int or null"),
+            }
+        }
+        assert_eq!((total, nulls), (321, 3));
+        assert_eq!(lst.iter().len(), lst.len());
+        assert_eq!(lst.iter().next_back(), Some(&Some(Value::Int(300))));
+        let empty = List::new(None, None).unwrap();
+        assert_eq!(empty.iter().next(), None);
+    }
+
+    #[test]
+    fn t_list_collection_vtypes() {
+        let uxo = parse("uxf 1.0\n=P x\n[[1] {<k> 2} (P 3) 4]\n").unwrap();
+        let Value::List(values) = uxo.value() else {
+            panic!("expected a list");
+        };
+        let [list, map, table, int] =
+            [0, 1, 2, 3].map(|i| values.get(i).unwrap().clone());
+        let mut lists = List::new(Some("list"), None).unwrap();
+        let err = lists.push(map.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value that is a list, got a map"
+        );
+        let err = lists.push(table.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value that is a list, got a P table"
+        );
+        let err = lists.push(int.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value that is a list, got int"
+        );
+        lists.push(list.clone()).unwrap();
+        lists.push(None).unwrap();
+        let mut maps = List::new(Some("map"), None).unwrap();
+        maps.push(map.clone()).unwrap();
+        let err = maps.push(list.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value that is a map, got a list"
+        );
+        let mut tables = List::new(Some("table"), None).unwrap();
+        tables.push(table.clone()).unwrap();
+        let err = tables.push(map.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#376:expected a list value that is a table, got a map"
+        );
+        assert_eq!(
+            Uxf::new(Value::List(maps)).to_string(),
+            "uxf 1.0\n[map {<k> 2}]\n"
+        );
+    }
+
+    #[test]
+    fn t_list_set_comment() {
+        let mut lst = List::new(Some("int"), None).unwrap();
+        assert_eq!(lst.comment(), None);
+        lst.set_comment(Some("ids"), None).unwrap();
+        assert_eq!(lst.comment(), Some("ids"));
+        assert_eq!(lst.to_string(), "[#<ids> int]");
+        lst.set_comment(None, Some(2)).unwrap();
+        assert_eq!(lst.comment(), None);
+        assert!(!lst.to_string().contains('#'));
+        let err = lst.set_comment(Some("ids"), Some(2)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 2 characters long, got 3 \
+            characters"
+        );
+        assert_eq!(lst.comment(), None);
+        lst.set_comment(Some("id"), Some(2)).unwrap();
+        assert_eq!(lst.comment(), Some("id"));
     }
 }
@@ -3,10 +3,405 @@
 
 #[cfg(test)]
 mod tests {
+    use uxf::list::List;
     use uxf::map::Map;
+    use uxf::parser::parse;
+    use uxf::uxf::Uxf;
+    use uxf::value::{Key, Value};
 
     #[test]
     fn t_map() {
-        // TODO
+        let text = "uxf 1.0\n{#<ages> str int <Ann> 30 <Bob> ?}\n";
+        let mut m: Map = match parse(text).unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        assert_eq!(m.ktype(), Some("str"));
+        assert_eq!(m.vtype(), Some("int"));
+        assert_eq!(m.comment(), Some("ages"));
+        assert_eq!(m.len(), 2);
+        assert!(!m.is_empty());
+        let ann = Key::Str("Ann".to_string());
+        let cy = Key::Str("Cy".to_string());
+        assert!(m.contains_key(&ann));
+        assert!(!m.contains_key(&cy));
+        assert_eq!(m.get(&ann), Some(&Some(Value::Int(30))));
+        // Inserting returns the previous value (if any)
+        assert_eq!(
+            m.insert(cy.clone(), Some(Value::Int(7))).unwrap(),
+            None
+        );
+        assert_eq!(
+            m.insert(ann.clone(), Some(Value::Int(31))).unwrap(),
+            Some(Some(Value::Int(30)))
+        );
+        assert_eq!(
+            m.insert(cy.clone(), None).unwrap(),
+            Some(Some(7.into()))
+        );
+        assert_eq!(m.len(), 3);
+        let err = m.insert(Key::Int(1), Some(Value::Int(1))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#384:can't insert a key of type int into a map with ktype str"
+        );
+        let err = m.insert(cy.clone(), Some("x".into())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#396:expected a map value of type int, got str"
+        );
+        // A collection vtype only accepts that kind of collection
+        let mut lists = Map::new(Some("str"), Some("list"), None).unwrap();
+        let inner = Map::new(None, None, None).unwrap();
+        let err = lists.insert(ann.clone(), Some(Value::Map(inner)));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "#396:expected a map value that is a list, got a map"
+        );
+        let inner = List::new(None, None).unwrap();
+        lists.insert(ann.clone(), Some(Value::List(inner))).unwrap();
+        // Removing returns the value (if the key was present)
+        assert_eq!(m.remove(&cy), Some(None));
+        assert_eq!(m.remove(&cy), None);
+        assert_eq!(m.remove(&ann), Some(Some(Value::Int(31))));
+        assert_eq!(m.len(), 1);
+        assert_eq!(
+            Uxf::new(Value::Map(m)).to_string(),
+            "uxf 1.0\n{#<ages> str int <Bob> ?}\n"
+        );
+        let m: Map = match parse("uxf 1.0\n{}\n").unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        assert!(m.is_empty());
+        assert_eq!((m.ktype(), m.vtype(), m.comment()), (None, None, None));
+    }
+
+    #[test]
+    fn t_map_shrink_to_fit() {
+        let text = format!(
+            "uxf 1.0\n{{int str {}}}\n",
+            (0..1000)
+                .map(|i| format!("{} <v{}>", i, i))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let mut m: Map = match parse(&text).unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        m.retain(|k, _| matches!(k, Key::Int(i) if *i < 3));
+        let capacity = m.capacity();
+        assert!(capacity >= 1000, "{}", capacity);
+        m.shrink_to_fit();
+        assert!(m.capacity() < capacity);
+        assert!(m.capacity() >= 3);
+        let expected =
+            parse("uxf 1.0\n{int str 0 <v0> 1 <v1> 2 <v2>}\n").unwrap();
+        assert_eq!(&Value::Map(m), expected.value());
+    }
+
+    #[test]
+    fn t_map_comments() {
+        // A map-level comment roundtrips
+        let text = "uxf 1.0\n{#<settings> str int <a> 1}\n";
+        assert_eq!(parse(text).unwrap().to_string(), text);
+        // UXF has no per-entry comments
+        for text in [
+            "uxf 1.0\n{str int <a> #<entry> 1}\n",
+            "uxf 1.0\n{str int <a> 1 #<entry> <b> 2}\n",
+        ] {
+            let err = parse(text).unwrap_err();
+            assert!(err.to_string().starts_with("#440:"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn t_map_set() {
+        let mut m: Map = match parse("uxf 1.0\n{str}\n").unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        assert!(m.is_set());
+        for key in ["red", "green", "blue", "red"] {
+            m.insert_key(Key::Str(key.to_string())).unwrap();
+        }
+        assert!(m.is_set());
+        let expected =
+            parse("uxf 1.0\n{str <blue> ? <green> ? <red> ?}\n").unwrap();
+        assert_eq!(&Value::Map(m.clone()), expected.value());
+        let err = m.insert_key(Key::Int(1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#384:can't insert a key of type int into a map with ktype \
+            str"
+        );
+        let m: Map =
+            match parse("uxf 1.0\n{<a> ? <b> 1}\n").unwrap().value() {
+                Value::Map(m) => m.clone(),
+                _ => panic!("expected a map"),
+            };
+        assert!(!m.is_set());
+    }
+
+    #[test]
+    fn t_map_get() {
+        let uxo = parse(
+            "uxf 1.0\n[{<one> 1 <two> ? <three> [3]} \
+            {1 <a> 2 <b>} {2022-10-16 yes (:FF00:) no}]\n",
+        )
+        .unwrap();
+        let maps: Vec<Map> = uxo
+            .value()
+            .iter_paths()
+            .filter_map(|(_, v)| match v {
+                Value::Map(m) => Some(m.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(maps[0].get_str("one"), Some(&Some(Value::Int(1))));
+        assert_eq!(maps[0].get_str("two"), Some(&None)); // null
+        assert!(matches!(
+            maps[0].get_str("three"),
+            Some(Some(Value::List(_)))
+        ));
+        assert_eq!(maps[0].get_str("four"), None);
+        assert_eq!(maps[0].get_int(1), None); // wrong key type
+        assert_eq!(
+            maps[1].get_int(2),
+            Some(&Some(Value::Str("b".to_string())))
+        );
+        assert_eq!(maps[1].get_int(3), None);
+        assert_eq!(
+            maps[1].get(&Key::Int(1)),
+            Some(&Some(Value::Str("a".to_string())))
+        );
+        let date = chrono::NaiveDate::from_ymd_opt(2022, 10, 16).unwrap();
+        assert_eq!(maps[2].get_date(date), Some(&Some(Value::Bool(true))));
+        assert_eq!(
+            maps[2].get_bytes(&[0xFF, 0]),
+            Some(&Some(Value::Bool(false)))
+        );
+        assert_eq!(maps[2].get_bytes(&[0xFF]), None);
+    }
+
+    #[test]
+    fn t_map_get_typed() {
+        let uxo = parse(
+            "uxf 1.0\n{<host> <localhost> <port> 8080 <debug> yes \
+            <ratio> 0.5 <started> 2022-10-16 <proxy> ?}\n",
+        )
+        .unwrap();
+        let config = match uxo.value() {
+            Value::Map(m) => m,
+            _ => panic!("expected a map"),
+        };
+        assert_eq!(config.get_typed::<i64>("port").unwrap(), Some(8080));
+        assert_eq!(
+            config.get_typed::<String>("host").unwrap().as_deref(),
+            Some("localhost")
+        );
+        assert_eq!(config.get_typed::<bool>("debug").unwrap(), Some(true));
+        assert_eq!(config.get_typed::<f64>("ratio").unwrap(), Some(0.5));
+        assert_eq!(
+            config.get_typed::<chrono::NaiveDate>("started").unwrap(),
+            chrono::NaiveDate::from_ymd_opt(2022, 10, 16)
+        );
+        // Missing and null values are both None
+        assert_eq!(config.get_typed::<i64>("timeout").unwrap(), None);
+        assert_eq!(config.get_typed::<String>("proxy").unwrap(), None);
+        let port =
+            config.get_typed::<i64>("timeout").unwrap().unwrap_or(30);
+        assert_eq!(port, 30);
+        // Mismatches are errors
+        let err = config.get_typed::<i64>("host").unwrap_err();
+        assert_eq!(err.to_string(), "#392:expected int, got str");
+        let err = config.get_typed::<f64>("port").unwrap_err();
+        assert_eq!(err.to_string(), "#392:expected real, got int");
+    }
+
+    #[test]
+    fn t_map_keys() {
+        let text = "uxf 1.0\n{<c> 1 <a> 2 3 <x> <b> 4}\n";
+        let mut m: Map = match parse(text).unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        let s = |s: &str| Key::Str(s.to_string());
+        // Keys are in insertion order (reinserting doesn't move a key)
+        assert_eq!(
+            m.keys().collect::<Vec<_>>(),
+            vec![&s("c"), &s("a"), &Key::Int(3), &s("b")]
+        );
+        m.insert(s("d"), None).unwrap();
+        m.insert(s("c"), None).unwrap();
+        m.remove(&s("a"));
+        m.retain(|key, _| key != &Key::Int(3));
+        assert_eq!(
+            m.keys().collect::<Vec<_>>(),
+            vec![&s("c"), &s("b"), &s("d")]
+        );
+        // The order isn't significant
+        let mut other = m.clone();
+        other.remove(&s("c"));
+        other.insert(s("c"), None).unwrap();
+        assert_eq!(other, m);
+    }
+
+    #[test]
+    fn t_map_tighten_types() {
+        let map_of = |text: &str| -> Map {
+            match parse(&format!("uxf 1.0\n{}\n", text)).unwrap().value() {
+                Value::Map(m) => m.clone(),
+                _ => panic!("expected a map"),
+            }
+        };
+        for (text, expected) in [
+            ("{<a> 1 <b> ?}", "{str int <a> 1 <b> ?}"),
+            ("{<a> 1 <b> <x>}", "{str <a> 1 <b> <x>}"),
+            ("{1 <a> <b> <c>}", "{1 <a> <b> <c>}"),
+            ("{<a> ? <b> ?}", "{str <a> ? <b> ?}"),
+            ("{}", "{}"),
+            ("{date 2022-10-01 1}", "{date int 2022-10-01 1}"),
+            ("{int real 1 2.0}", "{int real 1 2.0}"),
+        ] {
+            let mut m = map_of(text);
+            m.tighten_types();
+            assert_eq!(m.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn t_map_new() {
+        for ktype in ["bytes", "date", "datetime", "int", "str"] {
+            let m = Map::new(Some(ktype), None, None).unwrap();
+            assert_eq!(m.ktype(), Some(ktype));
+        }
+        let m = Map::new(Some("str"), Some("Point"), Some("pts")).unwrap();
+        assert_eq!(m.vtype(), Some("Point"));
+        assert_eq!(m.to_string(), "{#<pts> str Point}");
+        for (ktype, vtype, code) in [
+            (Some("real"), None, "#610:"),
+            (Some("bool"), None, "#610:"),
+            (Some("Point"), None, "#610:"),
+            (None, Some("int"), "#282:"),
+            (Some("str"), Some("1bad"), "#"),
+        ] {
+            let err = Map::new(ktype, vtype, None).unwrap_err();
+            assert!(err.to_string().starts_with(code), "{}", err);
+        }
+    }
+
+    #[test]
+    fn t_map_insert_mixed_keys() {
+        let mut m = Map::new(Some("int"), Some("str"), None).unwrap();
+        let s = |s: &str| Some(Value::Str(s.to_string()));
+        m.insert(Key::Int(2), s("two")).unwrap();
+        let err = m.insert(Key::Str("x".to_string()), s("x")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#384:can't insert a key of type str into a map with ktype \
+            int"
+        );
+        m.insert(Key::Int(1), s("one")).unwrap();
+        assert!(m.insert(Key::Bytes(vec![1]), None).is_err());
+        let err = m.insert(Key::Int(3), Some(Value::Int(3))).unwrap_err();
+        assert!(err.to_string().starts_with("#396:"), "{}", err);
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.to_string(), "{int str 1 <one> 2 <two>}");
+    }
+
+    #[test]
+    fn t_map_iter_sorted() {
+        let text = "uxf 1.0\n{<c> 1 <a> 2 3 <x> <b> 4}\n";
+        let m: Map = match parse(text).unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        let s = |s: &str| Key::Str(s.to_string());
+        assert_eq!(
+            m.iter_sorted().map(|(key, _)| key).collect::<Vec<_>>(),
+            vec![&Key::Int(3), &s("a"), &s("b"), &s("c")]
+        );
+        let (_, value) = m.iter_sorted().next().unwrap();
+        assert_eq!(value, &Some(Value::Str("x".to_string())));
+    }
+
+    #[test]
+    fn t_map_iter() {
+        let text = "uxf 1.0\n{<c> 1 <a> 2 3 <x> <b> ?}\n";
+        let mut m: Map = match parse(text).unwrap().value() {
+            Value::Map(m) => m.clone(),
+            _ => panic!("expected a map"),
+        };
+        let s = |s: &str| Key::Str(s.to_string());
+        let items: Vec<_> = m.iter().collect();
+        assert_eq!(
+            items,
+            vec![
+                (&s("c"), &Some(Value::Int(1))),
+                (&s("a"), &Some(Value::Int(2))),
+                (&Key::Int(3), &Some(Value::Str("x".to_string()))),
+                (&s("b"), &None),
+            ]
+        );
+        assert!(m.iter().map(|(key, _)| key).eq(m.keys()));
+        m.remove(&s("a"));
+        m.insert(s("d"), Some(Value::Int(4))).unwrap();
+        assert_eq!(
+            m.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            vec![&s("c"), &Key::Int(3), &s("b"), &s("d")]
+        );
+        let empty = Map::new(None, None, None).unwrap();
+        assert_eq!(empty.iter().next(), None);
+    }
+
+    #[test]
+    fn t_map_ktype() {
+        for ktype in ["bytes", "date", "datetime", "int", "str"] {
+            assert!(Map::new(Some(ktype), None, None).is_ok());
+            let text = format!("uxf 1.0\n{{{}}}\n", ktype);
+            assert_eq!(parse(&text).unwrap().to_string(), text);
+        }
+        for ktype in ["real", "bool", "Point", "list", "table"] {
+            let expected = format!(
+                "#610:a map's ktype must be one of bytes date datetime \
+                int str, got {}",
+                ktype
+            );
+            let err = Map::new(Some(ktype), None, None).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+            let text =
+                format!("uxf 1.0\n=Point x y\n[{{{} 1 2}}]\n", ktype);
+            let err = parse(&text).unwrap_err();
+            if ktype == "Point" {
+                // A ttype isn't a type keyword so is read as a (bad) key
+                assert!(err.to_string().starts_with("#294:"), "{}", err);
+            } else {
+                assert_eq!(
+                    err.to_string(),
+                    format!("{} (line 3, col 3)", expected)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn t_map_set_comment() {
+        let mut m = Map::new(Some("str"), None, None).unwrap();
+        assert_eq!(m.comment(), None);
+        m.set_comment(Some("ids"), None).unwrap();
+        assert_eq!(m.comment(), Some("ids"));
+        assert_eq!(m.to_string(), "{#<ids> str}");
+        m.set_comment(None, Some(2)).unwrap();
+        assert_eq!(m.comment(), None);
+        assert!(!m.to_string().contains('#'));
+        let err = m.set_comment(Some("ids"), Some(2)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 2 characters long, got 3 \
+            characters"
+        );
+        assert_eq!(m.comment(), None);
     }
 }
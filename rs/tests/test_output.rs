@@ -0,0 +1,205 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+#[cfg(test)]
+mod tests {
+    use uxf::list::List;
+    use uxf::output::{Format, Indent};
+    use uxf::parser::parse;
+    use uxf::test_utils::value_to_str;
+    use uxf::uxf::Uxf;
+    use uxf::value::Value;
+
+    #[test]
+    fn t_align_columns() {
+        let uxo = parse(
+            "uxf 1.0\n=Item name:str qty:int price:real\n\
+            (Item <pen> 5 1.25 <notebook> 120 ? <ink> ? 13.5)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=Item name:str qty:int price:real\n\
+            (Item <pen> 5 1.25 <notebook> 120 ? <ink> ? 13.5)\n"
+        );
+        let format = Format { align_columns: true, ..Default::default() };
+        let text = uxo.to_string_with_format(&format).unwrap();
+        assert_eq!(
+            text,
+            "uxf 1.0\n=Item name:str qty:int price:real\n(Item\n\
+            <pen>      5   1.25\n\
+            <notebook> 120 ?\n\
+            <ink>      ?   13.5\n)\n"
+        );
+        assert_eq!(parse(&text).unwrap(), uxo);
+    }
+
+    #[test]
+    fn t_indent() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x:int y:int\n[#<data> 1 <a> {<k> [2 3] \
+            <j> 4} (Point 1 2 3 4) (Point 5 6) []]\n",
+        )
+        .unwrap();
+        let format = Format::with_indent("  ").unwrap();
+        assert_eq!(format.indent, Some(Indent::Spaces(2)));
+        let text = uxo.to_string_with_format(&format).unwrap();
+        assert_eq!(
+            text,
+            "uxf 1.0\n=Point x:int y:int\n[#<data>\n  1\n  <a>\n  {\n    \
+            <j> 4\n    <k> [2 3]\n  }\n  (Point\n    1 2\n    3 4\n  )\n  \
+            (Point 5 6)\n  []\n]\n"
+        );
+        assert_eq!(parse(&text).unwrap(), uxo);
+        let format = Format::with_indent("\t").unwrap();
+        assert_eq!(format.indent, Some(Indent::Tab));
+        let text = uxo.to_string_with_format(&format).unwrap();
+        assert_eq!(
+            text,
+            "uxf 1.0\n=Point x:int y:int\n[#<data>\n\t1\n\t<a>\n\t{\n\t\t\
+            <j> 4\n\t\t<k> [2 3]\n\t}\n\t(Point\n\t\t1 2\n\t\t3 4\n\t)\n\t\
+            (Point 5 6)\n\t[]\n]\n"
+        );
+        assert_eq!(parse(&text).unwrap(), uxo);
+        // Aligned tables are indented too
+        let format = Format { align_columns: true, ..format };
+        let text = uxo.to_string_with_format(&format).unwrap();
+        assert!(text.contains("(Point\n\t\t1 2\n\t\t3 4\n\t)"), "{}", text);
+        assert_eq!(parse(&text).unwrap(), uxo);
+        // Collections of scalars stay on one line
+        let uxo = parse("uxf 1.0\n[1 2 3]\n").unwrap();
+        let format = Format::with_indent("    ").unwrap();
+        assert_eq!(
+            uxo.to_string_with_format(&format).unwrap(),
+            "uxf 1.0\n[1 2 3]\n"
+        );
+        // Empty means new lines but no indentation
+        let uxo = parse("uxf 1.0\n[[1] [2]]\n").unwrap();
+        let format = Format::with_indent("").unwrap();
+        assert_eq!(
+            uxo.to_string_with_format(&format).unwrap(),
+            "uxf 1.0\n[\n[1]\n[2]\n]\n"
+        );
+        for (indent, expected) in [
+            ("--", "#720:indent may only contain whitespace, got \"--\""),
+            (" x", "#720:indent may only contain whitespace, got \" x\""),
+            (
+                " \t",
+                "#722:indent must be at most 32 spaces or a single tab, \
+                got \" \\t\"",
+            ),
+            (
+                "\t\t",
+                "#722:indent must be at most 32 spaces or a single tab, \
+                got \"\\t\\t\"",
+            ),
+        ] {
+            let err = Format::with_indent(indent).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+        assert!(Format::with_indent(&" ".repeat(32)).is_ok());
+        assert!(Format::with_indent(&" ".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn t_pretty() {
+        let format = Format::pretty();
+        assert_eq!(format.indent, Some(Indent::Spaces(2)));
+        assert_eq!(format.wrap_width, Some(76));
+        let text = std::fs::read_to_string("../testdata/t30.uxf").unwrap();
+        let uxo = parse(&text).unwrap();
+        let expected =
+            std::fs::read_to_string("../testdata/expected/t30-pretty.uxf")
+                .unwrap();
+        let pretty = uxo.to_string_with_format(&format).unwrap();
+        assert_eq!(pretty, expected);
+        assert_eq!(parse(&pretty).unwrap(), uxo);
+        // Nested collections indent cumulatively and wrap beneath their
+        // collection; empty collections stay on one line
+        let uxo = parse(
+            "uxf 1.0\n=Point x:int y:int label:str\n[[] {} [1 2 3 4 5 6 \
+            7 8 9 10 11 12] {<a> [<alpha> <beta> <gamma> <delta>] <b> []} \
+            (Point 1 2 <the first point> 3 4 <the second point>) (Point)]\n",
+        )
+        .unwrap();
+        let format = Format { wrap_width: Some(24), ..Format::pretty() };
+        let text = uxo.to_string_with_format(&format).unwrap();
+        assert_eq!(
+            text,
+            "uxf 1.0\n=Point x:int y:int\n  label:str\n[\n  []\n  {}\n  \
+            [1 2 3 4 5 6 7 8 9 10\n    11 12]\n  {\n    <a> [<alpha> <beta>\n      \
+            <gamma> <delta>]\n    <b> []\n  }\n  (Point\n    1 2\n      \
+            <the first point>\n    3 4\n      <the second point>\n  )\n  (Point)\n]\n"
+        );
+        assert_eq!(parse(&text).unwrap(), uxo);
+        // Items wider than the wrap width are written whole
+        let uxo =
+            parse("uxf 1.0\n[<a long str that won't fit> 1]\n").unwrap();
+        let format = Format { wrap_width: Some(10), ..Format::pretty() };
+        assert_eq!(
+            uxo.to_string_with_format(&format).unwrap(),
+            "uxf 1.0\n[<a long str that won't fit>\n  1]\n"
+        );
+    }
+
+    #[test]
+    fn t_display_collections() {
+        let text = "uxf 1.0\n=Point x:int y:int\n{#<m> str list \
+                    <a> [#<l> int 1 ? 3] <b> [{<k> (Point 1 2 3 4)}] \
+                    <c> []}\n";
+        let uxo = parse(text).unwrap();
+        let Value::Map(m) = uxo.value() else {
+            panic!("expected a map");
+        };
+        let expected = "{#<m> str list <a> [#<l> int 1 ? 3] \
+                        <b> [{<k> (Point 1 2 3 4)}] <c> []}";
+        assert_eq!(m.to_string(), expected);
+        assert_eq!(value_to_str(uxo.value().clone()), expected);
+        let Some(Some(Value::List(lst))) = m.get_str("a") else {
+            panic!("expected a list");
+        };
+        assert_eq!(lst.to_string(), "[#<l> int 1 ? 3]");
+        let Some(Some(Value::List(lst))) = m.get_str("b") else {
+            panic!("expected a list");
+        };
+        let Some(Some(Value::Map(inner))) = lst.get(0) else {
+            panic!("expected a map");
+        };
+        let Some(Some(Value::Table(t))) = inner.get_str("k") else {
+            panic!("expected a table");
+        };
+        assert_eq!(t.to_string(), "(Point 1 2 3 4)");
+        // The output is valid UXF
+        let reparsed =
+            parse(&format!("uxf 1.0\n=Point x:int y:int\n{}\n", m))
+                .unwrap();
+        assert_eq!(reparsed, uxo);
+    }
+
+    #[test]
+    fn t_output_non_finite_reals() {
+        for (r, text) in [
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "NaN"),
+        ] {
+            let mut lst = List::new(Some("real"), None).unwrap();
+            lst.push(Some(Value::Real(1.5))).unwrap();
+            lst.push(Some(Value::Real(r))).unwrap();
+            let uxo = Uxf::new(Value::List(lst));
+            let err =
+                uxo.to_string_with_format(&Format::default()).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "#386:value[1]: reals must be finite, got {}",
+                    text
+                )
+            );
+            // to_string() writes them anyway, but they can't be read back
+            let written = uxo.to_string();
+            assert_eq!(written, format!("uxf 1.0\n[real 1.5 {}]\n", text));
+            assert!(parse(&written).is_err());
+        }
+    }
+}
@@ -0,0 +1,877 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use uxf::error::UxfError;
+    use uxf::field::make_fields;
+    use uxf::parser::{
+        parse, parse_header, parse_partial, parse_with_options,
+        parse_with_schema, ParseOptions, Parser,
+    };
+    use uxf::tclass::TClass;
+    use uxf::util::normalize_name;
+    use uxf::value::{Key, PathSegment, Value};
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn t_parse_bigint() {
+        let text = "uxf 1.0\n[123456789012345678901234567890 -7 \
+                    -123456789012345678901234567890]\n";
+        let uxo = parse(text).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        uxf::test_utils::assert_roundtrip(&uxo);
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn t_parse_bigint() {
+        let text = "uxf 1.0\n[123456789012345678901234567890]\n";
+        let err = parse(text).unwrap_err();
+        assert!(err.to_string().starts_with("#220:"), "{}", err);
+    }
+
+    #[test]
+    fn t_parse_header() {
+        // The body is deliberately invalid to show that it isn't parsed.
+        let text = "uxf 1.0 MyApp 1.2\n#<schema>\n=Point x:int y:int\n\
+                    =#<enum> Ready\n=Pair first second\n\
+                    [1 2 <unterminated\n";
+        assert!(parse(text).is_err());
+        let (custom, tclasses) = parse_header(text).unwrap();
+        assert_eq!(custom, "MyApp 1.2");
+        assert_eq!(
+            tclasses,
+            vec![
+                TClass::new(
+                    "Pair",
+                    make_fields(&[("first", ""), ("second", "")]).unwrap(),
+                    None
+                )
+                .unwrap(),
+                TClass::new(
+                    "Point",
+                    make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+                    None
+                )
+                .unwrap(),
+                TClass::new_fieldless("Ready", Some("enum")).unwrap(),
+            ]
+        );
+        let (custom, tclasses) = parse_header("uxf 1.0\n{}\n").unwrap();
+        assert!(custom.is_empty());
+        assert!(tclasses.is_empty());
+    }
+
+    #[test]
+    fn t_parse_leading_zeros_and_plus_signs() {
+        let lenient = ParseOptions { lenient: true, ..Default::default() };
+        for text in ["[007]", "[-007]", "[00]", "[01.5]", "[+007]"] {
+            let text = format!("uxf 1.0\n{}\n", text);
+            let err = parse(&text).unwrap_err();
+            assert!(err.to_string().starts_with("#212:"), "{}", err);
+        }
+        for (text, expected) in [
+            ("[007 -007 00 01.5 +007]", "[7 -7 0 1.5 7]"),
+            (
+                "[+5 -5 +2.5 0 0.5 -0.0 2022-01-02]",
+                "[5 -5 2.5 0 0.5 -0.0 2022-01-02]",
+            ),
+        ] {
+            let text = format!("uxf 1.0\n{}\n", text);
+            let expected = format!("uxf 1.0\n{}\n", expected);
+            let uxo = parse_with_options(&text, &lenient).unwrap();
+            assert_eq!(uxo.to_string(), expected);
+        }
+        // Signs are valid UXF so are accepted in strict mode too
+        let uxo = parse("uxf 1.0\n[+5 +2.5 -5]\n").unwrap();
+        assert_eq!(uxo.to_string(), "uxf 1.0\n[5 2.5 -5]\n");
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn t_parse_numbers_locale_independent() {
+        // These locales use , as the decimal separator; they must make no
+        // difference (even if they aren't installed)
+        for locale in ["de_DE.UTF-8", "fr_FR.UTF-8", "C"] {
+            std::env::set_var("LC_ALL", locale);
+            std::env::set_var("LC_NUMERIC", locale);
+            let uxo = parse("uxf 1.0\n[3.14 -2.5e3 1000 0.5]\n").unwrap();
+            assert_eq!(
+                uxo.to_string(),
+                "uxf 1.0\n[3.14 -2500.0 1000 0.5]\n"
+            );
+            let values: Vec<Value> = uxo
+                .value()
+                .iter_paths()
+                .skip(1)
+                .map(|(_, v)| v.clone())
+                .collect();
+            assert_eq!(values[0], Value::Real(3.14));
+            assert_eq!(values[1], Value::Real(-2500.0));
+            assert_eq!(values[2], Value::Int(1000));
+            for text in ["[3,14]", "[1,000]"] {
+                let text = format!("uxf 1.0\n{}\n", text);
+                let err = parse(&text).unwrap_err();
+                assert!(err.to_string().starts_with("#170:"), "{}", err);
+            }
+        }
+        std::env::remove_var("LC_ALL");
+        std::env::remove_var("LC_NUMERIC");
+    }
+
+    #[test]
+    fn t_parse_on_str() {
+        // Turns UUID-shaped strs into 16 bytes
+        fn uuid(s: &str) -> Option<Value> {
+            let parts: Vec<&str> = s.split('-').collect();
+            let lens: Vec<usize> = parts.iter().map(|p| p.len()).collect();
+            if lens != [8, 4, 4, 4, 12] {
+                return None;
+            }
+            let hex = parts.concat();
+            let mut bytes = vec![];
+            for i in (0..hex.len()).step_by(2) {
+                bytes.push(u8::from_str_radix(&hex[i..i + 2], 16).ok()?);
+            }
+            Some(Value::Bytes(bytes))
+        }
+        let options = ParseOptions {
+            on_str: Some(Box::new(uuid)),
+            ..Default::default()
+        };
+        let text = "uxf 1.0\n{<67e55044-10b1-426f-9247-bb680e5fe0c8> \
+                    [<67e55044-10b1-426f-9247-bb680e5fe0c8> <not-a-uuid> \
+                    <67e55044-10b1-426f-9247-bb680e5fe0cX>]}\n";
+        let uxo = parse_with_options(text, &options).unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n{<67e55044-10b1-426f-9247-bb680e5fe0c8> \
+            [(:67E5504410B1426F9247BB680E5FE0C8:) <not-a-uuid> \
+            <67e55044-10b1-426f-9247-bb680e5fe0cX>]}\n"
+        );
+        // The default is to leave strs alone
+        let uxo = parse(text).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        assert_eq!(
+            format!("{:?}", options),
+            "ParseOptions { lenient: false, record_spans: false, \
+            normalize_names: false, max_comment_len: None, \
+            coerce_bools: false, on_str: Some(\"Fn\"), on_warning: None }"
+        );
+    }
+
+    #[test]
+    fn t_parse_partial() {
+        let text = "uxf 1.0 Log\n=Point x:int y:int\n\
+                    [<start> {<a> 1 <b> [2 3]} (Point 1 2 3 4) [5 6 <seven>]]\n";
+        // Complete text isn't truncated
+        let (uxo, truncated) = parse_partial(text);
+        assert!(!truncated);
+        assert_eq!(uxo.unwrap(), parse(text).unwrap());
+        for (end, expected) in [
+            // Inside a str inside a nested list
+            (
+                text.len() - 5,
+                "[<start> {<a> 1 <b> [2 3]} (Point 1 2 3 4) [5 6]]",
+            ),
+            // Just after a value inside a list
+            (
+                text.len() - 12,
+                "[<start> {<a> 1 <b> [2 3]} (Point 1 2 3 4) [5]]",
+            ),
+            // Inside a table record
+            (text.len() - 19, "[<start> {<a> 1 <b> [2 3]} (Point 1 2)]"),
+            // After a map key
+            (text.find("<b>").unwrap() + 3, "[<start> {<a> 1}]"),
+            // Inside a list inside a map
+            (text.find("3]").unwrap(), "[<start> {<a> 1 <b> [2]}]"),
+            // Just after the root list begins
+            (text.find("<start>").unwrap(), "[]"),
+        ] {
+            let (uxo, truncated) = parse_partial(&text[..end]);
+            assert!(truncated, "{}", end);
+            let expected =
+                format!("uxf 1.0 Log\n=Point x:int y:int\n{}\n", expected);
+            assert_eq!(uxo.unwrap(), parse(&expected).unwrap());
+        }
+        // Complete but invalid, or ending outside any collection, isn't
+        // truncated
+        for text in [
+            "uxf 1.0\n[1 ]]\n",
+            "uxf 1.0\n[1] [2]\n",
+            "uxf 1.0\n[1] <two\n",
+            "uxf 1.0\n1 2\n",
+            "uxf 1.0\n=Point x:int",
+            "uxf 1.0\n",
+            "uxf 1.0 Log",
+            "uxf 2.0\n[1 2\n",
+            "",
+        ] {
+            let (uxo, truncated) = parse_partial(text);
+            assert!(uxo.is_none(), "{:?}", text);
+            assert!(!truncated, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn t_parse_with_schema() {
+        let point = TClass::new(
+            "Point",
+            make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        let schema = [point];
+        let uxo =
+            parse_with_schema("uxf 1.0\n(Point 1 2)\n", &schema).unwrap();
+        let expected =
+            parse("uxf 1.0\n=Point x:int y:int\n(Point 1 2)\n").unwrap();
+        assert_eq!(uxo, expected);
+        assert!(parse("uxf 1.0\n(Point 1 2)\n").is_err());
+        // Identical in-file definitions are harmless
+        let text = "uxf 1.0\n=Point x:int y:int\n[(Point 1 2)]\n";
+        assert_eq!(
+            parse_with_schema(text, &schema).unwrap(),
+            parse(text).unwrap()
+        );
+        // Conflicting ones aren't
+        let err = parse_with_schema(
+            "uxf 1.0\n=Point x:real y:real\n(Point 1.5 2.5)\n",
+            &schema,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#528:conflicting ttype definitions for Point (line 2, col 2)"
+        );
+        // And neither are in-file duplicates
+        let err = parse("uxf 1.0\n=Pair a b\n=Pair a c\n[]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#528:conflicting ttype definitions for Pair (line 3, col 2)"
+        );
+    }
+
+    #[test]
+    fn t_parser() {
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&warnings);
+        let mut parser = Parser::new(ParseOptions {
+            lenient: true,
+            on_warning: Some(Box::new(move |warning: &UxfError| {
+                sink.borrow_mut().push(warning.code())
+            })),
+            ..Default::default()
+        });
+        assert!(parser.options().lenient);
+        let point = TClass::new(
+            "Point",
+            make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        parser.add_tclass(point.clone()).unwrap();
+        parser.add_tclass(point.clone()).unwrap(); // harmless duplicate
+        assert_eq!(parser.tclasses(), [&point]);
+        let other = TClass::new(
+            "Point",
+            make_fields(&[("x", "real")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        let err = parser.add_tclass(other).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#528:conflicting ttype definitions for Point"
+        );
+        // The same options and tclasses apply to every document
+        let uxo = parser.parse("uxf 1.0\n(Point 01 2)\n").unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=Point x:int y:int\n(Point 1 2)\n"
+        );
+        let uxo = parser.parse("uxf 1.0\n[(Point 3 4) [5").unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=Point x:int y:int\n[(Point 3 4) [5]]\n"
+        );
+        assert_eq!(*warnings.borrow(), [414, 414]);
+        // The defaults are strict with no predefined tclasses
+        let parser = Parser::default();
+        assert!(parser.parse("uxf 1.0\n(Point 1 2)\n").is_err());
+        assert!(parser.parse("uxf 1.0\n[007]\n").is_err());
+        assert_eq!(
+            parser.parse("uxf 1.0\n[1 2]\n").unwrap(),
+            parse("uxf 1.0\n[1 2]\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn t_parse_header_errors() {
+        for (text, expected) in [
+            ("", "#110:missing UXF file header or empty file (line 1)"),
+            (
+                "uxf 1.0",
+                "#110:missing UXF file header or empty file (line 1)",
+            ),
+            ("UXF 1.0\n[]\n", "#130:not a UXF file (line 1)"),
+            ("[1 2]\n", "#130:not a UXF file (line 1)"),
+            (
+                "uxf\n[]\n",
+                "#151:failed to read UXF file version number (line 1)",
+            ),
+            (
+                "uxf x.y\n[]\n",
+                "#151:failed to read UXF file version number (line 1)",
+            ),
+            ("uxf 9.5\n[]\n", "#141:version 9.5 > current 1.0 (line 1)"),
+        ] {
+            let err = parse(text).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn t_parse_version() {
+        let uxo = parse("uxf 1.0\n[]\n").unwrap();
+        assert_eq!(uxo.version(), 1.0);
+        assert_eq!(uxo.custom(), None);
+        let uxo = parse("uxf 1.0 My Custom Format\n[]\n").unwrap();
+        assert_eq!(uxo.version(), 1.0);
+        assert_eq!(uxo.custom(), Some("My Custom Format"));
+        assert_eq!(uxo.to_string(), "uxf 1.0 My Custom Format\n[]\n");
+        let uxo = parse("uxf 0.9\n[]\n").unwrap();
+        assert_eq!(uxo.version(), 0.9);
+        assert_eq!(uxo.to_string(), "uxf 1.0\n[]\n");
+        assert_eq!(uxo, parse("uxf 1.0\n[]\n").unwrap());
+        let (uxo, _) = parse_partial("uxf 1\n[1 2");
+        assert_eq!(uxo.unwrap().version(), 1.0);
+        for (text, expected) in [
+            ("uxfx 1.0\n[]\n", "#130:not a UXF file (line 1)"),
+            (
+                "uxf NaN\n[]\n",
+                "#151:failed to read UXF file version number (line 1)",
+            ),
+            (
+                "uxf -1.0\n[]\n",
+                "#151:failed to read UXF file version number (line 1)",
+            ),
+            ("uxf 2.0\n[]\n", "#141:version 2 > current 1.0 (line 1)"),
+        ] {
+            let err = parse(text).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn t_parse_comment_order() {
+        // A collection's comment comes first, then its type(s)
+        let text = "uxf 1.0\n=P x\n{#<m> str list <a> [#<l> int 1 2] \
+                    <b> (#<t> P 3)}\n";
+        let uxo = parse(text).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        uxf::test_utils::assert_roundtrip(&uxo);
+        let list = "list's comment must come before its vtype, e.g., \
+                    [#<c> int ...]";
+        let map = "map's comment must come before its ktype and vtype, \
+                   e.g., {#<c> str int ...}";
+        let table = "table's comment must come before its ttype, e.g., \
+                     (#<c> Point ...)";
+        for (text, lineno, column, what) in [
+            ("[int #<c> 1 2]", 3, 6, list),
+            ("{str #<c> int <a> 1}", 3, 6, map),
+            ("{str int #<c> <a> 1}", 3, 10, map),
+            ("(P #<c> 1)", 3, 4, table),
+            ("[int\n  #<c> 1]", 4, 3, list),
+        ] {
+            let text = format!("uxf 1.0\n=P x\n{}\n", text);
+            let err = parse(&text).unwrap_err();
+            let err = err.downcast_ref::<UxfError>().unwrap();
+            assert_eq!(err.code(), 444, "{}", err);
+            assert_eq!(err.lineno(), Some(lineno), "{}", err);
+            assert_eq!(err.column(), Some(column), "{}", err);
+            assert_eq!(err.message(), format!("a {}", what));
+        }
+    }
+
+    #[test]
+    fn t_parse_multiple_values() {
+        let text = "uxf 1.0\n=P x\n[1 2]\n[3 4] {<k> 5}\n(P 6)\n";
+        let err = parse(text).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#404:expected end of data after the map, list, or table, got \
+             ListBegin (line 4, col 1)"
+        );
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&warnings);
+        let options = ParseOptions {
+            lenient: true,
+            record_spans: true,
+            on_warning: Some(Box::new(move |warning: &UxfError| {
+                sink.borrow_mut().push(warning.to_string())
+            })),
+            ..Default::default()
+        };
+        let uxo = parse_with_options(text, &options).unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=P x\n[[1 2] [3 4] {<k> 5} (P 6)]\n"
+        );
+        assert_eq!(
+            *warnings.borrow(),
+            ["#406:expected one map, list, or table, got several: \
+              wrapped in an implicit list (line 4, col 1)"]
+        );
+        let span = |path: &[PathSegment]| &text[uxo.span_of(path).unwrap()];
+        assert_eq!(span(&[]), "[1 2]\n[3 4] {<k> 5}\n(P 6)");
+        assert_eq!(span(&[PathSegment::Index(0)]), "[1 2]");
+        assert_eq!(
+            span(&[PathSegment::Index(0), PathSegment::Index(1)]),
+            "2"
+        );
+        assert_eq!(span(&[PathSegment::Index(1)]), "[3 4]");
+        assert_eq!(span(&[PathSegment::Index(3)]), "(P 6)");
+        // Only collections are wrapped
+        let err = parse_with_options("uxf 1.0\n[1] [2] 3\n", &options)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("#404:"), "{}", err);
+        // A single value isn't wrapped
+        warnings.borrow_mut().clear();
+        let uxo = parse_with_options("uxf 1.0\n[1]\n", &options).unwrap();
+        assert_eq!(uxo.to_string(), "uxf 1.0\n[1]\n");
+        assert!(warnings.borrow().is_empty());
+    }
+
+    #[test]
+    fn t_parse_long_line_columns() {
+        // Columns are counted incrementally, so a long line is fast
+        let values = "1 ".repeat(50_000);
+        let text = format!("uxf 1.0\n[{}é @]\n", values);
+        let err = parse(&text).unwrap_err();
+        let err = err.downcast_ref::<UxfError>().unwrap();
+        assert_eq!(err.code(), 170, "{}", err);
+        assert_eq!(err.lineno(), Some(2));
+        assert_eq!(err.column(), Some(values.len() + 4));
+        let text = format!("uxf 1.0\n[<a\nb> {}@]\n", values);
+        let err = parse(&text).unwrap_err();
+        let err = err.downcast_ref::<UxfError>().unwrap();
+        assert_eq!(err.lineno(), Some(3));
+        assert_eq!(err.column(), Some(values.len() + 4));
+    }
+
+    #[test]
+    fn t_parse_error_positions() {
+        // Columns count chars (not bytes) and restart after newlines
+        // inside strs
+        for (text, code, lineno, column) in [
+            ("uxf 1.0\n[<café> <naïve>\n  <日本> @ 1]\n", 170, 3, 8),
+            ("uxf 1.0\n[<a\nbc> % 1]\n", 170, 3, 5),
+            ("uxf 1.0\n[1 <ü>\n   <x> 0012]\n", 212, 3, 8),
+            ("uxf 1.0\n{<ключ> 1 <é> 2]\n", 294, 2, 16),
+        ] {
+            let err = parse(text).unwrap_err();
+            let err = err.downcast_ref::<UxfError>().unwrap();
+            assert_eq!(err.code(), code, "{}", err);
+            assert_eq!(err.lineno(), Some(lineno), "{}", err);
+            assert_eq!(err.column(), Some(column), "{}", err);
+            assert!(
+                err.to_string().ends_with(&format!(
+                    " (line {}, col {})",
+                    lineno, column
+                )),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn t_parse_datetimes() {
+        let uxo = parse(
+            "uxf 1.0\n{datetime 2022-10-01T09 1 2022-10-01T09:30 2 \
+            2022-10-01T09:30:15 3 2022-10-01T09:30:16.75 4}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n{datetime 2022-10-01T09:00:00 1 2022-10-01T09:30:00 2 \
+            2022-10-01T09:30:15 3 2022-10-01T09:30:16 4}\n"
+        );
+        for text in
+            ["[2022-10-01T9]", "[2022-10-01T09:3]", "[2022-13-01T09]"]
+        {
+            let err = parse(&format!("uxf 1.0\n{}\n", text)).unwrap_err();
+            assert!(err.to_string().starts_with("#220:"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn t_parse_datetime_timezones() {
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&warnings);
+        let options = ParseOptions {
+            on_warning: Some(Box::new(move |warning: &UxfError| {
+                sink.borrow_mut().push(warning.to_string())
+            })),
+            ..Default::default()
+        };
+        let uxo = parse_with_options(
+            "uxf 1.0\n[2022-10-01T09:30Z 2022-10-01T09:30:15+01:00 \
+            2022-10-01T09-05 2022-10-01T09:30:15.5-0130]\n",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n[2022-10-01T09:30:00 2022-10-01T09:30:15 \
+            2022-10-01T09:00:00 2022-10-01T09:30:15]\n"
+        );
+        let warnings = warnings.borrow();
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(
+            warnings[1],
+            "#231:skipped timezone data, used \"2022-10-01T09:30:15\", \
+            got \"2022-10-01T09:30:15+01:00\" (line 2, col 20)"
+        );
+        for text in [
+            "[2022-10-01T09:30:15+1]",
+            "[2022-10-01T09:30:15+01:0]",
+            "[2022-10-01T09:30:15ZZ]",
+            "[2022-10-01T09:30:15+01:00Z]",
+        ] {
+            let err = parse(&format!("uxf 1.0\n{}\n", text)).unwrap_err();
+            assert!(err.to_string().starts_with("#220:"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn t_parse_python_files() {
+        // These files were written by the Python UXF implementation
+        let mut count = 0;
+        for dir in ["../testdata", "../testdata/expected"] {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|ext| ext != "uxf") {
+                    continue;
+                }
+                let text = std::fs::read_to_string(&path).unwrap();
+                if text.lines().any(|line| line.starts_with('!')) {
+                    let err = parse(&text).unwrap_err();
+                    assert!(
+                        err.to_string().starts_with("#176:"),
+                        "{}",
+                        err
+                    );
+                    continue;
+                }
+                let uxo = parse(&text)
+                    .unwrap_or_else(|err| panic!("{:?}: {}", path, err));
+                uxf::test_utils::assert_roundtrip(&uxo);
+                count += 1;
+            }
+        }
+        assert!(count > 100, "{}", count);
+    }
+
+    #[test]
+    fn t_parse_lenient_unclosed() {
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&warnings);
+        let options = ParseOptions {
+            lenient: true,
+            on_warning: Some(Box::new(move |warning: &UxfError| {
+                sink.borrow_mut().push(warning.to_string())
+            })),
+            ..Default::default()
+        };
+        let text = "uxf 1.0\n[1 2 3";
+        let err = parse(text).unwrap_err();
+        assert!(err.to_string().starts_with("#410:"), "{}", err);
+        let uxo = parse_with_options(text, &options).unwrap();
+        assert_eq!(uxo, parse("uxf 1.0\n[1 2 3]\n").unwrap());
+        assert_eq!(
+            *warnings.borrow(),
+            ["#414:missing ']' at end of data: closed implicitly (line 2, col 7)"]
+        );
+        warnings.borrow_mut().clear();
+        let text = "uxf 1.0\n=P x y\n[{<a> [1 (P 1 2\n3 4\n";
+        assert!(parse(text).is_err());
+        let uxo = parse_with_options(text, &options).unwrap();
+        assert_eq!(
+            uxo,
+            parse("uxf 1.0\n=P x y\n[{<a> [1 (P 1 2 3 4)]}]\n").unwrap()
+        );
+        assert_eq!(
+            *warnings.borrow(),
+            [
+                "#414:missing ')' at end of data: closed implicitly (line 5, col 1)",
+                "#414:missing ']' at end of data: closed implicitly (line 5, col 1)",
+                "#414:missing '}' at end of data: closed implicitly (line 5, col 1)",
+                "#414:missing ']' at end of data: closed implicitly (line 5, col 1)",
+            ]
+        );
+        // Only missing closers are recovered, not incomplete items
+        for text in ["uxf 1.0\n{<a> 1 <b>", "uxf 1.0\n=P x y\n(P 1 2 3"] {
+            assert!(
+                parse_with_options(text, &options).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn t_parse_spans() {
+        let text = "uxf 1.0\n=Point x y\n[1 <two>\n{<k> [3 ?]}\n\
+                    (Point 4 (:0A:) 5 2022-10-16)]\n";
+        let options =
+            ParseOptions { record_spans: true, ..Default::default() };
+        let uxo = parse_with_options(text, &options).unwrap();
+        assert_eq!(uxo, parse(text).unwrap()); // spans don't affect ==
+        let key = PathSegment::Key(Key::Str("k".to_string()));
+        let cell =
+            |row, name: &str| PathSegment::Cell(row, name.to_string());
+        for (path, expected) in [
+            (
+                vec![],
+                "[1 <two>\n{<k> [3 ?]}\n(Point 4 (:0A:) 5 2022-10-16)]",
+            ),
+            (vec![PathSegment::Index(0)], "1"),
+            (vec![PathSegment::Index(1)], "<two>"),
+            (vec![PathSegment::Index(2)], "{<k> [3 ?]}"),
+            (vec![PathSegment::Index(2), key.clone()], "[3 ?]"),
+            (
+                vec![
+                    PathSegment::Index(2),
+                    key.clone(),
+                    PathSegment::Index(1),
+                ],
+                "?",
+            ),
+            (vec![PathSegment::Index(3), cell(0, "y")], "(:0A:)"),
+            (vec![PathSegment::Index(3), cell(1, "y")], "2022-10-16"),
+        ] {
+            let span = uxo.span_of(&path).unwrap();
+            assert_eq!(&text[span], expected, "{:?}", path);
+        }
+        // Every value's path has a span
+        for (path, _) in uxo.value().iter_paths() {
+            assert!(uxo.span_of(&path).is_some(), "{:?}", path);
+        }
+        assert_eq!(uxo.span_of(&[PathSegment::Index(4)]), None);
+        // Spans aren't recorded by default
+        assert_eq!(parse(text).unwrap().span_of(&[]), None);
+    }
+
+    #[test]
+    fn t_parse_normalize_names() {
+        // Café with a precomposed é (U+E9) and with e + U+0301
+        let composed = "Caf\u{E9}";
+        let decomposed = "Cafe\u{301}";
+        assert_ne!(composed, decomposed);
+        assert_eq!(normalize_name(decomposed), composed);
+        assert_eq!(normalize_name(composed), composed);
+        let text = format!(
+            "uxf 1.0\n={} na\u{EF}ve:{}\n=Menu items:{}\n\
+            (Menu ({} 1))\n",
+            decomposed, decomposed, composed, composed
+        );
+        // By default names are used as is so decomposed ones are invalid
+        let err = parse(&text).unwrap_err();
+        assert!(err.to_string().starts_with("#170:"), "{}", err);
+        let options =
+            ParseOptions { normalize_names: true, ..Default::default() };
+        let uxo = parse_with_options(&text, &options).unwrap();
+        let expected = format!(
+            "uxf 1.0\n={} na\u{EF}ve:{}\n=Menu items:{}\n\
+            (Menu ({} 1))\n",
+            composed, composed, composed, composed
+        );
+        assert_eq!(uxo.to_string(), expected);
+        assert_eq!(uxo, parse(&expected).unwrap());
+        let name = normalize_name("nai\u{308}ve");
+        let expected = TClass::new(
+            composed,
+            make_fields(&[(&name, composed)]).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(uxo.tclass(composed), Some(&expected));
+        // The normalized definitions are identical so aren't conflicts
+        let text =
+            format!("uxf 1.0\n={} x\n={} x\n[]\n", decomposed, composed);
+        assert!(parse_with_options(&text, &options).is_ok());
+    }
+
+    #[test]
+    fn t_parse_entry_point() {
+        let text = "uxf 1.0 My Data\n#<file comment>\n[#<mixed> 1 -2 3.5 \
+            yes no ? <hi> 2022-09-21 2022-09-21T14:30:05 [int 4 5] \
+            {str int <a> 1} []]\n";
+        let uxo = parse(text).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        let uxo = parse("uxf 1.0\n{}\n").unwrap();
+        assert_eq!(uxo.to_string(), "uxf 1.0\n{}\n");
+        // Header errors are reported on line 1
+        for (text, code) in [
+            ("", "#110:"),
+            ("uxf 1.0", "#110:"),
+            ("UXF 1.0\n[]\n", "#130:"),
+            ("uxf one\n[]\n", "#151:"),
+        ] {
+            let err = parse(text).unwrap_err().to_string();
+            assert!(err.starts_with(code), "{}", err);
+            assert!(err.contains("(line 1"), "{}", err);
+        }
+        // Other errors are reported on the line they occur
+        let err = parse("uxf 1.0\n[1\n2 @]\n").unwrap_err().to_string();
+        assert!(err.starts_with("#170:"), "{}", err);
+        assert!(err.contains("(line 3"), "{}", err);
+        assert!(parse("uxf 1.0\n1\n").is_err());
+        assert!(parse("uxf 1.0\n[1] [2]\n").is_err());
+    }
+
+    #[test]
+    fn t_parse_bytes() {
+        let uxo = parse(
+            "uxf 1.0\n[(:48 65 6c\n6C 6F:) (::) {(:00 ff:) (:DE AD:)}]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n[(:48656C6C6F:) (::) {(:00FF:) (:DEAD:)}]\n"
+        );
+        for text in ["[(:486:)]", "[(:4G:)]", "[(:48"] {
+            let err = parse(&format!("uxf 1.0\n{}\n", text)).unwrap_err();
+            let err = err.to_string();
+            assert!(
+                err.starts_with("#200:")
+                    || err.starts_with("#202:")
+                    || err.starts_with("#270:"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn t_parse_str_escapes() {
+        let text =
+            "uxf 1.0\n#<a &lt;b&gt;>\n[#<&amp;&amp;> <x &lt; y &amp;&amp; \
+            y &gt; z> <&lt;&gt;> {str <&amp;lt;> <&gt;>}]\n";
+        let uxo = parse(text).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        // The unescaped text must not be unescaped a second time
+        let uxo = parse("uxf 1.0\n[<&amp;lt;>]\n").unwrap();
+        assert_eq!(uxo.to_string(), "uxf 1.0\n[<&amp;lt;>]\n");
+    }
+
+    #[test]
+    fn t_parse_ttype_definitions() {
+        let text = "uxf 1.0\n=Pair first second\n\
+            =#<a point> Point x:real y:real\n(Point 1.5 -2.0 0.0 3.25)\n";
+        let uxo = parse(text).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        let uxo = parse(
+            "uxf 1.0\n=Pair first second\n=Point x:real y:real\n\
+            [(Pair) (Pair <a> 1 <b> 2)]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=Pair first second\n=Point x:real y:real\n\
+            [(Pair) (Pair <a> 1 <b> 2)]\n"
+        );
+        // A table may only use a ttype that has been defined
+        let err = parse("uxf 1.0\n=Pair first second\n(Point 1.5 2.0)\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("#450:"), "{}", err);
+        assert!(err.contains("(line 3"), "{}", err);
+        let err = parse("uxf 1.0\n=Point x:\n(Point 1)\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("#260:"), "{}", err);
+    }
+
+    #[test]
+    fn t_parse_coerce_bools() {
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&warnings);
+        let lenient = ParseOptions {
+            lenient: true,
+            coerce_bools: true,
+            on_warning: Some(Box::new(move |warning: &UxfError| {
+                sink.borrow_mut().push(warning.to_string())
+            })),
+            ..Default::default()
+        };
+        let expected = "uxf 1.0\n=Row id:int ok:bool\n\
+                        (Row 1 yes 2 no 3 yes 4 no 5 yes 6 no 7 ?)\n";
+        let text = "uxf 1.0\n=Row id:int ok:bool\n\
+                    (Row 1 1 2 0 3 true 4 false 5 yes 6 no 7 ?)\n";
+        let uxo = parse_with_options(text, &lenient).unwrap();
+        assert_eq!(uxo.to_string(), expected);
+        assert_eq!(
+            *warnings.borrow(),
+            [
+                "#416:read 1 as yes for a bool field (line 3, col 8)",
+                "#416:read 0 as no for a bool field (line 3, col 12)",
+                "#416:read true as yes for a bool field (line 3, col 16)",
+                "#416:read false as no for a bool field (line 3, col 23)",
+            ]
+        );
+        // Only bool fields are coerced
+        let text = "uxf 1.0\n=Row id:int ok:bool any\n(Row 1 yes 0)\n";
+        let uxo = parse_with_options(text, &lenient).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        // Strict mode only accepts yes and no
+        let strict =
+            ParseOptions { coerce_bools: true, ..Default::default() };
+        let text = "uxf 1.0\n=Row id:int ok:bool\n(Row 1 yes 2 no)\n";
+        let uxo = parse_with_options(text, &strict).unwrap();
+        assert_eq!(uxo.to_string(), text);
+        for alias in ["1", "0", "true", "false"] {
+            let text = format!(
+                "uxf 1.0\n=Row id:int ok:bool\n(Row 1 yes 2 {})\n",
+                alias
+            );
+            let err = parse_with_options(&text, &strict).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "#418:expected yes or no for a bool field, got {} \
+                    (line 3, col 14)",
+                    alias
+                )
+            );
+        }
+        // Without the option the aliases aren't bools
+        let text = "uxf 1.0\n=Row id:int ok:bool\n(Row 1 true)\n";
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let err = parse_with_options(text, &options).unwrap_err();
+        assert!(err.to_string().starts_with("#412:"), "{}", err);
+    }
+
+    #[test]
+    fn t_parse_duplicate_ttypes() {
+        // Identical redefinitions are harmless
+        let uxo =
+            parse("uxf 1.0\n=Pair a b\n=Pair a b\n(Pair 1 2)\n").unwrap();
+        assert_eq!(uxo.to_string(), "uxf 1.0\n=Pair a b\n(Pair 1 2)\n");
+        // Conflicting ones are rejected on the redefinition's line
+        for text in [
+            "uxf 1.0\n=Pair a b\n\n=Pair a c\n[]\n",
+            "uxf 1.0\n=Pair a b\n\n=Pair a\n[]\n",
+            "uxf 1.0\n=Pair a b\n\n=Pair b a\n[]\n",
+        ] {
+            let err = parse(text).unwrap_err().to_string();
+            assert!(err.starts_with("#528:"), "{}", err);
+            assert!(err.ends_with("(line 4, col 2)"), "{}", err);
+        }
+    }
+}
@@ -3,21 +3,660 @@
 
 #[cfg(test)]
 mod tests {
-    use uxf::table::Table;
+    use uxf::field::{make_fields, Field};
+    use uxf::parser::{parse, parse_with_options, ParseOptions};
+    use uxf::table::{ColumnStats, Table};
     use uxf::tclass::TClass;
     use uxf::test_utils::value_to_str;
     use uxf::value::Value;
+    use uxf::Uxf;
 
     #[test]
     fn t_table() {
         let tclass = TClass::new_fieldless("Point", None).unwrap();
         let t = Table::new(tclass);
         let v = Value::Table(t);
+        assert_eq!(value_to_str(v), "(Point)");
+        // TODO lots more tests
+    }
+
+    #[test]
+    fn t_table_join() {
+        let points = table_of(
+            "uxf 1.0\n=Point id:int x:int y:int\n\
+            (Point 1 10 20 2 30 40 3 50 60 ? 70 80)\n",
+        );
+        let colors = table_of(
+            "uxf 1.0\n=Color id:int name:str\n\
+            (Color 1 <red> 3 <blue> 3 <navy> 4 <green> ? <none>)\n",
+        );
+        let expected = table_of(
+            "uxf 1.0\n\
+            =PointColor id:int x:int y:int Color_id:int name:str\n\
+            (PointColor 1 10 20 1 <red> 3 50 60 3 <blue> \
+            3 50 60 3 <navy>)\n",
+        );
+        let joined = points.join(&colors, "id", "id").unwrap();
+        assert_eq!(joined, expected);
+        let err = points.join(&colors, "id", "ID").unwrap_err();
         assert_eq!(
-            value_to_str(v),
-            "Table { tclass: TClass { ttype: \"Point\", fields: [], \
-            comment: None }, comment: None, records: [] }"
+            err.to_string(),
+            "#360:table Color has no field called ID"
         );
-        // TODO lots more tests
+        // Derived names are truncated to the maximum name length
+        let (l, r) = ("L".repeat(40), "R".repeat(58));
+        let left = table_of(&format!(
+            "uxf 1.0\n={} id:int {}_i\n({} 1 2)\n",
+            l, r, l
+        ));
+        let right = table_of(&format!(
+            "uxf 1.0\n={} id:int name:str\n({} 1 <a>)\n",
+            r, r
+        ));
+        let joined = left.join(&right, "id", "id").unwrap();
+        assert_eq!(joined.tclass().ttype(), format!("{}{}", l, &r[..20]));
+        let names: Vec<&str> =
+            joined.tclass().fields().iter().map(|f| f.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "id".to_string(),
+                format!("{}_i", r),
+                format!("{}_2", r),
+                "name".to_string()
+            ]
+        );
+        assert_eq!(joined.records_len(), 1);
+    }
+
+    #[test]
+    fn t_table_enum_values() {
+        let uxo = parse(
+            "uxf 1.0\n=StateWaiting\n=StateReady\n=Point x y\n\
+            =StateDone\n[(StateReady) (Point 1 2)]\n",
+        )
+        .unwrap();
+        let tables: Vec<&Table> = uxo
+            .value()
+            .iter_paths()
+            .filter_map(|(_, v)| match v {
+                Value::Table(t) => Some(t),
+                _ => None,
+            })
+            .collect();
+        // Only this table's own value; not the other fieldless ttypes
+        assert_eq!(
+            tables[0].enum_values().unwrap(),
+            vec![Value::from("StateReady")]
+        );
+        let err = tables[1].enum_values().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#362:only fieldless tables have enum values, Point has 2 fields"
+        );
+    }
+
+    #[test]
+    fn t_table_column_stats() {
+        let t = table_of(
+            "uxf 1.0\n=Item name:str qty:int any\n\
+            (Item <a> 5 1 <b> ? 2.5 <c> -3 <x> <d> 10 ?)\n",
+        );
+        assert_eq!(
+            t.column_stats("qty").unwrap(),
+            ColumnStats {
+                count: 3,
+                min: Some(-3.0),
+                max: Some(10.0),
+                sum: 12.0,
+                mean: Some(4.0),
+            }
+        );
+        let err = t.column_stats("name").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#364:can only compute stats for int or real fields, name is str"
+        );
+        let err = t.column_stats("any").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#366:can only compute stats for numeric values, record 2 \
+            field any is not numeric"
+        );
+        let err = t.column_stats("nosuch").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#360:table Item has no field called nosuch"
+        );
+        let t = table_of("uxf 1.0\n=Item qty:real\n(Item ? ?)\n");
+        assert_eq!(
+            t.column_stats("qty").unwrap(),
+            ColumnStats {
+                count: 0,
+                min: None,
+                max: None,
+                sum: 0.0,
+                mean: None
+            }
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn t_table_column_stats_bigint() {
+        let t = table_of(
+            "uxf 1.0\n=Item qty:int\n\
+            (Item 1 100000000000000000000 ? -1)\n",
+        );
+        assert_eq!(
+            t.column_stats("qty").unwrap(),
+            ColumnStats {
+                count: 3,
+                min: Some(-1.0),
+                max: Some(1e20),
+                sum: 1e20,
+                mean: Some(1e20 / 3.0),
+            }
+        );
+    }
+
+    #[test]
+    fn t_table_content_hash() {
+        let mut a =
+            table_of("uxf 1.0\n=Point x:int y:real\n(Point 1 2.5)\n");
+        let mut b = a.clone();
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_eq!(a, b);
+        let hash = a.content_hash();
+        a.push_record(vec![Some(Value::Int(3)), None]).unwrap();
+        assert_ne!(a.content_hash(), hash); // cache was invalidated
+        assert_ne!(a, b);
+        b.push_record(vec![Some(Value::Int(3)), None]).unwrap();
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_eq!(a, b);
+        // 0.0 == -0.0 so the hashes must agree
+        a.push_record(vec![None, Some(Value::Real(0.0))]).unwrap();
+        b.push_record(vec![None, Some(Value::Real(-0.0))]).unwrap();
+        assert_eq!(a, b);
+        // Equal hashes aren't enough: the comments must match too
+        let c =
+            table_of("uxf 1.0\n=Point x:int y:real\n(#<c> Point 1 2.5)\n");
+        let d = table_of("uxf 1.0\n=Point x:int y:real\n(Point 1 2.5)\n");
+        assert_eq!(c.content_hash(), d.content_hash());
+        assert_ne!(c, d);
+        let err = a.push_record(vec![None]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#370:expected a record of 2 fields for a Point table, got 1"
+        );
+        let mut e = table_of("uxf 1.0\n=E\n(E)\n");
+        let err = e.push_record(vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#368:can't add a record to fieldless table E"
+        );
+    }
+
+    #[test]
+    fn t_table_validate() {
+        let header = "uxf 1.0\n=Line start:Point end:Point\n\
+                      =Point x:int y:int\n=Shape name:str lines:list\n";
+        let lines = table_of(&format!(
+            "{}(Line (Point 1 2) (Point 3 4) (Point 5 ?) ?)\n",
+            header
+        ));
+        lines.validate().unwrap();
+        let lines = table_of(&format!(
+            "{}(Line (Point 1 2) (Point 3 4) (Point 5 6) (Point 7 <no>))\n",
+            header
+        ));
+        let err = lines.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#372:Line[1].end > Point[0].y: expected int, got str"
+        );
+        let shape = table_of(&format!(
+            "{}(Shape <box> [Line (Line (Point 1 2) (Point 1.5 2))])\n",
+            header
+        ));
+        let err = shape.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#372:Shape[0].lines[0] > Line[0].end > Point[0].x: expected \
+            int, got real"
+        );
+        let shape = table_of(&format!(
+            "{}(Shape <box> [Line (Point 1 2)])\n",
+            header
+        ));
+        let err = shape.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#372:Shape[0].lines[0]: expected Line, got Point"
+        );
+        // append() validates too
+        let mut points = table_of(&format!("{}(Point 1 2)\n", header));
+        let err = points
+            .append(vec![Some(Value::Int(3)), Some(Value::Real(4.0))])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#358:field 'y' expected int, got real"
+        );
+        points.append(vec![Some(Value::Int(3)), None]).unwrap();
+        points.validate().unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn t_table_to_json() {
+        let points = table_of(
+            "uxf 1.0\n=Point x:int y:real label:str when:date data:bytes \
+            tags\n(Point 1 2.5 <A> 2022-10-01 (:48 69:) [1 <two> ?] \
+            -3 4.0 ? ? (::) {<k> yes})\n",
+        );
+        assert_eq!(
+            points.to_json().unwrap(),
+            serde_json::json!([
+                {"x": 1, "y": 2.5, "label": "A", "when": "2022-10-01",
+                 "data": "SGk=", "tags": [1, "two", null]},
+                {"x": -3, "y": 4.0, "label": null, "when": null,
+                 "data": "", "tags": {"k": true}},
+            ])
+        );
+        let items = table_of(
+            "uxf 1.0\n=Item tags\n(Item {<a> 1 <1> 2 1 3 <b> 4})\n",
+        );
+        let err = items.to_json().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#730:can't convert map to JSON: keys 1 and <1> both become \
+            \"1\""
+        );
+        assert_eq!(
+            err.downcast_ref::<uxf::error::UxfError>().unwrap().code(),
+            730
+        );
+    }
+
+    #[test]
+    fn t_table_shrink_to_fit() {
+        let text = format!(
+            "uxf 1.0\n=Pair a b\n(Pair {})\n",
+            (0..2000).map(|i| i.to_string()).collect::<Vec<_>>().join(" ")
+        );
+        let mut pairs = table_of(&text);
+        let mut pairs2 = pairs.clone();
+        pairs.retain(|record| record[0] == Some(Value::Int(0)));
+        let capacity = pairs.capacity();
+        assert!(capacity >= 1000, "{}", capacity);
+        pairs.shrink_to_fit();
+        assert!(pairs.capacity() < capacity);
+        assert_eq!(pairs, table_of("uxf 1.0\n=Pair a b\n(Pair 0 1)\n"));
+        assert_ne!(pairs, pairs2);
+        pairs2.shrink_to_fit();
+        assert_eq!(pairs2, table_of(&text));
+    }
+
+    #[test]
+    fn t_table_dedup_by_key() {
+        let mut people = table_of(
+            "uxf 1.0\n=Person id:int name:str\n\
+            (Person 3 <Carol> 1 <Alice> 3 <Caroline> ? <Unknown> 2 <Bob> \
+            1 <Alicia> ? <Nobody> 2 <Bobby>)\n",
+        );
+        people.dedup_by_key("id").unwrap();
+        assert_eq!(
+            people,
+            table_of(
+                "uxf 1.0\n=Person id:int name:str\n\
+                (Person ? <Unknown> 1 <Alice> 2 <Bob> 3 <Carol>)\n"
+            )
+        );
+        people.dedup_by_key("name").unwrap(); // no duplicate names
+        assert_eq!(
+            Value::Table(people.clone()).summary(80),
+            "(Person 4 records)"
+        );
+        let err = people.dedup_by_key("age").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#360:table Person has no field called age"
+        );
+    }
+
+    fn table_of(text: &str) -> Table {
+        match parse(text).unwrap().value() {
+            Value::Table(t) => t.clone(),
+            _ => panic!("expected a table"),
+        }
+    }
+
+    #[test]
+    fn t_table_append() {
+        let tclass = TClass::new(
+            "Point",
+            make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        let mut points = Table::new(tclass);
+        assert_eq!(points.records_len(), 0);
+        assert_eq!(points.record(0), None);
+        points
+            .append(vec![Some(Value::Int(1)), Some(Value::Int(2))])
+            .unwrap();
+        points.push_empty_record().unwrap();
+        for record in [vec![], vec![Some(Value::Int(3))], vec![None; 3]] {
+            let n = record.len();
+            let err = points.append(record).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "#370:expected a record of 2 fields for a Point \
+                    table, got {}",
+                    n
+                )
+            );
+        }
+        assert_eq!(points.records_len(), 2);
+        assert_eq!(
+            points.record(0),
+            Some(&[Some(Value::Int(1)), Some(Value::Int(2))][..])
+        );
+        assert_eq!(points.record(1), Some(&[None, None][..]));
+        assert_eq!(points.record(2), None);
+        assert_eq!(
+            Uxf::new(Value::Table(points)).to_string(),
+            "uxf 1.0\n=Point x:int y:int\n(Point 1 2 ? ?)\n"
+        );
+        let tclass = TClass::new_fieldless("Ready", None).unwrap();
+        let mut ready = Table::new(tclass);
+        let err = ready.append(vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#368:can't add a record to fieldless table Ready"
+        );
+        assert!(ready.push_empty_record().is_err());
+        assert_eq!(ready.records_len(), 0);
+        let err = ready.append(vec![Some(Value::Int(1))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#368:can't add a record to fieldless table Ready"
+        );
+        assert_eq!(ready.records_len(), 0);
+        // Fieldless tables can't have values in UXF text either (even when
+        // recording spans, which needs each value's field name)
+        for record_spans in [false, true] {
+            let options =
+                ParseOptions { record_spans, ..Default::default() };
+            let err = parse_with_options(
+                "uxf 1.0\n=Ready\n[(Ready 1 2)]\n",
+                &options,
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "#452:a fieldless table may not have values, got 2 for \
+                 Ready (line 3, col 3)"
+            );
+        }
+    }
+
+    #[test]
+    fn t_table_insert_column() {
+        let mut points = table_of(
+            "uxf 1.0\n=#<2D> Point x:int y:int\n(Point 1 2 3 4 5 6)\n",
+        );
+        let hash = points.content_hash();
+        points
+            .insert_column(
+                1,
+                Field::new("visible", "bool").unwrap(),
+                Some(Value::Bool(true)),
+            )
+            .unwrap();
+        assert_ne!(points.content_hash(), hash);
+        assert_eq!(points.records_len(), 3);
+        for row in 0..points.records_len() {
+            let record = points.record(row).unwrap();
+            assert_eq!(record.len(), 3);
+            assert_eq!(record[1], Some(Value::Bool(true)));
+        }
+        let expected = table_of(
+            "uxf 1.0\n=#<2D> Point x:int visible:bool y:int\n\
+            (Point 1 yes 2 3 yes 4 5 yes 6)\n",
+        );
+        assert_eq!(points, expected);
+        // Appending with a null default
+        points
+            .insert_column(3, Field::new_anyvtype("label").unwrap(), None)
+            .unwrap();
+        assert_eq!(points.record(2).unwrap()[3], None);
+        points.validate().unwrap();
+        let err = points
+            .insert_column(5, Field::new_anyvtype("z").unwrap(), None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#378:can't insert column z at index 5 in a Point table with \
+            4 fields"
+        );
+        let err = points
+            .insert_column(0, Field::new_anyvtype("x").unwrap(), None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#388:table Point already has a field called x"
+        );
+        let err = points
+            .insert_column(
+                0,
+                Field::new("id", "int").unwrap(),
+                Some(Value::Str("one".to_string())),
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "#372:Point.id: expected int, got str");
+        assert_eq!(points.record(0).unwrap().len(), 4); // unchanged
+    }
+
+    #[test]
+    fn t_table_remove_column() {
+        let mut points = table_of(
+            "uxf 1.0\n=#<3D> Point x:int y:int z:int\n\
+            (Point 1 2 3 4 5 6)\n",
+        );
+        points.remove_column("y").unwrap();
+        let expected = table_of(
+            "uxf 1.0\n=#<3D> Point x:int z:int\n(Point 1 3 4 6)\n",
+        );
+        assert_eq!(points, expected);
+        let err = points.remove_column("y").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#360:table Point has no field called y"
+        );
+        points.remove_column("x").unwrap();
+        assert_eq!(points.record(1), Some(&[Some(Value::Int(6))][..]));
+        let err = points.remove_column("z").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#390:can't remove z since it is table Point's only field"
+        );
+        assert_eq!(points.records_len(), 2);
+        // Removing an inserted column restores the original
+        let original = expected.clone();
+        let mut points = expected;
+        points
+            .insert_column(1, Field::new_anyvtype("y").unwrap(), None)
+            .unwrap();
+        assert_ne!(points, original);
+        points.remove_column("y").unwrap();
+        assert_eq!(points, original);
+    }
+
+    #[test]
+    fn t_table_append_field_vtypes() {
+        let tclass = TClass::new(
+            "Customer",
+            make_fields(&[
+                ("CID", "int"),
+                ("name", "str"),
+                ("at", "Point"),
+            ])
+            .unwrap()
+            .into_iter()
+            .chain([Field::new_anyvtype("extra").unwrap()])
+            .collect(),
+            None,
+        )
+        .unwrap();
+        let mut customers = Table::new(tclass);
+        let point = table_of("uxf 1.0\n=Point x:int y:int\n(Point 1 2)\n");
+        // A top-level mismatch is only ever reported by field name, and a
+        // nested one by path
+        let bad_point =
+            table_of("uxf 1.0\n=Point x:int y:int\n(Point 1 <2>)\n");
+        // A correct record (any-vtype fields accept anything, e.g., bools)
+        customers
+            .append(vec![
+                Some(Value::Int(1)),
+                Some(Value::Str("Ann".to_string())),
+                Some(Value::Table(point.clone())),
+                Some(Value::Bool(true)),
+            ])
+            .unwrap();
+        customers.append(vec![None, None, None, None]).unwrap();
+        // Wrong-type records
+        for (record, message) in [
+            (
+                vec![Some(Value::Str("2".to_string())), None, None, None],
+                "#358:field 'CID' expected int, got str",
+            ),
+            (
+                vec![None, Some(Value::Int(3)), None, None],
+                "#358:field 'name' expected str, got int",
+            ),
+            (
+                vec![None, None, Some(Value::Bool(false)), None],
+                "#358:field 'at' expected Point, got bool",
+            ),
+            (
+                vec![None, None, Some(Value::Table(bad_point)), None],
+                "#372:Customer[2].at > Point[0].y: expected int, got str",
+            ),
+        ] {
+            let err = customers.append(record).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+        assert_eq!(customers.records_len(), 2);
+        customers.validate().unwrap();
+    }
+
+    #[test]
+    fn t_table_count_records() {
+        let mut points = table_of(
+            "uxf 1.0\n=Point x:int y:int\n(Point 1 2 15 4 ? 6 10 8 20 0)\n",
+        );
+        let over = |limit| {
+            move |record: &[Option<Value>]| match &record[0] {
+                Some(Value::Int(x)) => *x > limit,
+                _ => false,
+            }
+        };
+        assert_eq!(points.count_records(over(10)), 2);
+        assert_eq!(points.count_records(over(0)), 4);
+        assert_eq!(points.count_records(over(100)), 0);
+        assert_eq!(points.count_records(|record| record[0].is_none()), 1);
+        points.retain(|_| false);
+        assert_eq!(points.count_records(|_| true), 0);
+    }
+
+    #[test]
+    fn t_table_tclass_and_records() {
+        let points = table_of(
+            "uxf 1.0\n=#<A point> Point x:int y\n(Point 1 2 3 ?)\n",
+        );
+        let tclass = points.tclass();
+        assert_eq!(tclass.ttype(), "Point");
+        assert_eq!(tclass.comment(), Some("A point"));
+        assert_eq!(tclass.len(), 2);
+        assert_eq!(points.records().len(), points.records_len());
+        assert_eq!(
+            points.records(),
+            &[
+                vec![Some(Value::Int(1)), Some(Value::Int(2))],
+                vec![Some(Value::Int(3)), None],
+            ]
+        );
+        let total: i64 = points
+            .records()
+            .iter()
+            .flatten()
+            .map(|value| match value {
+                Some(Value::Int(i)) => *i,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn t_table_is_cell_null() {
+        let text = "uxf 1.0\n=Pair name:str items:list\n\
+            (Pair ? [] <> ? <x> [1])\n";
+        let pairs = table_of(text);
+        let text = Uxf::new(Value::Table(pairs.clone())).to_string();
+        assert_eq!(
+            text,
+            "uxf 1.0\n=Pair name:str items:list\n(Pair ? [] <> ? <x> [1])\n"
+        );
+        let roundtripped = table_of(&text);
+        assert_eq!(roundtripped, pairs);
+        for t in [&pairs, &roundtripped] {
+            assert_eq!(t.is_cell_null(0, 0), Some(true));
+            assert_eq!(t.is_cell_null(0, 1), Some(false));
+            assert_eq!(t.is_cell_null(1, 0), Some(false));
+            assert_eq!(t.is_cell_null(1, 1), Some(true));
+            assert_eq!(t.is_cell_null(2, 0), Some(false));
+            assert_eq!(t.is_cell_null(3, 0), None);
+            assert_eq!(t.is_cell_null(0, 2), None);
+            assert_eq!(
+                t.record(1).unwrap()[0],
+                Some(Value::Str("".into()))
+            );
+        }
+    }
+
+    #[test]
+    fn t_table_comments() {
+        let mut points = table_of(
+            "uxf 1.0\n=#<A point> Point x y\n(#<Some points> Point 1 2)\n",
+        );
+        assert_eq!(points.comment(), Some("Some points"));
+        assert_eq!(points.tclass().comment(), Some("A point"));
+        points.set_comment(Some("Other points"), Some(12)).unwrap();
+        assert_eq!(points.comment(), Some("Other points"));
+        assert_eq!(
+            Uxf::new(Value::Table(points.clone())).to_string(),
+            "uxf 1.0\n=#<A point> Point x y\n(#<Other points> Point 1 2)\n"
+        );
+        points.set_comment(None, None).unwrap();
+        assert_eq!(points.comment(), None);
+        assert_eq!(points.tclass().comment(), Some("A point"));
+        let err =
+            points.set_comment(Some("Other points"), Some(11)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 11 characters long, got 12 \
+            characters"
+        );
+        assert_eq!(points.comment(), None);
+        assert_eq!(
+            Uxf::new(Value::Table(points)).to_string(),
+            "uxf 1.0\n=#<A point> Point x y\n(Point 1 2)\n"
+        );
+        let empty = Table::new(TClass::new_fieldless("E", None).unwrap());
+        assert_eq!(empty.comment(), None);
     }
 }
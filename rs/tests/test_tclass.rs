@@ -3,9 +3,10 @@
 
 #[cfg(test)]
 mod tests {
+    use std::cmp::Ordering;
     use uxf::constants::*;
     use uxf::field::{make_fields, Field};
-    use uxf::tclass::TClass;
+    use uxf::tclass::{FieldDiff, TClass};
     use uxf::test_utils::check_error_code;
 
     // TODO new() new_fieldless() is_fieldless() ttype() comment() len()
@@ -41,11 +42,103 @@ mod tests {
         );
     }
 
+    #[test]
+    fn t_tclass_fields() {
+        let tclass =
+            TClass::new("General", valid_fields(), Some("first test"))
+                .unwrap();
+        let names: Vec<&str> =
+            tclass.fields().iter().map(|field| field.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "CID",
+                "title",
+                "selected",
+                "when",
+                "size",
+                "timestamp",
+                "Kind",
+                "Filename",
+                "Categories",
+                "Extra"
+            ]
+        );
+        assert_eq!(tclass.fields(), valid_fields());
+        assert_eq!(
+            tclass.field(0),
+            Some(&Field::new("CID", "int").unwrap())
+        );
+        assert_eq!(
+            tclass.field(9),
+            Some(&Field::new("Extra", "Point").unwrap())
+        );
+        assert_eq!(tclass.field(10), None);
+        let field = tclass.field_by_name("when").unwrap();
+        assert_eq!(field.vtype(), Some("date"));
+        assert_eq!(tclass.field_by_name("Kind").unwrap().vtype(), None);
+        assert_eq!(tclass.field_by_name("kind"), None);
+        assert_eq!(tclass.field_by_name("missing"), None);
+        let tclass = TClass::new_fieldless("StateReady", None).unwrap();
+        assert!(tclass.fields().is_empty());
+        assert_eq!(tclass.field(0), None);
+        assert_eq!(tclass.field_by_name("x"), None);
+    }
+
+    #[test]
+    fn t_tclass_duplicate_fields() {
+        let fields = make_fields(&[("x", "int"), ("y", "int")]).unwrap();
+        let tclass = TClass::new("Point", fields, None).unwrap();
+        assert_eq!(tclass.len(), 2);
+        // Names are case-sensitive
+        let fields = make_fields(&[("x", "int"), ("X", "str")]).unwrap();
+        assert!(TClass::new("Point", fields, None).is_ok());
+        for pairs in [
+            &[("x", "int"), ("x", "str")][..],
+            &[("x", "int"), ("y", "int"), ("x", "")],
+            &[("x", ""), ("x", "")],
+        ] {
+            let fields = make_fields(pairs).unwrap();
+            let err = TClass::new("Point", fields, None).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "#336:duplicate field name x in tclass Point"
+            );
+        }
+        let err =
+            uxf::parser::parse("uxf 1.0\n=Point x y x\n[]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#336:duplicate field name x in tclass Point (line 2, col 2)"
+        );
+        // A field's error keeps its own code and has the field's position
+        let text = format!("uxf 1.0\n=Point x\n  {}\n[]\n", "y".repeat(61));
+        let err = uxf::parser::parse(&text).unwrap_err();
+        let err = err.downcast_ref::<uxf::error::UxfError>().unwrap();
+        assert_eq!(err.code(), 606);
+        assert_eq!((err.lineno(), err.column()), (Some(3), Some(3)));
+        assert!(!err.message().contains('#'), "{}", err);
+    }
+
     #[test]
     fn t_tclass_new_fieldless() {
         // TODO with & without comment
     }
 
+    #[test]
+    fn t_tclass_record_of_nulls() {
+        let tclass =
+            TClass::new("General", valid_fields(), Some("first test"))
+                .unwrap();
+        let record = tclass.record_of_nulls().unwrap();
+        assert_eq!(record.len(), tclass.len());
+        assert_eq!(record.len(), 10);
+        assert!(record.iter().all(|value| value.is_none()));
+        let tclass = TClass::new_fieldless("StateReady", None).unwrap();
+        let err = tclass.record_of_nulls().unwrap_err();
+        assert!(err.to_string().starts_with("#352:"), "{}", err);
+    }
+
     #[test]
     fn t_tclass_invalid_ttype() {
         for (code, name) in [
@@ -98,6 +191,96 @@ mod tests {
         }
     }
 
+    #[test]
+    fn t_tclass_diff() {
+        let point = |fields: &[(&str, &str)]| {
+            TClass::new("Point", make_fields(fields).unwrap(), None)
+                .unwrap()
+        };
+        let v1 = point(&[("x", "int"), ("y", "int")]);
+        let v2 = point(&[("x", "int"), ("y", "int"), ("z", "int")]);
+        assert!(v1.diff(&v1).is_empty());
+        assert_eq!(
+            v1.diff(&v2),
+            vec![FieldDiff::Added(Field::new("z", "int").unwrap())]
+        );
+        assert_eq!(
+            v2.diff(&v1),
+            vec![FieldDiff::Removed(Field::new("z", "int").unwrap())]
+        );
+        // Inserting a field first doesn't make the others count as moved
+        let v3 = point(&[("id", ""), ("x", "real"), ("y", "int")]);
+        assert_eq!(
+            v1.diff(&v3),
+            vec![
+                FieldDiff::Retyped {
+                    name: "x".to_string(),
+                    old: Some("int".to_string()),
+                    new: Some("real".to_string())
+                },
+                FieldDiff::Added(Field::new_anyvtype("id").unwrap()),
+            ]
+        );
+        let v4 = point(&[("y", "int"), ("x", "")]);
+        assert_eq!(
+            v1.diff(&v4),
+            vec![
+                FieldDiff::Retyped {
+                    name: "x".to_string(),
+                    old: Some("int".to_string()),
+                    new: None
+                },
+                FieldDiff::Moved { name: "x".to_string(), old: 0, new: 1 },
+                FieldDiff::Moved { name: "y".to_string(), old: 1, new: 0 },
+            ]
+        );
+        // Comments aren't compared
+        let v5 =
+            TClass::new("Point", v1.fields().to_vec(), Some("2D")).unwrap();
+        assert!(v1.diff(&v5).is_empty());
+    }
+
+    #[test]
+    fn t_tclass_eq_ignores_comment() {
+        let fields = make_fields(&[("x", "int"), ("y", "int")]).unwrap();
+        let a = TClass::new("Point", fields.clone(), None).unwrap();
+        let b = TClass::new("Point", fields.clone(), Some("2D")).unwrap();
+        let c =
+            TClass::new("Point", fields.clone(), Some("other")).unwrap();
+        for (x, y) in [(&a, &b), (&b, &c), (&a, &c)] {
+            assert_eq!(x, y);
+            assert_eq!(x.cmp(y), Ordering::Equal);
+            assert_eq!(x.partial_cmp(y), Some(Ordering::Equal));
+        }
+        assert_eq!(b.comment(), Some("2D")); // the comment is kept though
+        let a = TClass::new_fieldless("Ready", None).unwrap();
+        let b = TClass::new_fieldless("Ready", Some("flag")).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.cmp(&b), Ordering::Equal);
+        // The ttype and fields are significant
+        let d = TClass::new("Pair", fields, Some("2D")).unwrap();
+        assert_ne!(b, d);
+        let e = TClass::new(
+            "Point",
+            make_fields(&[("x", "int"), ("y", "real")]).unwrap(),
+            Some("2D"),
+        )
+        .unwrap();
+        let b = TClass::new(
+            "Point",
+            make_fields(&[("x", "int"), ("y", "int")]).unwrap(),
+            Some("2D"),
+        )
+        .unwrap();
+        assert_ne!(b, e);
+        assert_ne!(b.cmp(&e), Ordering::Equal);
+        // So sorting and deduplicating ignore comments too
+        let mut tclasses = vec![e.clone(), c.clone(), d.clone(), b.clone()];
+        tclasses.sort();
+        tclasses.dedup();
+        assert_eq!(tclasses, vec![d, b, e]);
+    }
+
     fn valid_fields() -> Vec<Field> {
         make_fields(&[
             ("CID", "int"),
@@ -0,0 +1,227 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+#[cfg(test)]
+mod tests {
+    use uxf::list::List;
+    use uxf::map::Map;
+    use uxf::parser::{parse, parse_with_options, ParseOptions};
+    use uxf::table::Table;
+    use uxf::tclass::TClass;
+    use uxf::util::{
+        bytes_to_uxf, escape_str, real_to_uxf, unescape_str, uxf_to_bytes,
+    };
+    use uxf::value::Value;
+
+    #[test]
+    fn t_max_comment_len() {
+        let long = "x".repeat(1000);
+        let text = format!("uxf 1.0\n#<{}>\n[#<short> 1]\n", long);
+        parse(&text).unwrap();
+        let options = ParseOptions {
+            max_comment_len: Some(10),
+            ..Default::default()
+        };
+        let err = parse_with_options(&text, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 10 characters long, got 1000 \
+            characters (line 2, col 1)"
+        );
+        let err =
+            parse_with_options("uxf 1.0\n[\n#<abcdefghijk> 1]\n", &options)
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 10 characters long, got 11 \
+            characters (line 3, col 1)"
+        );
+        let err = parse_with_options(
+            "uxf 1.0\n=#<abcdefghijk> T\n(T)\n",
+            &options,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("#182:"), "{}", err);
+        // The limit is in characters, not bytes
+        let text = format!("uxf 1.0\n#<{}>\n[]\n", "é".repeat(10));
+        let mut uxo = parse_with_options(&text, &options).unwrap();
+        // The parsed Uxf keeps the limit for its comment setter
+        assert_eq!(uxo.max_comment_len(), Some(10));
+        let err = uxo.set_comment(Some(&long)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 10 characters long, got 1000 \
+            characters"
+        );
+        assert_eq!(uxo.comment(), Some("é".repeat(10).as_str()));
+        uxo.set_max_comment_len(None);
+        uxo.set_comment(Some(&long)).unwrap();
+        assert_eq!(uxo.comment(), Some(long.as_str()));
+        // The List, Map, and Table setters take the limit to use
+        let max_len = Some(10);
+        let mut lst = List::new(None, None).unwrap();
+        let err = lst.set_comment(Some(&long), max_len).unwrap_err();
+        assert!(err.to_string().starts_with("#182:"), "{}", err);
+        let mut m = Map::new(None, None, None).unwrap();
+        let err = m.set_comment(Some(&long), max_len).unwrap_err();
+        assert!(err.to_string().starts_with("#182:"), "{}", err);
+        let mut table =
+            Table::new(TClass::new_fieldless("T", Some(&long)).unwrap());
+        let err = table.set_comment(Some(&long), max_len).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#182:comments may be at most 10 characters long, got 1000 \
+            characters"
+        );
+        assert_eq!(table.comment(), None);
+        table.set_comment(Some(&long), None).unwrap();
+        assert_eq!(table.comment(), Some(long.as_str()));
+    }
+
+    #[test]
+    fn t_real_to_uxf() {
+        for (r, expected) in [
+            // Whole numbers
+            (5.0, "5.0"),
+            (-5.0, "-5.0"),
+            (0.0, "0.0"),
+            (-0.0, "-0.0"),
+            (1e10, "10000000000.0"),
+            // Fractions
+            (0.25, "0.25"),
+            (-3.5, "-3.5"),
+            (0.1, "0.1"),
+            // Exponents
+            (1e16, "1e16"),
+            (1.5e300, "1.5e300"),
+            (1e-7, "1e-7"),
+            (-2.5e-10, "-2.5e-10"),
+            (f64::MAX, "1.7976931348623157e308"),
+        ] {
+            let text = real_to_uxf(r).unwrap();
+            assert_eq!(text, expected);
+            // Every real is read back as exactly the same real
+            let uxo = parse(&format!("uxf 1.0\n[{}]\n", text)).unwrap();
+            assert_eq!(
+                uxo.to_string(),
+                format!("uxf 1.0\n[{}]\n", expected)
+            );
+            let value = match uxo.value() {
+                Value::List(lst) => lst.get(0).unwrap().clone().unwrap(),
+                _ => panic!("expected a list"),
+            };
+            assert_eq!(value, Value::Real(r));
+        }
+        for r in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+            let err = real_to_uxf(r).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("#386:reals must be finite, got {}", r)
+            );
+        }
+    }
+
+    #[test]
+    fn t_bytes_to_uxf() {
+        for (b, text) in [
+            (&b""[..], "(::)"),
+            (b"Hello", "(:48656C6C6F:)"),
+            (&[0, 15, 16, 255], "(:000F10FF:)"),
+        ] {
+            assert_eq!(bytes_to_uxf(b), text);
+            assert_eq!(uxf_to_bytes(text).unwrap(), b);
+            assert_eq!(uxf_to_bytes(&text[2..text.len() - 2]).unwrap(), b);
+        }
+        // Whitespace is ignored and case doesn't matter
+        assert_eq!(uxf_to_bytes("(:48 65 6c\n6C\t6F:)").unwrap(), b"Hello");
+        assert_eq!(uxf_to_bytes(" ").unwrap(), b"");
+        for (text, expected) in [
+            (
+                "486",
+                "#200:expected an even number of hex digits, got \"486\"",
+            ),
+            (
+                "(:48 6:)",
+                "#200:expected an even number of hex digits, got \
+                \"(:48 6:)\"",
+            ),
+            (
+                "4G",
+                "#202:bytes may only contain hex digits, got 'G' in \"4G\"",
+            ),
+            (
+                "(:48:",
+                "#202:bytes may only contain hex digits, got '(' in \
+                \"(:48:\"",
+            ),
+        ] {
+            let err = uxf_to_bytes(text).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+        // The parser uses the same rules (and adds the position)
+        let err = parse("uxf 1.0\n[1 (:4 8 6:)]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#200:expected an even number of hex digits, got \"4 8 6\" \
+            (line 2, col 4)"
+        );
+        let err = parse("uxf 1.0\n[(:4x:)]\n").unwrap_err();
+        assert!(err.to_string().starts_with("#202:"), "{}", err);
+    }
+
+    #[test]
+    fn t_escape_str() {
+        for (text, escaped) in [
+            ("", ""),
+            ("plain", "plain"),
+            ("&", "&amp;"),
+            ("<", "&lt;"),
+            (">", "&gt;"),
+            ("x < y && y > z", "x &lt; y &amp;&amp; y &gt; z"),
+            ("<&>", "&lt;&amp;&gt;"),
+            ("&lt;", "&amp;lt;"),
+            ("café <ü>", "café &lt;ü&gt;"),
+        ] {
+            assert_eq!(escape_str(text), escaped);
+            assert_eq!(unescape_str(escaped).unwrap(), text);
+        }
+        // Adjacent entities are each unescaped exactly once
+        assert_eq!(unescape_str("&amp;&lt;&gt;&amp;").unwrap(), "&<>&");
+        assert_eq!(unescape_str("&amp;amp;").unwrap(), "&amp;");
+        for (text, bad) in [
+            ("a & b", "& b"),
+            ("&", "&"),
+            ("&amp", "&amp"),
+            ("&lt;&quot;", "&quot;"),
+            ("AT&T", "&T"),
+        ] {
+            let err = unescape_str(text).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "#272:a str's & must start &amp;, &lt;, or &gt;, got \
+                    {:?}",
+                    bad
+                )
+            );
+        }
+        // The parser rejects a bare & in strs and comments
+        let err = parse("uxf 1.0\n[1 <AT&T>]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#272:a str's & must start &amp;, &lt;, or &gt;, got \"&T\" \
+            (line 2, col 4)"
+        );
+        let err = parse("uxf 1.0\n[#<a & b> 1]\n").unwrap_err();
+        assert!(err.to_string().starts_with("#272:"), "{}", err);
+        let text = "uxf 1.0\n[#<&lt;&amp;&gt;> <AT&amp;T> <&lt;&gt;>]\n";
+        let uxo = parse(text).unwrap();
+        let Value::List(lst) = uxo.value() else {
+            panic!("expected a list");
+        };
+        assert_eq!(lst.comment(), Some("<&>"));
+        assert_eq!(lst.get(0), Some(&Some(Value::Str("AT&T".to_string()))));
+        assert_eq!(lst.get(1), Some(&Some(Value::Str("<>".to_string()))));
+        assert_eq!(uxo.to_string(), text);
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use uxf::error::Warning;
+    use uxf::field::make_fields;
+    use uxf::parser::{parse, parse_with_options, ParseOptions};
+    use uxf::tclass::TClass;
+    use uxf::test_utils::assert_roundtrip;
+    use uxf::uxf::{MergePolicy, ValidationError};
+    use uxf::Uxf;
+
+    #[test]
+    fn t_uxf_roundtrip_all_types() {
+        let text = "uxf 1.0 All Types
+#<File &lt;comment&gt;>
+=#<enum> StateReady
+=Point x:int y:int
+=Pair first second
+[#<every type> ? yes no (:48 65 6c 6C 6F:) (::) 2022-09-21
+2022-09-21T14:30:05 -17 0 9223372036854775807 3.5 -0.25 1e-9 7.0
+<hello &amp; &lt;goodbye&gt;> <> <multi
+line>
+[int 1 2 ?] [] [#<empty>] {str int <a> 1 <b> ?} {}
+{(:00 FF:) 2022-01-01 2022-01-02 <date key>}
+(Point 1 2 3 4) (StateReady) (#<nested> Pair (Pair 1 [2]) {})
+]
+";
+        let uxo = parse(text).unwrap();
+        assert_roundtrip(&uxo);
+    }
+
+    #[test]
+    fn t_uxf_add_tclass_merge() {
+        let text = "uxf 1.0\n=Point x:int y:int\n(Point 1 2)\n";
+        let point = |pairs| {
+            TClass::new("Point", make_fields(pairs).unwrap(), None).unwrap()
+        };
+        let reordered = point(&[("y", "int"), ("x", "int")]);
+        let mut uxo = parse(text).unwrap();
+        uxo.add_tclass_merge(reordered.clone(), MergePolicy::AllowReorder)
+            .unwrap();
+        assert_eq!(uxo.to_string(), text); // original order kept
+        let err = uxo
+            .add_tclass_merge(reordered, MergePolicy::Strict)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#692:ttype definitions for Point have the same fields but in \
+            a different order"
+        );
+        let same = point(&[("x", "int"), ("y", "int")]);
+        uxo.add_tclass_merge(same, MergePolicy::Strict).unwrap();
+        let retyped = point(&[("y", "real"), ("x", "int")]);
+        let err = uxo
+            .add_tclass_merge(retyped, MergePolicy::AllowReorder)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#528:conflicting ttype definitions for Point"
+        );
+        let pair = TClass::new(
+            "Pair",
+            make_fields(&[("a", ""), ("b", "")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        uxo.add_tclass_merge(pair, MergePolicy::Strict).unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=Pair a b\n=Point x:int y:int\n(Point 1 2)\n"
+        );
+    }
+
+    #[test]
+    fn t_uxf_rename_ttype() {
+        let mut uxo = parse(
+            "uxf 1.0\n=#<line> Line start:Point end:Point\n\
+            =Point x:int y:int\n[(Line (Point 1 2) (Point 3 4)) \
+            [Point (Point 5 6)] {int Point 1 (Point 7 8)}]\n",
+        )
+        .unwrap();
+        uxo.rename_ttype("Point", "Coord").unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n=Coord x:int y:int\n\
+            =#<line> Line start:Coord end:Coord\n[(Line (Coord 1 2) \
+            (Coord 3 4)) [Coord (Coord 5 6)] {int Coord 1 (Coord 7 8)}]\n"
+        );
+        assert_roundtrip(&uxo);
+        let err = uxo.rename_ttype("Point", "Dot").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#696:cannot rename nonexistent ttype Point"
+        );
+        let err = uxo.rename_ttype("Coord", "Line").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#698:cannot rename ttype Coord to existing ttype Line"
+        );
+        let err = uxo.rename_ttype("Coord", "int").unwrap_err();
+        assert!(err.to_string().starts_with("#304:"));
+    }
+
+    #[test]
+    fn t_uxf_ttype_cycles() {
+        let uxo =
+            parse("uxf 1.0\n=Node value:int child:Node\n(Node)\n").unwrap();
+        assert_eq!(uxo.ttype_cycles(), vec![vec!["Node".to_string()]]);
+        let uxo = parse(
+            "uxf 1.0\n=Point x:int y:int\n=Line start:Point end:Point\n\
+            =A b:B p:Point\n=B c:C\n=C a:A b:B\n=Tree left:Tree \
+            right:Tree\n[]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.ttype_cycles(),
+            vec![
+                vec!["A".to_string(), "B".to_string(), "C".to_string()],
+                vec!["B".to_string(), "C".to_string()],
+                vec!["Tree".to_string()],
+            ]
+        );
+        let uxo = parse(
+            "uxf 1.0\n=Point x:int y:int\n=Line start:Point end:Point\n[]\n",
+        )
+        .unwrap();
+        assert!(uxo.ttype_cycles().is_empty());
+    }
+
+    #[test]
+    fn t_uxf_new() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x:int y:int\n=Pair a b\n\
+            [(Pair (Point 1 2) 3)]\n",
+        )
+        .unwrap();
+        let mut uxo2 = Uxf::new(uxo.value().clone());
+        assert_eq!(uxo2, uxo);
+        assert_eq!(uxo2.custom(), None);
+        assert_eq!(uxo2.comment(), None);
+        assert_eq!(uxo2.tclasses().len(), 2);
+        assert_eq!(uxo2.tclass("Point").unwrap().len(), 2);
+        assert!(uxo2.tclass("Line").is_none());
+        uxo2.set_custom(Some("MyApp 1.0")).unwrap();
+        assert_eq!(uxo2.custom(), Some("MyApp 1.0"));
+        uxo2.set_comment(Some("points")).unwrap();
+        assert_eq!(
+            uxo2.to_string(),
+            "uxf 1.0 MyApp 1.0\n#<points>\n=Pair a b\n=Point x:int y:int\n\
+            [(Pair (Point 1 2) 3)]\n"
+        );
+        assert_roundtrip(&uxo2);
+        let err = uxo2.set_custom(Some("two\nlines")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#114:custom header text may not contain newlines"
+        );
+        uxo2.set_custom(Some("")).unwrap();
+        assert_eq!(uxo2.custom(), None);
+        let line = TClass::new(
+            "Line",
+            make_fields(&[("start", "Point"), ("end", "Point")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        uxo2.add_tclass(line.clone()).unwrap();
+        uxo2.add_tclass(line).unwrap(); // harmless duplicate
+        assert_eq!(uxo2.tclasses().len(), 3);
+        let point = TClass::new(
+            "Point",
+            make_fields(&[("x", "real"), ("y", "real")]).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert!(uxo2.add_tclass(point).is_err());
+    }
+
+    #[test]
+    fn t_uxf_custom_verbatim() {
+        for custom in
+            ["MyApp Data v3", " MyApp  Data v3", "  MyApp\tData v3  "]
+        {
+            let text = format!("uxf 1.0 {}\n[]\n", custom);
+            let uxo = parse(&text).unwrap();
+            assert_eq!(uxo.custom(), Some(custom));
+            assert_eq!(uxo.to_string(), text);
+            assert_roundtrip(&uxo);
+            let mut uxo2 = Uxf::new(uxo.value().clone());
+            uxo2.set_custom(Some(custom)).unwrap();
+            assert_eq!(uxo2, uxo);
+        }
+        // A CRLF line ending isn't part of the custom text
+        let uxo = parse("uxf 1.0 MyApp Data v3\r\n[]\n").unwrap();
+        assert_eq!(uxo.custom(), Some("MyApp Data v3"));
+        assert_eq!(parse("uxf 1.0\r\n[]\n").unwrap().custom(), None);
+    }
+
+    #[test]
+    fn t_uxf_to_tree_string() {
+        let uxo = parse(
+            "uxf 1.0 MyApp\n=Point x:int y\n[1 <a long string that will \
+            certainly be truncated by the tree view> {<k> [2 3] <j> ?} \
+            (Point 1 ? 3 [4])]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_tree_string(),
+            "uxf 1.0 MyApp
+└── list[4 items]
+    ├── [0] int 1
+    ├── [1] str <a long string that will certainly be …>
+    ├── [2] map[2 items]
+    │   ├── <j> ?
+    │   └── <k> list[2 items]
+    │       ├── [0] int 2
+    │       └── [1] int 3
+    └── [3] (Point 2 records)
+        ├── [0]
+        │   ├── x int 1
+        │   └── y ?
+        └── [1]
+            ├── x int 3
+            └── y list[1 item]
+                └── [0] int 4
+"
+        );
+        let uxo = parse("uxf 1.0\n{}\n").unwrap();
+        assert_eq!(uxo.to_tree_string(), "uxf 1.0\n└── map[0 items]\n");
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn t_uxf_cache_bytes() {
+        let text = "uxf 1.0 Cached
+#<File comment>
+=#<enum> StateReady
+=Point x:int y:int
+=Pair first second
+[#<every type> ? yes no (:48 65 6C 6C 6F:) (::) 2022-09-21
+2022-09-21T14:30:05 -17 0 9223372036854775807 -9223372036854775808 3.5
+-0.25 1e-9 7.0 <hello> <> <multi
+line> [int 1 2 ?] [] [#<empty>] {str int <a> 1 <b> ?} {}
+{(:00 FF:) 2022-01-01 2022-01-02 <date key> -3 ?
+2022-01-02T03:04:05 {}}
+(Point 1 2 3 4) (StateReady) (#<nested> Pair (Pair 1 [2]) {})
+]
+";
+        let uxo = parse(text).unwrap();
+        let bytes = uxo.to_cache_bytes();
+        let cached = Uxf::from_cache_bytes(&bytes).unwrap();
+        assert_eq!(cached, uxo);
+        assert_eq!(cached.to_string(), uxo.to_string());
+        assert!(bytes.len() < text.len(), "{}", bytes.len());
+        // A table whose tclass differs from the ``Uxf``'s one of the same
+        // ttype is cached with its own tclass
+        let mut uxo = parse("uxf 1.0\n=P a\n[(P 1)]\n").unwrap();
+        let p2 =
+            TClass::new("P", make_fields(&[("b", "str")]).unwrap(), None)
+                .unwrap();
+        let mut t = uxf::table::Table::new(p2);
+        t.append(vec![Some("x".into())]).unwrap();
+        if let uxf::Value::List(lst) = uxo.value_mut() {
+            lst.push(Some(uxf::Value::Table(t))).unwrap();
+        }
+        let cached = Uxf::from_cache_bytes(&uxo.to_cache_bytes()).unwrap();
+        assert_eq!(cached, uxo);
+        // Every test file roundtrips through the cache
+        for entry in std::fs::read_dir("../testdata").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "uxf") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            if let Ok(uxo) = parse(&text) {
+                let cached = Uxf::from_cache_bytes(&uxo.to_cache_bytes())
+                    .unwrap_or_else(|err| panic!("{:?}: {}", path, err));
+                assert_eq!(cached, uxo, "{:?}", path);
+            }
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn t_uxf_cache_bytes_errors() {
+        let uxo = parse("uxf 1.0\n[1 <two> 3.0]\n").unwrap();
+        let bytes = uxo.to_cache_bytes();
+        // A cache written by an incompatible version is rejected
+        let mut old = bytes.clone();
+        old[4..6].copy_from_slice(&99u16.to_le_bytes());
+        let err = Uxf::from_cache_bytes(&old).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#802:unsupported UXF cache version 99 (expected 1); the cache \
+             must be rebuilt from the UXF text"
+        );
+        for data in [&b""[..], b"uxf 1.0\n[]\n", b"UXF"] {
+            let err = Uxf::from_cache_bytes(data).unwrap_err();
+            assert_eq!(err.to_string(), "#800:not UXF cache data");
+        }
+        // Every truncation is detected
+        for end in 4..bytes.len() {
+            let err = Uxf::from_cache_bytes(&bytes[..end]).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "#804:UXF cache data is truncated or corrupt",
+                "{}",
+                end
+            );
+        }
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(Uxf::from_cache_bytes(&extra).is_err());
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn t_uxf_cache_bytes_tclass_comments() {
+        // A table whose tclass only differs from the ``Uxf``'s one of the
+        // same ttype by its comment keeps its own comment
+        let mut uxo = parse("uxf 1.0\n=#<A> P a\n[(P 1)]\n").unwrap();
+        let p = TClass::new("P", make_fields(&[("a", "")]).unwrap(), None)
+            .unwrap();
+        let mut t = uxf::table::Table::new(p);
+        t.push_record(vec![Some(2.into())]).unwrap();
+        if let uxf::Value::List(lst) = uxo.value_mut() {
+            lst.push(Some(uxf::Value::Table(t))).unwrap();
+        }
+        let cached = Uxf::from_cache_bytes(&uxo.to_cache_bytes()).unwrap();
+        let uxf::Value::List(lst) = cached.value() else {
+            panic!("expected a list");
+        };
+        let comments: Vec<_> = lst
+            .iter()
+            .map(|value| match value {
+                Some(uxf::Value::Table(t)) => t.tclass().comment(),
+                _ => panic!("expected a table"),
+            })
+            .collect();
+        assert_eq!(comments, [Some("A"), None]);
+        assert_eq!(cached.to_string(), uxo.to_string());
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn t_uxf_cache_bytes_depth() {
+        let nested = |depth| {
+            format!("uxf 1.0\n{}{}\n", "[".repeat(depth), "]".repeat(depth))
+        };
+        let uxo = parse(&nested(256)).unwrap();
+        let cached = Uxf::from_cache_bytes(&uxo.to_cache_bytes()).unwrap();
+        assert_eq!(cached, uxo);
+        let uxo = parse(&nested(257)).unwrap();
+        let err = Uxf::from_cache_bytes(&uxo.to_cache_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#808:UXF cache data is nested more than 256 levels deep"
+        );
+        // Deeply nested corrupt data is rejected without overflowing
+        let mut bytes = uxo.to_cache_bytes();
+        bytes.truncate(8); // magic version custom comment
+        bytes.push(0); // no tclasses
+        bytes.extend([9, 0, 0, 1].repeat(100_000)); // [[[...
+        let err = Uxf::from_cache_bytes(&bytes).unwrap_err();
+        assert!(err.to_string().starts_with("#808:"), "{}", err);
+    }
+
+    #[test]
+    fn t_uxf_intern() {
+        let mut text = String::from(
+            "uxf 1.0\n=#<A point> Point x:int y:int\n=Pair a b\n[",
+        );
+        for i in 0..1000 {
+            text.push_str(&format!("(Point {} {}) ", i, i + 1));
+        }
+        text.push_str("[(Pair (Pair 1 2) (Point 3 4))]]\n");
+        let original = parse(&text).unwrap();
+        let mut uxo = original.clone();
+        // Every table (including the nested ones) except the first of
+        // each ttype gets a shared copy of its tclass
+        assert_eq!(uxo.intern(), 1001);
+        assert_eq!(uxo, original);
+        assert_eq!(uxo.to_string(), original.to_string());
+        assert_eq!(uxo.intern(), 0); // already interned
+                                     // Interning doesn't change equality with uninterned documents
+        let other =
+            parse(&text.replace("(Point 3 4)", "(Point 3 5)")).unwrap();
+        assert_ne!(uxo, other);
+        let mut other = other;
+        other.intern();
+        assert_ne!(uxo, other);
+        // Only identical tclasses are shared
+        let mut uxo = parse("uxf 1.0\n=P a\n[(P 1)]\n").unwrap();
+        let p = TClass::new("P", make_fields(&[("b", "")]).unwrap(), None)
+            .unwrap();
+        if let uxf::Value::List(lst) = uxo.value_mut() {
+            for i in 0..3 {
+                let mut t = uxf::table::Table::new(p.clone());
+                t.append(vec![Some(i.into())]).unwrap();
+                lst.push(Some(uxf::Value::Table(t))).unwrap();
+            }
+        }
+        let original = uxo.clone();
+        assert_eq!(uxo.intern(), 2);
+        assert_eq!(uxo, original);
+    }
+
+    #[test]
+    fn t_uxf_value_type_histogram() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n[1 2.5 <a> yes no ? (:AB:) 2022-10-16 \
+            2022-10-16T12:00 {<z> [3 <b>] <y> ?} (Point 4 5 ? 6.5) [[[7]]]]\n",
+        )
+        .unwrap();
+        let histogram = uxo.value_type_histogram();
+        let expected: HashMap<&str, usize> = [
+            ("bool", 2),
+            ("bytes", 1),
+            ("date", 1),
+            ("datetime", 1),
+            ("int", 5),
+            ("list", 5),
+            ("map", 1),
+            ("null", 3),
+            ("real", 2),
+            ("str", 2),
+            ("table", 1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(histogram, expected);
+        let uxo = parse("uxf 1.0\n[]\n").unwrap();
+        assert_eq!(uxo.value_type_histogram(), [("list", 1)].into());
+    }
+
+    #[test]
+    fn t_uxf_validate() {
+        let text = "uxf 1.0\n[int 1 <two> 3 ?]\n";
+        let uxo = parse(text).unwrap();
+        let warnings: Vec<Warning> = uxo.validate().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code(), 372);
+        assert_eq!(
+            warnings[0].message(),
+            "value[1]: expected int, got str"
+        );
+        assert_eq!(warnings[0].lineno(), None);
+        // With spans the warnings give the value's position
+        let options =
+            ParseOptions { record_spans: true, ..Default::default() };
+        let uxo = parse_with_options(text, &options).unwrap();
+        let warnings = uxo.validate().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "#372:value[1]: expected int, got str (bytes 15..20)"
+        );
+        assert_eq!(&text[15..20], "<two>");
+        // Nested lists, maps, and tables are checked
+        let uxo = parse(
+            "uxf 1.0\n=P x:int y\n{str list <a> [real 1.5 2] \
+             <b> [(P 1 <y> 2.5 3)] <c> [P (P <x> ?)]}\n",
+        )
+        .unwrap();
+        let warnings: Vec<String> =
+            uxo.validate().unwrap().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            [
+                "#372:value{<a>}[1]: expected real, got int",
+                "#372:value{<b>}[0][1].x: expected int, got real",
+                "#372:value{<c>}[0][0].x: expected int, got str",
+            ]
+        );
+        // Valid documents have no warnings
+        let uxo =
+            parse("uxf 1.0\n=P x:int\n[[int 1 ?] (P 1 ?)]\n").unwrap();
+        assert!(uxo.validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn t_uxf_validate_all() {
+        // A valid document has no problems
+        let uxo =
+            parse("uxf 1.0\n=P x:int\n[[int 1 ?] (P 1 ?)]\n").unwrap();
+        assert!(uxo.validate_all().is_empty());
+        // Fields may have vtypes that aren't defined
+        let mut uxo = parse(
+            "uxf 1.0\n=Line start:Point end:Point\n=Point x:int y:int\n\
+            =Shape name:str outline:Curve\n\
+            [[int 1 <two> 3] (Point 1 2 <3> 4.0) (Line ? ?)]\n",
+        )
+        .unwrap();
+        let tclass =
+            TClass::new("Pair", make_fields(&[("a", "")]).unwrap(), None)
+                .unwrap();
+        let mut pair = uxf::table::Table::new(tclass);
+        pair.append(vec![Some(true.into())]).unwrap();
+        let curves = uxf::list::List::new(Some("Curve"), None).unwrap();
+        if let uxf::Value::List(lst) = uxo.value_mut() {
+            lst.push(Some(uxf::Value::Table(pair))).unwrap();
+            lst.push(Some(uxf::Value::List(curves))).unwrap();
+        }
+        let errors = uxo.validate_all();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            [
+                "#694:Shape.outline: undefined ttype Curve",
+                "#694:value[3]: undefined ttype Pair",
+                "#694:value[4]: undefined ttype Curve",
+                "#372:value[0][1]: expected int, got str",
+                "#372:value[1][1].x: expected int, got str",
+                "#372:value[1][1].y: expected int, got real",
+            ]
+        );
+        assert_eq!(
+            errors[0],
+            ValidationError::MissingTType {
+                path: "Shape.outline".to_string(),
+                ttype: "Curve".to_string()
+            }
+        );
+        assert!(matches!(&errors[3], ValidationError::Mismatch(err)
+                         if err.code() == 372));
+        // validate() errors at the first undefined ttype in the value
+        let err = uxo.validate().unwrap_err();
+        assert_eq!(err.to_string(), "#694:value[3]: undefined ttype Pair");
+        // and otherwise only reports the mismatches
+        if let uxf::Value::List(lst) = uxo.value_mut() {
+            lst.splice(3.., vec![]).unwrap();
+        }
+        assert_eq!(uxo.validate().unwrap().len(), 3);
+    }
+}
@@ -3,8 +3,19 @@
 
 #[cfg(test)]
 mod tests {
-    use uxf::test_utils::{opt_value_to_str, value_to_str};
-    use uxf::value::Value;
+    use chrono::NaiveDate;
+    use std::collections::{BTreeMap, HashMap};
+    use uxf::list::List;
+    use uxf::map::Map;
+    use uxf::parser::parse;
+    use uxf::table::Table;
+    use uxf::test_utils::{opt_value_to_str, value_diff, value_to_str};
+    use uxf::uxf_value;
+    use uxf::value::{
+        opt_type_name, Key, ListOrder, PathSegment, Value, ValueKey,
+    };
+    use uxf::visitor::Visitor;
+    use uxf::Uxf;
 
     #[test]
     fn t_single_value() {
@@ -18,4 +29,928 @@ mod tests {
         assert_eq!(value_to_str(i), "987123");
         // TODO lots more tests
     }
+
+    #[test]
+    fn t_iter_paths() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n\
+            [1 ? {<k> [yes (Point 5 6 7 8)]} <end>]\n",
+        )
+        .unwrap();
+        let paths: Vec<(Vec<PathSegment>, &Value)> =
+            uxo.value().iter_paths().collect();
+        // root list, 1, map, list, yes, table, 5, 6, 7, 8, <end>
+        assert_eq!(paths.len(), 11);
+        assert!(paths[0].0.is_empty());
+        assert_eq!(paths[0].1, uxo.value());
+        assert_eq!(paths[1], (vec![PathSegment::Index(0)], &Value::Int(1)));
+        let (path, _) =
+            paths.iter().find(|(_, v)| **v == Value::Int(8)).unwrap();
+        assert_eq!(
+            path,
+            &vec![
+                PathSegment::Index(2),
+                PathSegment::Key(Key::Str("k".to_string())),
+                PathSegment::Index(1),
+                PathSegment::Cell(1, "y".to_string()),
+            ]
+        );
+        assert_eq!(
+            paths.last().unwrap(),
+            &(vec![PathSegment::Index(3)], &Value::Str("end".to_string()))
+        );
+    }
+
+    #[test]
+    fn t_uxf_value_macro() {
+        let parsed = |text: &str| {
+            parse(&format!("uxf 1.0\n{}\n", text)).unwrap().value().clone()
+        };
+        assert_eq!(uxf_value!([]), parsed("[]"));
+        assert_eq!(uxf_value!({}), parsed("{}"));
+        assert_eq!(uxf_value!([1 2 <three>]), parsed("[1 2 <three>]"));
+        assert_eq!(uxf_value!({<k> 1}), parsed("{<k> 1}"));
+        assert_eq!(
+            uxf_value!([-7 0.5 -2.25 ? yes no <"two words"> [[]] {}]),
+            parsed("[-7 0.5 -2.25 ? yes no <two words> [[]] {}]")
+        );
+        assert_eq!(
+            uxf_value!({1 [<a> <b>] 2 ? <key> {<inner> -1}}),
+            parsed("{1 [<a> <b>] 2 ? <key> {<inner> -1}}")
+        );
+        assert_ne!(uxf_value!([1 2]), parsed("[2 1]"));
+    }
+
+    #[test]
+    fn t_simplify() {
+        let uxo = parse(
+            "uxf 1.0\n=Pair first second\n\
+            [[int ? ?] [int] [int 1 ?] [str] {str int <a> ?} \
+            {str int} {date} {str int <b> 2} (Pair [real] [real ? 1.5])]\n",
+        )
+        .unwrap();
+        let mut value = uxo.value().clone();
+        value.simplify();
+        let expected = parse(
+            "uxf 1.0\n=Pair first second\n\
+            [[? ?] [] [int 1 ?] [] {str <a> ?} \
+            {} {} {str int <b> 2} (Pair [] [real ? 1.5])]\n",
+        )
+        .unwrap();
+        assert_eq!(&value, expected.value());
+        // All-null typed list loses its type
+        let mut value =
+            parse("uxf 1.0\n[int ? ?]\n").unwrap().value().clone();
+        value.simplify();
+        assert_eq!(&value, parse("uxf 1.0\n[? ?]\n").unwrap().value());
+    }
+
+    #[test]
+    fn t_value_summary() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n[<hello world> <two\nlines> \
+            (Point 1 2 3 4 5 6) {1 2} [] 3.5 (:AB CD:)]\n",
+        )
+        .unwrap();
+        let values: Vec<&Value> =
+            uxo.value().iter_paths().map(|(_, v)| v).collect();
+        assert_eq!(values[0].summary(80), "list[7 items]");
+        assert_eq!(values[1].summary(80), "<hello world>");
+        assert_eq!(values[1].summary(11), "<hello wo…>");
+        assert_eq!(values[1].summary(2), "<…");
+        assert_eq!(values[2].summary(80), "<two lines>");
+        assert_eq!(values[3].summary(80), "(Point 3 records)");
+        assert_eq!(values[3].summary(8), "(Point …");
+        assert_eq!(values[10].summary(80), "map[1 item]");
+        assert_eq!(values[12].summary(80), "list[0 items]");
+        assert_eq!(values[13].summary(80), "3.5");
+        assert_eq!(values[14].summary(80), "(:ABCD:)");
+        assert_eq!(values[14].summary(0), "");
+        // Long strs and big lists stay within bounds
+        let long = Value::Str("x".repeat(1000));
+        let summary = long.summary(20);
+        assert_eq!(summary.chars().count(), 20);
+        assert!(summary.ends_with("x…>"));
+        let big = parse(&format!(
+            "uxf 1.0\n[{}]\n",
+            (0..100).map(|i| i.to_string()).collect::<Vec<_>>().join(" ")
+        ))
+        .unwrap();
+        assert_eq!(big.value().summary(80), "list[100 items]");
+        assert_eq!(big.value().summary(10), "list[100 …");
+    }
+
+    #[test]
+    fn t_value_is_empty() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n=Empty\n[[] {} (Point) (Empty) <> (::) \
+            [?] {1 ?} (Point 1 2) <x> (:00:) no 0 0.0 2022-01-01 \
+            2022-01-01T00:00:00]\n",
+        )
+        .unwrap();
+        let values: Vec<&Value> = uxo
+            .value()
+            .iter_paths()
+            .filter(|(path, _)| path.len() == 1)
+            .map(|(_, v)| v)
+            .collect();
+        assert_eq!(values.len(), 16);
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(value.is_empty(), i < 6, "{} {:?}", i, value);
+        }
+        assert!(!uxo.value().is_empty());
+    }
+
+    #[test]
+    fn t_value_categories() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n[yes (:AB:) 2022-10-01 \
+            2022-10-01T09:30:15 1 [] {} 1.5 <a> (Point 1 2)]\n",
+        )
+        .unwrap();
+        let Value::List(lst) = uxo.value() else {
+            panic!("expected a list");
+        };
+        // (is_scalar, is_collection, can_be_key) for each value
+        let expected = [
+            ("bool", true, false, false),
+            ("bytes", true, false, true),
+            ("date", true, false, true),
+            ("datetime", true, false, true),
+            ("int", true, false, true),
+            ("list", false, true, false),
+            ("map", false, true, false),
+            ("real", true, false, false),
+            ("str", true, false, true),
+            ("table", false, true, false),
+        ];
+        assert_eq!(lst.len(), expected.len());
+        for (value, (name, scalar, collection, key)) in
+            lst.iter().flatten().zip(expected)
+        {
+            assert_eq!(value.type_name(), name);
+            assert_eq!(value.is_scalar(), scalar, "{}", name);
+            assert_eq!(value.is_collection(), collection, "{}", name);
+            assert_eq!(value.can_be_key(), key, "{}", name);
+            assert_eq!(
+                Key::try_from(value.clone()).is_ok(),
+                key,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn t_value_try_into_key() {
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let datetime = date.and_hms_opt(9, 30, 15).unwrap();
+        for (value, expected) in [
+            (Value::Bytes(vec![0, 255]), Key::Bytes(vec![0, 255])),
+            (Value::Date(date), Key::Date(date)),
+            (Value::DateTime(datetime), Key::DateTime(datetime)),
+            (Value::Int(-7), Key::Int(-7)),
+            (Value::Str("k".to_string()), Key::Str("k".to_string())),
+        ] {
+            let key = Key::try_from(value.clone()).unwrap();
+            assert_eq!(key, expected);
+            assert_eq!(Value::from(key), value);
+        }
+        let err = Key::try_from(Value::Bool(true)).unwrap_err();
+        assert_eq!(err.code(), 356);
+        assert_eq!(
+            err.to_string(),
+            "#356:a map key must be bytes, date, datetime, int, or str, \
+             got bool"
+        );
+        let result: Result<Key, _> = Value::Real(1.5).try_into();
+        assert_eq!(
+            result.unwrap_err().message(),
+            "a map key must be bytes, date, datetime, int, or str, got real"
+        );
+        let lst = List::new(None, None).unwrap();
+        assert!(Key::try_from(Value::List(lst)).is_err());
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn t_value_categories_bigint() {
+        let value = Value::BigInt(num_bigint::BigInt::from(1) << 70);
+        assert!(value.is_scalar());
+        assert!(!value.is_collection());
+        assert!(!value.can_be_key());
+        assert!(Key::try_from(value).is_err());
+    }
+
+    #[test]
+    fn t_value_try_add() {
+        let sum = Value::Int(2).try_add(&Value::Int(-5)).unwrap();
+        assert_eq!(sum, Value::Int(-3));
+        let sum = Value::Int(2).try_add(&Value::Real(0.5)).unwrap();
+        assert_eq!(sum, Value::Real(2.5));
+        let sum = Value::Real(0.25).try_add(&Value::Int(1)).unwrap();
+        assert_eq!(sum, Value::Real(1.25));
+        let sum = Value::Real(0.25).try_add(&Value::Real(0.5)).unwrap();
+        assert_eq!(sum, Value::Real(0.75));
+        let err = Value::Int(i64::MAX).try_add(&Value::Int(1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("#380:int overflow adding {} and 1", i64::MAX)
+        );
+        let err = Value::Real(f64::MAX)
+            .try_add(&Value::Real(f64::MAX))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "#380:real overflow adding {} and {}",
+                f64::MAX,
+                f64::MAX
+            )
+        );
+        // An infinite sum is only an overflow if both inputs are finite
+        let sum =
+            Value::Real(f64::INFINITY).try_add(&Value::Int(1)).unwrap();
+        assert_eq!(sum, Value::Real(f64::INFINITY));
+        let err = Value::Int(1)
+            .try_add(&Value::Str("2".to_string()))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#382:can only add int or real values, got int and str"
+        );
+        let err = Value::Bool(true).try_add(&Value::Real(1.0)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "#382:can only add int or real values, got bool and real"
+        );
+        // Summing a list
+        let lst = uxf_value!([1 2 3.5 4]);
+        let mut total = Value::Int(0);
+        for (_, value) in lst.iter_paths().skip(1) {
+            total = total.try_add(value).unwrap();
+        }
+        assert_eq!(total, Value::Real(10.5));
+    }
+
+    #[test]
+    fn t_value_canonicalize() {
+        // Two equal maps with their items inserted in opposite orders and
+        // with a signed zero in one
+        let n = 50;
+        let forward: Vec<String> =
+            (0..n).map(|i| format!("<k{}> {}.0", i, i)).collect();
+        let mut backward = forward.clone();
+        backward.reverse();
+        backward[n - 1] = "<k0> -0.0".to_string();
+        let mut a = parse(&format!("uxf 1.0\n{{{}}}\n", forward.join(" ")))
+            .unwrap();
+        let mut b =
+            parse(&format!("uxf 1.0\n{{{}}}\n", backward.join(" ")))
+                .unwrap();
+        assert_ne!(a.to_string(), b.to_string());
+        a.value_mut().canonicalize(ListOrder::Preserve);
+        b.value_mut().canonicalize(ListOrder::Preserve);
+        assert_eq!(a, b);
+        assert_eq!(a.to_string(), b.to_string());
+        assert!(a.to_string().starts_with("uxf 1.0\n{<k0> 0.0 <k1> 1.0 "));
+        // The keys are in canonical order in memory too
+        let Value::Map(m) = b.value() else {
+            panic!("expected a map");
+        };
+        let keys: Vec<&Key> = m.keys().collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+        assert_eq!(keys[0], &Key::Str("k0".to_string()));
+        // Lists are only sorted if asked
+        let mut uxo = parse(
+            "uxf 1.0\n[3 ? <b> 1.5 -0.0 <a> [2 1] 1 {<z> 1 <y> 2} no]\n",
+        )
+        .unwrap();
+        uxo.value_mut().canonicalize(ListOrder::Preserve);
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n[3 ? <b> 1.5 0.0 <a> [2 1] 1 {<y> 2 <z> 1} no]\n"
+        );
+        uxo.value_mut().canonicalize(ListOrder::Sort);
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n[? no 1 3 [1 2] {<y> 2 <z> 1} 0.0 1.5 <a> <b>]\n"
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn t_value_canonicalize_bigint() {
+        // Big ints and ints are compared numerically, not as text
+        let mut uxo = parse(
+            "uxf 1.0\n[10 99999999999999999999 -99999999999999999999 9 \
+            -5]\n",
+        )
+        .unwrap();
+        uxo.value_mut().canonicalize(ListOrder::Sort);
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n[-99999999999999999999 -5 9 10 \
+            99999999999999999999]\n"
+        );
+    }
+
+    #[test]
+    fn t_value_accept() {
+        #[derive(Default)]
+        struct Counter {
+            counts: BTreeMap<&'static str, usize>,
+            depth: usize,
+            max_depth: usize,
+            keys: Vec<String>,
+        }
+        impl Counter {
+            fn count(&mut self, name: &'static str) {
+                *self.counts.entry(name).or_default() += 1;
+            }
+            fn begin(&mut self, name: &'static str) {
+                self.count(name);
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+        }
+        impl Visitor for Counter {
+            fn visit_bool(&mut self, _b: bool) {
+                self.count("bool");
+            }
+            fn visit_bytes(&mut self, _b: &[u8]) {
+                self.count("bytes");
+            }
+            fn visit_date(&mut self, _d: &NaiveDate) {
+                self.count("date");
+            }
+            fn visit_int(&mut self, _i: i64) {
+                self.count("int");
+            }
+            fn visit_null(&mut self) {
+                self.count("null");
+            }
+            fn visit_real(&mut self, _r: f64) {
+                self.count("real");
+            }
+            fn visit_str(&mut self, _s: &str) {
+                self.count("str");
+            }
+            fn visit_list(&mut self, _lst: &List) {
+                self.begin("list");
+            }
+            fn end_list(&mut self, _lst: &List) {
+                self.depth -= 1;
+            }
+            fn visit_map(&mut self, _m: &Map) {
+                self.begin("map");
+            }
+            fn visit_key(&mut self, key: &Key) {
+                self.keys.push(format!("{:?}", key));
+            }
+            fn end_map(&mut self, _m: &Map) {
+                self.depth -= 1;
+            }
+            fn visit_table(&mut self, _t: &Table) {
+                self.begin("table");
+            }
+            fn end_table(&mut self, _t: &Table) {
+                self.depth -= 1;
+            }
+        }
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n[1 2.5 <a> yes ? (:AB:) 2022-10-16 \
+            {<z> [3 <b>] <y> ?} (Point 4 5 ? 6.5) [[[7]]]]\n",
+        )
+        .unwrap();
+        let mut counter = Counter::default();
+        uxo.value().accept(&mut counter);
+        let expected: BTreeMap<&str, usize> = [
+            ("bool", 1),
+            ("bytes", 1),
+            ("date", 1),
+            ("int", 5),
+            ("list", 5),
+            ("map", 1),
+            ("null", 3),
+            ("real", 2),
+            ("str", 2),
+            ("table", 1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(counter.counts, expected);
+        assert_eq!(counter.depth, 0);
+        assert_eq!(counter.max_depth, 4);
+        assert_eq!(counter.keys, ["Str(\"y\")", "Str(\"z\")"]);
+        // Scalars visit just themselves
+        let mut counter = Counter::default();
+        Value::Int(1).accept(&mut counter);
+        assert_eq!(counter.counts.len(), 1);
+        assert_eq!(counter.counts["int"], 1);
+    }
+
+    #[test]
+    fn t_value_visit() {
+        #[derive(Default)]
+        struct Counter {
+            counts: BTreeMap<&'static str, usize>,
+            events: Vec<String>,
+        }
+        impl Visitor for Counter {
+            fn on_scalar(&mut self, value: Option<&Value>) {
+                let name = value.map_or("null", |v| v.type_name());
+                *self.counts.entry(name).or_default() += 1;
+            }
+            fn on_list_start(&mut self, lst: &List) {
+                *self.counts.entry("list").or_default() += 1;
+                self.events.push(format!("[{}", lst.len()));
+            }
+            fn on_list_end(&mut self, _lst: &List) {
+                self.events.push("]".to_string());
+            }
+            fn on_map_start(&mut self, m: &Map) {
+                *self.counts.entry("map").or_default() += 1;
+                self.events.push(format!("{{{}", m.len()));
+            }
+            fn on_map_end(&mut self, _m: &Map) {
+                self.events.push("}".to_string());
+            }
+            fn on_table_start(&mut self, t: &Table) {
+                *self.counts.entry("table").or_default() += 1;
+                self.events.push(format!("({}", t.tclass().ttype()));
+            }
+            fn on_table_end(&mut self, _t: &Table) {
+                self.events.push(")".to_string());
+            }
+        }
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n[1 2.5 <a> yes ? (:AB:) 2022-10-16 \
+            {<z> [3 <b>] <y> ?} (Point 4 5 ? 6.5) [[[7]]]]\n",
+        )
+        .unwrap();
+        let mut counter = Counter::default();
+        uxo.value().visit(&mut counter);
+        let expected: BTreeMap<&str, usize> = [
+            ("bool", 1),
+            ("bytes", 1),
+            ("date", 1),
+            ("int", 5),
+            ("list", 5),
+            ("map", 1),
+            ("null", 3),
+            ("real", 2),
+            ("str", 2),
+            ("table", 1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(counter.counts, expected);
+        // Collections start and end in document order (map items in
+        // canonical key order)
+        assert_eq!(counter.events.concat(), "[10{2[2]}(Point)[1[1[1]]]]");
+        // Scalars visit just themselves
+        let mut counter = Counter::default();
+        Value::Int(1).visit(&mut counter);
+        assert_eq!(counter.counts.len(), 1);
+        assert_eq!(counter.counts["int"], 1);
+        assert!(counter.events.is_empty());
+    }
+
+    #[test]
+    fn t_value_new_int_and_real() {
+        for i in [i64::MIN, -1, 0, 1, i64::MAX] {
+            let value = Value::new_int(i);
+            assert_eq!(value, Value::Int(i));
+            let uxo = parse(&format!("uxf 1.0\n[{}]\n", i)).unwrap();
+            assert_eq!(uxo.value().iter_paths().nth(1).unwrap().1, &value);
+        }
+        for r in [f64::MIN, -1.5, 0.0, f64::MIN_POSITIVE, f64::MAX] {
+            assert_eq!(Value::new_real(r).unwrap(), Value::Real(r));
+        }
+        for (r, expected) in [
+            (f64::NAN, "#386:reals must be finite, got NaN"),
+            (f64::INFINITY, "#386:reals must be finite, got inf"),
+            (f64::NEG_INFINITY, "#386:reals must be finite, got -inf"),
+        ] {
+            let err = Value::new_real(r).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn t_value_eq() {
+        let text = "uxf 1.0\n=Point x y\n[1 1.5 <a> (:AB:) 2022-10-16 \
+                    2022-10-16T12:30:00 yes ? [int 2 3] {<k> [4]} \
+                    (Point 5 6 7 ?)]\n";
+        let a = parse(text).unwrap();
+        let b = parse(&a.to_string()).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.value(), b.value());
+        // Any difference, however deeply nested, makes them unequal
+        for other in [
+            "[int 2 3]",
+            "[1 1.5 <a>]",
+            "[1 1.5 <a> (:AB:) 2022-10-16 2022-10-16T12:30:00 yes ? \
+            [2 3] {<k> [4]} (Point 5 6 7 ?)]",
+            "[1 1.5 <a> (:AB:) 2022-10-16 2022-10-16T12:30:00 yes ? \
+            [int 2 3] {<k> [4]} (Point 5 6 7 8)]",
+            "[1 1.5 <a> (:AB:) 2022-10-16 2022-10-16T12:30:00 yes ? \
+            [int 2 3] {<k> [#<x> 4]} (Point 5 6 7 ?)]",
+        ] {
+            let c = parse(&format!("uxf 1.0\n=Point x y\n{}\n", other))
+                .unwrap();
+            assert_ne!(a.value(), c.value(), "{}", other);
+        }
+        // Ints and reals are different types
+        assert_ne!(Value::Int(1), Value::Real(1.0));
+        // NaN equals NaN (but nothing else) so every value equals itself
+        let nan = Value::Real(f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert_eq!(nan, Value::Real(-f64::NAN));
+        assert_ne!(nan, Value::Real(0.0));
+        assert_eq!(Value::Real(0.0), Value::Real(-0.0));
+    }
+
+    #[test]
+    fn t_key_ord() {
+        let date = |d| NaiveDate::from_ymd_opt(2022, 10, d).unwrap();
+        let datetime = |h| date(1).and_hms_opt(h, 0, 0).unwrap();
+        let expected = vec![
+            Key::Bytes(vec![]),
+            Key::Bytes(vec![0, 255]),
+            Key::Bytes(vec![1]),
+            Key::Bytes(vec![1, 0]),
+            Key::Date(date(1)),
+            Key::Date(date(16)),
+            Key::DateTime(datetime(9)),
+            Key::DateTime(datetime(21)),
+            Key::Int(i64::MIN),
+            Key::Int(-1),
+            Key::Int(2),
+            Key::Int(10),
+            Key::Str("".to_string()),
+            Key::Str("B".to_string()),
+            Key::Str("a".to_string()),
+            Key::Str("ab".to_string()),
+            Key::Str("é".to_string()),
+            Key::Str("😀".to_string()),
+        ];
+        let mut keys = expected.clone();
+        keys.reverse();
+        keys.swap(0, 9);
+        keys.swap(3, 14);
+        keys.sort();
+        assert_eq!(keys, expected);
+        // Map items are written in this order
+        let uxo = parse(
+            "uxf 1.0\n{<b> 1 2022-10-16 2 (:01:) 3 10 4 <B> 5 -1 6 \
+            2022-10-01T09:00:00 7}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            uxo.to_string(),
+            "uxf 1.0\n{(:01:) 3 2022-10-16 2 2022-10-01T09:00:00 7 -1 6 \
+            10 4 <B> 5 <b> 1}\n"
+        );
+    }
+
+    #[test]
+    fn t_value_from() {
+        let v: Value = 5.into();
+        assert_eq!(v, Value::Int(5));
+        assert_eq!(Value::from(-7i64), Value::Int(-7));
+        assert_eq!(Value::from(2.5), Value::Real(2.5));
+        assert_eq!(Value::from(true), Value::Bool(true));
+        let v: Value = false.into();
+        assert_eq!(v, Value::Bool(false));
+        assert_eq!(Value::from("hi"), Value::Str("hi".to_string()));
+        let s = String::from("owned");
+        assert_eq!(Value::from(s), Value::Str("owned".to_string()));
+        let v: Value = vec![0xAB, 0xCD].into();
+        assert_eq!(v, Value::Bytes(vec![0xAB, 0xCD]));
+        let d = NaiveDate::from_ymd_opt(2022, 10, 16).unwrap();
+        assert_eq!(Value::from(d), Value::Date(d));
+        let dt = d.and_hms_opt(12, 30, 0).unwrap();
+        assert_eq!(Value::from(dt), Value::DateTime(dt));
+        // Values convert back again
+        assert_eq!(String::try_from(Value::from("hi")).unwrap(), "hi");
+        assert_eq!(i64::try_from(Value::from(5)).unwrap(), 5);
+        let mut lst = List::new(Some("str"), None).unwrap();
+        lst.push(Some("hi".into())).unwrap();
+        lst.push(Some(String::from("there").into())).unwrap();
+        assert!(lst.push(Some(5.into())).is_err());
+        assert_eq!(
+            Uxf::new(Value::List(lst)).to_string(),
+            "uxf 1.0\n[str <hi> <there>]\n"
+        );
+    }
+
+    #[test]
+    fn t_value_real_is_integral() {
+        for r in [3.0, -0.0, 0.0, -12.0, 1e20] {
+            assert!(Value::Real(r).real_is_integral(), "{}", r);
+        }
+        for r in [3.5, -0.25, f64::NAN, f64::INFINITY, 1e-20] {
+            assert!(!Value::Real(r).real_is_integral(), "{}", r);
+        }
+        assert!(!Value::Int(3).real_is_integral());
+        assert!(!Value::from("3.0").real_is_integral());
+        // Integral reals are still written as reals
+        let uxo = parse("uxf 1.0\n[3.0 3 -0.0]\n").unwrap();
+        assert_eq!(uxo.to_string(), "uxf 1.0\n[3.0 3 -0.0]\n");
+        let integral: Vec<bool> = uxo
+            .value()
+            .iter_paths()
+            .skip(1)
+            .map(|(_, v)| v.real_is_integral())
+            .collect();
+        assert_eq!(integral, [true, false, true]);
+    }
+
+    #[test]
+    fn t_value_diff() {
+        let a = parse(
+            "uxf 1.0\n=P x y\n{str <a> [1 2 (P 1 2 3 4)] <b> [#<c> str <s>]}\n",
+        )
+        .unwrap();
+        assert_eq!(value_diff(a.value(), a.value()), "");
+        let b = parse(
+            "uxf 1.0\n=P x y\n{str <a> [1 2 (P 1 2 3 5)] <b> [#<c> str <s>]}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value_diff(a.value(), b.value()),
+            "value{<a>}[2][1].y: 4 != 5"
+        );
+        let b = parse(
+            "uxf 1.0\n=P x y\n{str <a> [1 ? (P 1 2 3 4)] <b> [#<d> <s>]}\n",
+        )
+        .unwrap();
+        let diff = value_diff(a.value(), b.value());
+        let mut lines: Vec<&str> = diff.lines().collect();
+        lines.sort(); // map items are in arbitrary order
+        assert_eq!(
+            lines,
+            [
+                "value{<a>}[1]: 2 != null or absent",
+                "value{<b>}: comment c != d",
+                "value{<b>}: vtype str != none",
+            ]
+        );
+        // Values inside values of different types aren't compared
+        let a = parse("uxf 1.0\n[[1 2] 3]\n").unwrap();
+        let b = parse("uxf 1.0\n[{int 1 2} 3.0 4]\n").unwrap();
+        assert_eq!(
+            value_diff(a.value(), b.value()),
+            "value: length 2 != 3\nvalue[0]: list[2 items] != map[1 item]\n\
+             value[1]: 3 != 3.0\nvalue[2]: null or absent != 4"
+        );
+    }
+
+    #[test]
+    fn t_value_as_number() {
+        assert_eq!(Value::Int(42).as_number(), Some(42.0));
+        assert_eq!(Value::Int(-7).as_number(), Some(-7.0));
+        assert_eq!(Value::Real(2.5).as_number(), Some(2.5));
+        assert_eq!(Value::Real(-0.0).as_number(), Some(0.0));
+        // Large ints are converted lossily
+        assert_eq!(
+            Value::Int(9007199254740993).as_number(),
+            Some(9007199254740992.0)
+        );
+        for value in [
+            Value::Bool(true),
+            Value::Str("1".to_string()),
+            Value::Bytes(vec![1]),
+            Value::List(List::new(None, None).unwrap()),
+        ] {
+            assert_eq!(value.as_number(), None);
+        }
+        let uxo = parse("uxf 1.0\n[1 2.5 <x> 3 ?]\n").unwrap();
+        let total: f64 = match uxo.value() {
+            Value::List(lst) => (0..lst.len())
+                .filter_map(|i| lst.get(i).unwrap().as_ref())
+                .filter_map(Value::as_number)
+                .sum(),
+            _ => panic!("expected a list"),
+        };
+        assert_eq!(total, 6.5);
+    }
+
+    #[test]
+    fn t_value_clone() {
+        let text = "uxf 1.0\n=P x y\n{str <a> [1 [3 2] {int 4 <four>}] \
+                    <b> (P 5 [7 6])}\n";
+        let uxo = parse(text).unwrap();
+        let original = uxo.value().clone();
+        let mut copy = original.clone();
+        assert_eq!(copy, original);
+        // Changing values nested deep inside the copy (here, sorting the
+        // inner lists) doesn't affect the original
+        copy.canonicalize(ListOrder::Sort);
+        assert_ne!(copy, original);
+        assert_eq!(&original, uxo.value());
+        assert_eq!(
+            Uxf::new(copy.clone()).to_string(),
+            "uxf 1.0\n=P x y\n{str <a> [1 [2 3] {int 4 <four>}] \
+             <b> (P 5 [6 7])}\n"
+        );
+        let Value::Map(m) = &mut copy else {
+            panic!("expected a map");
+        };
+        m.remove(&Key::Str("a".to_string()));
+        m.insert(Key::Str("c".to_string()), None).unwrap();
+        let Value::Map(m) = &original else {
+            panic!("expected a map");
+        };
+        assert!(m.contains_key(&Key::Str("a".to_string())));
+        assert!(!m.contains_key(&Key::Str("c".to_string())));
+        assert_eq!(&original, uxo.value());
+    }
+
+    #[test]
+    fn t_value_as() {
+        let uxo = parse(
+            "uxf 1.0\n=P x\n[yes (:AB:) 2022-10-16 2022-10-16T12:30:05 \
+             7 2.5 <hi> [1] {<k> 2} (P 3)]\n",
+        )
+        .unwrap();
+        let values: Vec<Value> = match uxo.value() {
+            Value::List(lst) => (0..lst.len())
+                .map(|i| lst.get(i).unwrap().clone().unwrap())
+                .collect(),
+            _ => panic!("expected a list"),
+        };
+        let date = NaiveDate::from_ymd_opt(2022, 10, 16).unwrap();
+        assert_eq!(values[0].as_bool(), Some(true));
+        assert_eq!(values[1].as_bytes(), Some(&[0xAB][..]));
+        assert_eq!(values[2].as_date(), Some(date));
+        assert_eq!(
+            values[3].as_datetime(),
+            Some(date.and_hms_opt(12, 30, 5).unwrap())
+        );
+        assert_eq!(values[4].as_int(), Some(7));
+        assert_eq!(values[5].as_real(), Some(2.5));
+        assert_eq!(values[6].as_str(), Some("hi"));
+        assert_eq!(values[7].as_list().unwrap().len(), 1);
+        assert_eq!(
+            values[8].as_map().unwrap().get_typed::<i64>("k").unwrap(),
+            Some(2)
+        );
+        assert_eq!(values[9].as_table().unwrap().records_len(), 1);
+        // Mismatched variants (including int vs. real) give None
+        assert_eq!(values[4].as_real(), None);
+        assert_eq!(values[5].as_int(), None);
+        assert_eq!(values[6].as_bool(), None);
+        assert_eq!(values[0].as_str(), None);
+        assert_eq!(values[3].as_date(), None);
+        assert_eq!(values[2].as_datetime(), None);
+        assert_eq!(values[6].as_bytes(), None);
+        assert!(values[8].as_list().is_none());
+        assert!(values[7].as_map().is_none());
+        assert!(values[7].as_table().is_none());
+        // Typical traversal code
+        let ints: Vec<i64> =
+            values.iter().filter_map(Value::as_int).collect();
+        assert_eq!(ints, [7]);
+    }
+
+    #[test]
+    fn t_key_hash_eq() {
+        let keys = [
+            Key::Bytes(vec![0, 255]),
+            Key::Date(NaiveDate::from_ymd_opt(2022, 9, 21).unwrap()),
+            Key::Int(-7),
+            Key::Str("seven".to_string()),
+        ];
+        let mut items = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            items.insert(key.clone(), i);
+        }
+        assert_eq!(items.len(), keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(items.get(key), Some(&i));
+        }
+        // Equal keys hash equally, so inserting one replaces the other
+        items.insert(Key::Int(-7), 99);
+        assert_eq!(items.len(), keys.len());
+        assert_eq!(items[&Key::Int(-7)], 99);
+        assert_eq!(items.get(&Key::Bytes(vec![0, 255])), Some(&0));
+        assert_eq!(items.get(&Key::Bytes(vec![255, 0])), None);
+        assert_ne!(Key::Int(1), Key::Str("1".to_string()));
+    }
+
+    #[test]
+    fn t_value_type_name() {
+        let uxo = parse(
+            "uxf 1.0\n=Point x y\n[yes (:AB:) 2022-10-01 \
+            2022-10-01T09:30:15 1 [] {} 1.5 <a> (Point 1 2) ?]\n",
+        )
+        .unwrap();
+        let Value::List(lst) = uxo.value() else {
+            panic!("expected a list");
+        };
+        let names: Vec<&str> = (0..lst.len())
+            .map(|i| opt_type_name(lst.get(i).unwrap()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "bool", "bytes", "date", "datetime", "int", "list", "map",
+                "real", "str", "table", "null"
+            ]
+        );
+        assert_eq!(Value::Int(1).type_name(), "int");
+        // Type names are 'static (even a table's)
+        let name: &'static str =
+            lst.get(9).unwrap().as_ref().unwrap().type_name();
+        assert_eq!(name, "table");
+        let vtype_names: Vec<&str> =
+            lst.iter().flatten().map(|value| value.vtype_name()).collect();
+        assert_eq!(
+            vtype_names,
+            [
+                "bool", "bytes", "date", "datetime", "int", "list", "map",
+                "real", "str", "Point"
+            ]
+        );
+        assert_eq!(opt_type_name(&Some(Value::Bool(false))), "bool");
+        assert_eq!(opt_type_name(&None), "null");
+        #[cfg(feature = "bigint")]
+        assert_eq!(
+            Value::BigInt(num_bigint::BigInt::from(1) << 100).type_name(),
+            "int"
+        );
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        for (key, name) in [
+            (Key::Bytes(vec![1]), "bytes"),
+            (Key::Date(date), "date"),
+            (
+                Key::DateTime(date.and_hms_opt(9, 30, 0).unwrap()),
+                "datetime",
+            ),
+            (Key::Int(1), "int"),
+            (Key::Str("k".to_string()), "str"),
+        ] {
+            assert_eq!(key.type_name(), name);
+        }
+    }
+
+    // A Table's cached hash is interior mutability that clippy flags, but
+    // it doesn't affect the ordering
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn t_value_key() {
+        let mut counts: BTreeMap<ValueKey, usize> = BTreeMap::new();
+        for value in [
+            Value::Real(2.5),
+            Value::Real(f64::NAN),
+            Value::Real(-1.0),
+            Value::Real(0.0),
+            Value::Real(-0.0),
+            Value::Real(f64::INFINITY),
+            Value::Int(3),
+            Value::Str("b".to_string()),
+            Value::Real(2.5),
+            Value::Real(f64::NAN),
+            Value::Int(-7),
+            Value::Str("a".to_string()),
+            Value::Bool(true),
+        ] {
+            *counts.entry(ValueKey(value)).or_insert(0) += 1;
+        }
+        let order: Vec<(String, usize)> = counts
+            .iter()
+            .map(|(key, count)| (value_to_str(key.0.clone()), *count))
+            .collect();
+        let expected = [
+            ("yes", 1),
+            ("-7", 1),
+            ("3", 1),
+            ("-1.0", 1),
+            ("-0.0", 1),
+            ("0.0", 1),
+            ("2.5", 2),
+            ("inf", 1),
+            ("NaN", 2),
+            ("a", 1),
+            ("b", 1),
+        ];
+        assert_eq!(
+            order,
+            expected
+                .iter()
+                .map(|(text, count)| (text.to_string(), *count))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ValueKey(Value::Real(f64::NAN)),
+            Value::Real(f64::NAN).into()
+        );
+        assert!(ValueKey(Value::Real(-0.0)) < ValueKey(Value::Real(0.0)));
+        assert!(ValueKey(Value::Int(100)) < ValueKey(Value::Real(-100.0)));
+    }
 }